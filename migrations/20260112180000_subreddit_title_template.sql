@@ -0,0 +1 @@
+ALTER TABLE subreddits ADD COLUMN title_template TEXT;
@@ -0,0 +1,8 @@
+CREATE TABLE region_checks (
+    video_id TEXT NOT NULL,
+    region TEXT NOT NULL,
+    blocked INTEGER NOT NULL,
+    skipped INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL,
+    PRIMARY KEY (video_id, region)
+);
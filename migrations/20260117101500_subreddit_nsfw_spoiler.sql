@@ -0,0 +1,3 @@
+ALTER TABLE subreddits ADD COLUMN nsfw INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE subreddits ADD COLUMN spoiler INTEGER NOT NULL DEFAULT 0;
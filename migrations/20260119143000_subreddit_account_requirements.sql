@@ -0,0 +1,11 @@
+ALTER TABLE subreddits ADD COLUMN min_account_age_days INTEGER;
+
+ALTER TABLE subreddits ADD COLUMN min_karma INTEGER;
+
+CREATE TABLE reddit_account_stats (
+    reddit_account_id TEXT PRIMARY KEY NOT NULL,
+    created_utc INTEGER NOT NULL,
+    total_karma INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    FOREIGN KEY (reddit_account_id) REFERENCES reddit_accounts(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+ALTER TABLE subreddits ADD COLUMN comment_template TEXT;
+
+ALTER TABLE subreddits ADD COLUMN sticky_comment INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN last_seen_update INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN min_duration_seconds INTEGER;
@@ -0,0 +1,10 @@
+CREATE TABLE feed_events (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    subscription_id TEXT NOT NULL,
+    received_at INTEGER NOT NULL,
+    signature TEXT,
+    body TEXT NOT NULL,
+    FOREIGN KEY (subscription_id) REFERENCES subscriptions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX feed_events_subscription_index ON feed_events (subscription_id);
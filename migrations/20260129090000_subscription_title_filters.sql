@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN title_filters TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE forms ADD COLUMN idempotency_key TEXT;
+
+CREATE UNIQUE INDEX forms_idempotency_key_index ON forms (idempotency_key);
@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+
+UPDATE subscriptions SET status = 'expired' WHERE expires IS NOT NULL AND expires < CAST(strftime('%s', 'now') AS INTEGER);
@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions ADD COLUMN last_posted_at INTEGER;
+
+ALTER TABLE subscriptions ADD COLUMN min_post_interval_seconds INTEGER;
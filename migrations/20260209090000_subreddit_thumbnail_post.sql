@@ -0,0 +1 @@
+ALTER TABLE subreddits ADD COLUMN thumbnail_post INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE forms ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+UPDATE forms SET created_at = CAST(strftime('%s', 'now') AS INTEGER);
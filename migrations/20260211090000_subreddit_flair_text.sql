@@ -0,0 +1 @@
+ALTER TABLE subreddits ADD COLUMN flair_text TEXT;
@@ -0,0 +1,5 @@
+CREATE TABLE video_durations (
+    video_id TEXT NOT NULL PRIMARY KEY,
+    duration_seconds INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE submission_claims (
+    video_id TEXT NOT NULL,
+    subreddit_id INTEGER NOT NULL,
+    claimed_at INTEGER NOT NULL,
+    PRIMARY KEY (video_id, subreddit_id),
+    FOREIGN KEY (subreddit_id) REFERENCES subreddits(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+CREATE INDEX submissions_subscription_index ON submissions (subscription_id, created_at);
@@ -0,0 +1,19 @@
+CREATE TABLE failed_submissions (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    subscription_id TEXT NOT NULL,
+    reddit_account_id TEXT NOT NULL,
+    subreddit_id INTEGER NOT NULL,
+    video_id TEXT NOT NULL,
+    entry TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    next_attempt_at INTEGER,
+    created_at INTEGER NOT NULL,
+    UNIQUE (video_id, subreddit_id),
+    FOREIGN KEY (subscription_id) REFERENCES subscriptions(id) ON DELETE CASCADE,
+    FOREIGN KEY (reddit_account_id) REFERENCES reddit_accounts(id) ON DELETE CASCADE,
+    FOREIGN KEY (subreddit_id) REFERENCES subreddits(id) ON DELETE CASCADE
+);
+
+CREATE INDEX failed_submissions_subscription_index ON failed_submissions (subscription_id, created_at);
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN description_comment TEXT;
@@ -0,0 +1 @@
+ALTER TABLE subscription_links ADD COLUMN moderate_submissions INTEGER;
@@ -0,0 +1,3 @@
+ALTER TABLE subreddits ADD COLUMN posting_disabled_reason TEXT;
+
+ALTER TABLE failed_submissions ADD COLUMN error_code TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE token_refreshes (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    reddit_account_id TEXT NOT NULL,
+    refreshed_at INTEGER NOT NULL,
+    success INTEGER NOT NULL,
+    error TEXT,
+    FOREIGN KEY (reddit_account_id) REFERENCES reddit_accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX token_refreshes_reddit_account_index ON token_refreshes (reddit_account_id, refreshed_at);
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN pending_hmac_secret TEXT;
@@ -1,9 +1,12 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
 use thiserror::Error;
 
 use crate::{
     infrastructure::{Settings, SettingsError},
-    server::{ApiError, serve},
+    server::{ApiError, backfill, bulk_subscribe, doctor, openapi_spec, replay, serve},
 };
 
 #[derive(Debug, Parser)]
@@ -20,6 +23,28 @@ pub enum Commands {
         #[arg(long, default_value = "3000")]
         port: u16,
     },
+    /// Write the OpenAPI spec as JSON to a file, or stdout if no file is given.
+    OpenApi {
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Run the submission logic again for a stored feed event, e.g. after a failed submission.
+    Replay { event_id: i64 },
+    /// Submit the channel's videos published within the date range, e.g. --since 2026-01-01T00:00:00Z.
+    /// Without YOUTUBE_API_KEY only the latest 15 videos of the channel's feed can be backfilled.
+    Backfill {
+        channel_id: String,
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Check the database, the Reddit client credentials and the PubSubHubbub hub before going live.
+    Doctor,
+    /// Subscribe to every channel in a .csv or .json file, one channel per line with the columns:
+    /// channel (id or topic URL), post_shorts, reddit_account_ids, subreddits, flair_id, title_prefix and title_suffix.
+    /// The server has to be running with the same database for the hub to verify the subscriptions.
+    BulkSubscribe { file: PathBuf },
 }
 
 impl Cli {
@@ -27,14 +52,46 @@ impl Cli {
         Cli::parse()
     }
 
-    pub async fn handle(self, app_settings: Settings) -> Result<(), CommandError> {
+    pub async fn handle(self) -> Result<(), CommandError> {
         match self.command {
             Commands::Start { port } => {
                 if !(1024..=65535).contains(&port) {
                     return Err(CommandError::InvalidPort(port));
                 }
+                let app_settings = self.load_settings()?;
                 serve(port, app_settings).await?;
             }
+            Commands::OpenApi { ref out } => {
+                let spec = openapi_spec().to_pretty_json()?;
+
+                match out {
+                    Some(path) => {
+                        std::fs::write(path, spec)?;
+                        println!("OpenAPI spec written to: {}", path.display());
+                    }
+                    None => println!("{}", spec),
+                }
+            }
+            Commands::Replay { event_id } => {
+                let app_settings = self.load_settings()?;
+                replay(event_id, app_settings).await?;
+            }
+            Commands::Backfill {
+                ref channel_id,
+                since,
+                until,
+            } => {
+                let app_settings = self.load_settings()?;
+                backfill(channel_id.clone(), since, until, app_settings).await?;
+            }
+            Commands::Doctor => {
+                let app_settings = self.load_settings()?;
+                doctor(app_settings).await?;
+            }
+            Commands::BulkSubscribe { ref file } => {
+                let app_settings = self.load_settings()?;
+                bulk_subscribe(file, app_settings).await?;
+            }
         }
         Ok(())
     }
@@ -52,4 +109,8 @@ pub enum CommandError {
     ApiError(#[from] ApiError),
     #[error("Settings error: {0}")]
     SettingsError(#[from] SettingsError),
+    #[error("OpenAPI spec serialization error: {0}")]
+    OpenApiError(#[from] serde_json::Error),
+    #[error("File write error: {0}")]
+    FileError(#[from] std::io::Error),
 }
@@ -1,12 +1,21 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use handlebars::Handlebars;
+use axum::http::HeaderMap;
+use handlebars::{Handlebars, RenderError};
+use metrics_exporter_prometheus::PrometheusHandle;
 use sqlx::SqlitePool;
 use tokio::sync::mpsc;
+use url::Url;
 
 use crate::{
-    infrastructure::{connect::get_pool, settings::Settings},
-    server::{RedditCredentials, SubCommand},
+    infrastructure::{
+        ScheduledResubscriptions,
+        connect::get_pool,
+        settings::{
+            AlertWebhook, DuplicateSubscriptionAction, RegionBlockAction, Settings, TitleOverflow,
+        },
+    },
+    server::{ChannelCache, RedditCredentials, SubCommand},
 };
 
 #[derive(Clone)]
@@ -14,25 +23,40 @@ pub struct AppState {
     pub db_pool: SqlitePool,
     pub hb: Handlebars<'static>,
     pub scheduler_sender: mpsc::Sender<SubCommand>,
+    pub scheduled_resubscriptions: Arc<ScheduledResubscriptions>,
+    pub channels: Arc<ChannelCache>,
     pub reddit_credentials: RedditCredentials,
     pub base_url: String,
+    pub trust_proxy: bool,
+    pub youtube_api_key: Option<String>,
+    pub target_region: Option<String>,
+    pub region_block_action: RegionBlockAction,
+    pub title_overflow: TitleOverflow,
+    pub catch_up_max_age_hours: i64,
+    pub feed_replay_window_secs: i64,
+    pub token_refresh_window_secs: i64,
+    pub verification_timeout_secs: i64,
+    pub verification_retries: u32,
+    pub min_post_interval_secs: i64,
+    pub form_data_ttl_secs: i64,
+    pub duplicate_subscription_action: DuplicateSubscriptionAction,
+    pub check_callback_reachability: bool,
+    pub alert_webhook: Option<AlertWebhook>,
+    /// Whether the routes are behind the admin's HTTP Basic Auth.
+    pub admin_auth_enabled: bool,
+    pub metrics_handle: Option<PrometheusHandle>,
 }
 
 impl AppState {
-    pub async fn new(settings: Settings) -> (Arc<Self>, mpsc::Receiver<SubCommand>) {
+    pub async fn new(
+        settings: Settings,
+        metrics_handle: Option<PrometheusHandle>,
+    ) -> (Arc<Self>, mpsc::Receiver<SubCommand>) {
         let db_pool = get_pool(&settings)
             .await
             .expect("Error connecting to local SQLite DB.");
 
-        let mut hb = Handlebars::new();
-        hb.register_template_file("whole_document", "frontend/base_layout.html")
-            .expect("Error parsing base_layout template");
-        hb.register_template_file("detail_card", "frontend/partials/detail_card.html")
-            .expect("Error parsing detail_card template");
-        hb.register_template_file("form_actions", "frontend/partials/form_actions.html")
-            .expect("Error parsing form_actions template");
-        hb.register_template_file("delete_modal", "frontend/partials/delete_modal.html")
-            .expect("Error parsing delete_modal template");
+        let hb = register_templates(settings.templates_dir.as_deref());
 
         let (scheduler_sender, scheduler_receiver) = mpsc::channel(100);
 
@@ -44,10 +68,194 @@ impl AppState {
                 db_pool,
                 hb,
                 scheduler_sender,
+                scheduled_resubscriptions: Arc::default(),
+                channels: Arc::default(),
                 reddit_credentials,
                 base_url,
+                trust_proxy: settings.trust_proxy,
+                youtube_api_key: settings.youtube_api_key,
+                target_region: settings.target_region,
+                region_block_action: settings.region_block_action,
+                title_overflow: settings.title_overflow,
+                catch_up_max_age_hours: settings.catch_up_max_age_hours,
+                feed_replay_window_secs: settings.feed_replay_window_secs,
+                token_refresh_window_secs: settings.token_refresh_window_secs,
+                verification_timeout_secs: settings.verification_timeout_secs,
+                verification_retries: settings.verification_retries,
+                min_post_interval_secs: settings.min_post_interval_secs,
+                form_data_ttl_secs: settings.form_data_ttl_secs,
+                duplicate_subscription_action: settings.duplicate_subscription_action,
+                check_callback_reachability: settings.check_callback_reachability,
+                alert_webhook: settings.alert_webhook,
+                admin_auth_enabled: settings.admin_credentials.is_some(),
+                metrics_handle,
             }),
             scheduler_receiver,
         )
     }
+
+    /// A state on a migrated in-memory database, the scheduler's receiver is returned to keep the channel open.
+    #[cfg(test)]
+    pub async fn for_tests(settings: Settings) -> (Arc<Self>, mpsc::Receiver<SubCommand>) {
+        let (state, receiver) = Self::new(settings, None).await;
+
+        sqlx::migrate!()
+            .run(&state.db_pool)
+            .await
+            .expect("Error migrating the test database.");
+
+        (state, receiver)
+    }
+
+    /// The base URL the request was made to. Behind a trusted reverse proxy it's taken from the
+    /// `X-Forwarded-Proto` and `X-Forwarded-Host` headers, keeping the configured base URL's path.
+    /// Without a trusted proxy, or without valid headers, it's the configured base URL.
+    pub fn external_base_url(&self, headers: &HeaderMap) -> String {
+        if !self.trust_proxy {
+            return self.base_url.clone();
+        }
+
+        // Proxies append their value to an existing header, the first one is from the outermost proxy.
+        let forwarded = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .map(|value| value.trim().to_lowercase())
+                .filter(|value| !value.is_empty())
+        };
+
+        let (Some(proto), Some(host)) = (
+            forwarded("x-forwarded-proto"),
+            forwarded("x-forwarded-host"),
+        ) else {
+            return self.base_url.clone();
+        };
+
+        let Ok(configured) = Url::parse(&self.base_url) else {
+            return self.base_url.clone();
+        };
+
+        // Only a bare host (and port) is accepted, anything else could smuggle in another URL.
+        let derived = format!(
+            "{}://{}{}",
+            proto,
+            host,
+            configured.path().trim_end_matches('/')
+        );
+
+        match Url::parse(&derived) {
+            Ok(url)
+                if matches!(proto.as_str(), "http" | "https")
+                    && url.username().is_empty()
+                    && url.password().is_none()
+                    && url.host_str().is_some()
+                    && !host.contains(['/', '?', '#', '@']) =>
+            {
+                derived
+            }
+            _ => {
+                eprintln!(
+                    "Ignoring the invalid X-Forwarded-Proto '{}' and X-Forwarded-Host '{}', using the BASE_URL instead.",
+                    proto, host
+                );
+                self.base_url.clone()
+            }
+        }
+    }
+
+    /// Renders the page template inside the base layout.
+    pub fn render_page(&self, page: &str, data: &serde_json::Value) -> Result<String, RenderError> {
+        let body_content = self.hb.render(page, data)?;
+
+        self.hb.render(
+            "whole_document",
+            &serde_json::json!({ "body_content": body_content }),
+        )
+    }
+}
+
+/// The template names with their file in the frontend directory and their compiled-in source.
+const TEMPLATES: [(&str, &str, &str); 9] = [
+    (
+        "whole_document",
+        "base_layout.html",
+        include_str!("../../frontend/base_layout.html"),
+    ),
+    (
+        "detail_card",
+        "partials/detail_card.html",
+        include_str!("../../frontend/partials/detail_card.html"),
+    ),
+    (
+        "form_actions",
+        "partials/form_actions.html",
+        include_str!("../../frontend/partials/form_actions.html"),
+    ),
+    (
+        "delete_modal",
+        "partials/delete_modal.html",
+        include_str!("../../frontend/partials/delete_modal.html"),
+    ),
+    (
+        "landing_page",
+        "landing_page.html",
+        include_str!("../../frontend/landing_page.html"),
+    ),
+    (
+        "reddit_account",
+        "reddit_account.html",
+        include_str!("../../frontend/reddit_account.html"),
+    ),
+    (
+        "subscription",
+        "subscription.html",
+        include_str!("../../frontend/subscription.html"),
+    ),
+    (
+        "subreddit",
+        "subreddit.html",
+        include_str!("../../frontend/subreddit.html"),
+    ),
+    (
+        "callback_error",
+        "callback_error.html",
+        include_str!("../../frontend/callback_error.html"),
+    ),
+];
+
+/// The templates are compiled into the binary, so it runs from any working directory. With a
+/// templates directory they're read from its files instead and reloaded on every render, for editing them live.
+fn register_templates(templates_dir: Option<&Path>) -> Handlebars<'static> {
+    let mut hb = Handlebars::new();
+
+    let Some(templates_dir) = templates_dir else {
+        for (name, _, source) in TEMPLATES {
+            hb.register_template_string(name, source)
+                .unwrap_or_else(|e| panic!("Error parsing the {} template: {}", name, e));
+        }
+
+        return hb;
+    };
+
+    hb.set_dev_mode(true);
+
+    for (name, file, _) in TEMPLATES {
+        let template_path = templates_dir.join(file);
+        hb.register_template_file(name, &template_path)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Error parsing the {} template: {}",
+                    template_path.display(),
+                    e
+                )
+            });
+    }
+
+    println!(
+        "Loading the templates from {}, they're reloaded on every render.",
+        templates_dir.display()
+    );
+
+    hb
 }
@@ -1,14 +1,46 @@
-use std::str::FromStr;
+use std::{fs, io, path::PathBuf, str::FromStr, time::Duration};
 
-use sqlx::{Error, SqlitePool, sqlite::SqliteConnectOptions};
+use sqlx::{
+    Error, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+};
 use thiserror::Error;
 
-use crate::infrastructure::Settings;
+use crate::infrastructure::{DatabaseBackend, Settings};
 
 pub async fn get_pool(settings: &Settings) -> Result<SqlitePool, DbError> {
-    let options = SqliteConnectOptions::from_str(&settings.database_url)?.create_if_missing(true);
+    if settings.database_backend != DatabaseBackend::Sqlite {
+        return Err(DbError::UnsupportedBackend(settings.database_backend));
+    }
 
-    let pool = SqlitePool::connect_with(options).await?;
+    // WAL lets readers run alongside a writer, and the busy timeout makes concurrent writers
+    // (e.g. a burst of hub verification requests) wait for the lock instead of failing.
+    let journal_mode = if settings.database_wal_mode {
+        SqliteJournalMode::Wal
+    } else {
+        SqliteJournalMode::Delete
+    };
+
+    let options = SqliteConnectOptions::from_str(&settings.database_url)?
+        .create_if_missing(true)
+        .journal_mode(journal_mode)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(Duration::from_secs(settings.database_busy_timeout_secs));
+
+    // SQLite only creates the file, e.g. a `sqlite://data/bot.db` on a fresh volume needs the `data` directory first.
+    if let Some(directory) = options.get_filename().parent()
+        && !directory.as_os_str().is_empty()
+        && !directory.exists()
+    {
+        fs::create_dir_all(directory)
+            .map_err(|e| DbError::DirectoryError(directory.to_path_buf(), e))?;
+        println!("Created the database directory: {}", directory.display());
+    }
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(settings.database_max_connections)
+        .connect_with(options)
+        .await?;
 
     Ok(pool)
 }
@@ -17,4 +49,48 @@ pub async fn get_pool(settings: &Settings) -> Result<SqlitePool, DbError> {
 pub enum DbError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] Error),
+    #[error("Could not create the database directory {0}: {1}")]
+    DirectoryError(PathBuf, io::Error),
+    #[error("The {} database backend isn't supported, only SQLite is", .0.scheme())]
+    UnsupportedBackend(DatabaseBackend),
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn missing_database_directories_are_created() {
+        let root = std::env::temp_dir().join(Uuid::now_v7().to_string());
+        let database_path = root.join("data").join("nested").join("bot.db");
+        let mut settings = Settings::for_tests();
+        settings.database_url = format!("sqlite://{}", database_path.display());
+
+        let pool = get_pool(&settings).await.unwrap();
+        sqlx::query("SELECT 1").execute(&pool).await.unwrap();
+        pool.close().await;
+
+        assert!(database_path.exists());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn uncreatable_database_directory_is_a_directory_error() {
+        let root = std::env::temp_dir().join(Uuid::now_v7().to_string());
+        fs::create_dir_all(&root).unwrap();
+        // A file where the directory should be, creating it fails even when running as root.
+        fs::write(root.join("data"), "").unwrap();
+        let mut settings = Settings::for_tests();
+        settings.database_url = format!(
+            "sqlite://{}",
+            root.join("data").join("nested").join("bot.db").display()
+        );
+
+        let result = get_pool(&settings).await;
+
+        assert!(matches!(result, Err(DbError::DirectoryError(..))));
+        let _ = fs::remove_dir_all(root);
+    }
 }
@@ -2,7 +2,13 @@ mod app_state;
 mod connect;
 mod scheduler;
 mod settings;
+pub mod telemetry;
 
 pub use app_state::AppState;
-pub use scheduler::handle_scheduler;
-pub use settings::{Settings, SettingsError};
+pub use scheduler::{ScheduledResubscriptions, handle_scheduler};
+#[cfg(test)]
+pub use settings::AlertWebhook;
+pub use settings::{
+    AlertWebhookFormat, DatabaseBackend, DuplicateSubscriptionAction, RegionBlockAction, Settings,
+    SettingsError, TitleOverflow,
+};
@@ -1,63 +1,205 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use sqlx::{query, query_scalar};
+use chrono::Utc;
+use metrics::counter;
+use sqlx::query;
 use tokio::sync::mpsc::Receiver;
 use tokio_stream::StreamExt;
 use tokio_util::time::DelayQueue;
 
 use crate::{
-    infrastructure::AppState,
-    server::{ApiError, SubCommand, subscribe_to_channel},
+    infrastructure::{AppState, telemetry},
+    server::{
+        ApiError, PendingSubmission, PendingVerification, RESUBSCRIBE_EARLY_SECS, SimpleEntry,
+        SubCommand, SubscriptionStatus, delete_expired_form_data, expire_subscription,
+        mark_subscription_pending, prewarm_reddit_oauth_token, retry_failed_submission,
+        retry_submission, schedule_verification_check, send_failure_alert, submit_deferred_entry,
+        subscribe_to_channel,
+    },
 };
 
 pub async fn handle_scheduler(
     state: &Arc<AppState>,
     receiver: Receiver<SubCommand>,
 ) -> Result<(), ApiError> {
-    tokio::spawn(run_subscription_worker(state.clone(), receiver));
+    // Nothing restarts the worker, but the scheduled tasks run outside of it, so only a bug in the loop itself ends up here.
+    spawn_task(
+        "subscription worker".to_string(),
+        run_subscription_worker(state.clone(), receiver),
+    );
+
+    let now = Utc::now().timestamp();
 
-    let subscriptions_exist = query_scalar!(
+    // The queue only lives in memory, so it's rebuilt from the database. Anything that
+    // came due while the bot was down is run right away instead of being skipped.
+    let subscriptions = query!(
         r#"
-        SELECT EXISTS (
-            SELECT
-                s.id
-            FROM
-                subscriptions s
-            LIMIT 1
-        ) AS "result: bool";        
+        SELECT
+            s.id,
+            s.expires
+        FROM
+            subscriptions s;
         "#,
     )
-    .fetch_one(&state.db_pool)
+    .fetch_all(&state.db_pool)
     .await?;
 
-    if !subscriptions_exist {
-        return Ok(());
+    for subscription in subscriptions {
+        let wait_secs = match subscription.expires {
+            Some(expires) => (expires - RESUBSCRIBE_EARLY_SECS - now).max(0),
+            None => 0,
+        };
+
+        let _ = state
+            .scheduler_sender
+            .send(SubCommand::Schedule {
+                subscription_id: subscription.id,
+                wait_secs,
+            })
+            .await;
     }
 
-    let subscriptions = query!(
+    // Accounts without a refresh token are dropped by the refresh task itself.
+    let reddit_accounts = query!(
         r#"
         SELECT
-            s.id
+            ra.id,
+            ra.expires_at
         FROM
-            subscriptions s;
+            reddit_accounts ra;
         "#,
     )
     .fetch_all(&state.db_pool)
     .await?;
 
-    for subscription in subscriptions {
+    for reddit_account in reddit_accounts {
         let _ = state
             .scheduler_sender
-            .send(SubCommand::Schedule {
-                subscription_id: subscription.id,
-                wait_secs: 5,
+            .send(SubCommand::RefreshToken {
+                reddit_account_id: reddit_account.id,
+                wait_secs: (reddit_account.expires_at - state.token_refresh_window_secs - now)
+                    .max(0),
             })
             .await;
     }
 
+    let failed_submissions = query!(
+        r#"
+        SELECT
+            fs.id,
+            fs.next_attempt_at
+        FROM
+            failed_submissions fs
+        WHERE
+            fs.status = 'pending';
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    for failed_submission in failed_submissions {
+        let _ = state
+            .scheduler_sender
+            .send(SubCommand::RetryFailedSubmission {
+                failed_submission_id: failed_submission.id,
+                wait_secs: failed_submission
+                    .next_attempt_at
+                    .map_or(0, |next_attempt_at| (next_attempt_at - now).max(0)),
+            })
+            .await;
+    }
+
+    // The first cleanup removes whatever expired while the bot was down.
+    let _ = state
+        .scheduler_sender
+        .send(SubCommand::CleanupFormData { wait_secs: 0 })
+        .await;
+
     Ok(())
 }
 
+/// When each subscription's resubscribe is due, as queued in the worker, the queue itself can't be inspected.
+#[derive(Default)]
+pub struct ScheduledResubscriptions(Mutex<HashMap<String, i64>>);
+
+impl ScheduledResubscriptions {
+    fn insert(&self, subscription_id: &str, due_at: i64) {
+        if let Ok(mut resubscriptions) = self.0.lock() {
+            resubscriptions.insert(subscription_id.to_string(), due_at);
+        }
+    }
+
+    /// A subscription can be scheduled again before an older resubscribe fires, that one mustn't remove the newer time.
+    fn remove_due(&self, subscription_id: &str, now: i64) {
+        if let Ok(mut resubscriptions) = self.0.lock()
+            && resubscriptions
+                .get(subscription_id)
+                .is_some_and(|due_at| *due_at <= now)
+        {
+            resubscriptions.remove(subscription_id);
+        }
+    }
+
+    /// The subscription ids with their due time, soonest first.
+    pub fn snapshot(&self) -> Vec<(String, i64)> {
+        let mut resubscriptions: Vec<(String, i64)> = match self.0.lock() {
+            Ok(resubscriptions) => resubscriptions
+                .iter()
+                .map(|(subscription_id, due_at)| (subscription_id.clone(), *due_at))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        resubscriptions.sort_by_key(|(_, due_at)| *due_at);
+        resubscriptions
+    }
+}
+
+enum ScheduledTask {
+    Resubscribe(String),
+    CheckExpiry(String),
+    RetrySubmission(Box<PendingSubmission>),
+    RefreshToken(String),
+    CheckVerification(Box<PendingVerification>),
+    DeferredEntry(String, Box<SimpleEntry>),
+    CleanupFormData,
+    RetryFailedSubmission(i64),
+}
+
+/// Runs a scheduled task outside of the worker loop, a panic in the task is logged with its description instead of
+/// stopping the scheduling of everything else.
+fn spawn_task<F>(description: String, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::spawn(task);
+    tokio::spawn(async move {
+        if let Err(e) = handle.await
+            && e.is_panic()
+        {
+            eprintln!(
+                "The {} panicked: {}",
+                description,
+                panic_message(e.into_panic())
+            );
+        }
+    });
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
 pub async fn run_subscription_worker(state: Arc<AppState>, mut receiver: Receiver<SubCommand>) {
     let mut queue = DelayQueue::new();
     println!("Subscription worker started.");
@@ -69,17 +211,139 @@ pub async fn run_subscription_worker(state: Arc<AppState>, mut receiver: Receive
                 match cmd {
                     SubCommand::Schedule { subscription_id, wait_secs } => {
                         println!("Now scheduling for subscription: {}", subscription_id);
-                        queue.insert(subscription_id, Duration::from_secs(wait_secs as u64));
+                        state.scheduled_resubscriptions.insert(&subscription_id, Utc::now().timestamp() + wait_secs);
+                        queue.insert(ScheduledTask::Resubscribe(subscription_id.clone()), Duration::from_secs(wait_secs as u64));
+                        // The resubscribe is sent before the lease ends, this checks whether the renewal was verified in time.
+                        queue.insert(ScheduledTask::CheckExpiry(subscription_id), Duration::from_secs((wait_secs + RESUBSCRIBE_EARLY_SECS) as u64));
+                    }
+                    SubCommand::RetrySubmission { submission, wait_secs } => {
+                        println!("Now scheduling a submission retry for the video: {}", submission.entry.yt_video_id);
+                        queue.insert(ScheduledTask::RetrySubmission(submission), Duration::from_secs(wait_secs.max(0) as u64));
+                    }
+                    SubCommand::RefreshToken { reddit_account_id, wait_secs } => {
+                        println!("Now scheduling a token refresh for the Reddit account: {}", reddit_account_id);
+                        queue.insert(ScheduledTask::RefreshToken(reddit_account_id), Duration::from_secs(wait_secs.max(0) as u64));
+                    }
+                    SubCommand::CheckVerification { verification, wait_secs } => {
+                        println!("Now scheduling a verification check for subscription: {}", verification.subscription_id);
+                        queue.insert(ScheduledTask::CheckVerification(verification), Duration::from_secs(wait_secs.max(0) as u64));
+                    }
+                    SubCommand::DeferEntry { subscription_id, entry, wait_secs } => {
+                        println!("Now scheduling the deferred video {} for subscription: {}", entry.yt_video_id, subscription_id);
+                        queue.insert(ScheduledTask::DeferredEntry(subscription_id, entry), Duration::from_secs(wait_secs.max(0) as u64));
+                    }
+                    SubCommand::CleanupFormData { wait_secs } => {
+                        queue.insert(ScheduledTask::CleanupFormData, Duration::from_secs(wait_secs.max(0) as u64));
+                    }
+                    SubCommand::RetryFailedSubmission { failed_submission_id, wait_secs } => {
+                        println!("Now scheduling the retry of the failed submission: {}", failed_submission_id);
+                        queue.insert(ScheduledTask::RetryFailedSubmission(failed_submission_id), Duration::from_secs(wait_secs.max(0) as u64));
                     }
                 }
             }
-            // Handles subscription expirations
+            // Handles subscription expirations, submission retries, token refreshes, verification checks, deferred videos, form data cleanups and failed submissions
             Some(expired) = queue.next() => {
-                let subscription_id = expired.into_inner();
-                println!("Executing resubscribe for: {}", subscription_id);
+                match expired.into_inner() {
+                    ScheduledTask::Resubscribe(subscription_id) => {
+                        println!("Executing resubscribe for: {}", subscription_id);
+                        state.scheduled_resubscriptions.remove_due(&subscription_id, Utc::now().timestamp());
+                        counter!(telemetry::RESUBSCRIPTIONS).increment(1);
+
+                        // The resubscribe queues its verification check, so it can't wait on this loop.
+                        let resubscribe_state = state.clone();
+                        spawn_task(format!("resubscribe of the subscription {}", subscription_id), async move {
+                            if let Err(e) = subscribe_to_channel_via_subscription_id(&resubscribe_state, &subscription_id).await {
+                                eprintln!("Resubscribe error for {}: {:?}", subscription_id, e);
+                                send_failure_alert(&resubscribe_state, &format!("Resubscribing the subscription {} failed: {}", subscription_id, e)).await;
+                            }
+                        });
+                    }
+                    ScheduledTask::CheckExpiry(subscription_id) => {
+                        let expiry_state = state.clone();
+                        spawn_task(format!("expiry check of the subscription {}", subscription_id), async move {
+                            match expire_subscription(&expiry_state.db_pool, &subscription_id, &Utc::now().timestamp()).await {
+                                Ok(true) => {
+                                    eprintln!("The subscription {} expired without being renewed.", subscription_id);
+                                    send_failure_alert(&expiry_state, &format!("The subscription {} expired without being renewed", subscription_id)).await;
+                                }
+                                Ok(false) => {}
+                                Err(e) => eprintln!("Expiry check error for {}: {:?}", subscription_id, e),
+                            }
+                        });
+                    }
+                    ScheduledTask::RetrySubmission(submission) => {
+                        println!("Executing submission retry for the video: {}", submission.entry.yt_video_id);
 
-                if let Err(e) = subscribe_to_channel_via_subscription_id(&state, &subscription_id).await {
-                    eprintln!("Resubscribe error for {}: {:?}", subscription_id, e);
+                        // Submitting can take a while, so it shouldn't hold up the other scheduled tasks.
+                        let retry_state = state.clone();
+                        spawn_task(format!("submission retry of the video {}", submission.entry.yt_video_id), async move {
+                            let video_id = submission.entry.yt_video_id.clone();
+                            if let Err(e) = retry_submission(&retry_state, submission).await {
+                                eprintln!("Submission retry error for the video {}: {:?}", video_id, e);
+                            }
+                        });
+                    }
+                    ScheduledTask::RefreshToken(reddit_account_id) => {
+                        println!("Executing token refresh for the Reddit account: {}", reddit_account_id);
+
+                        // The next refresh is queued from the task, a failed refresh falls back to refreshing on submission.
+                        let refresh_state = state.clone();
+                        spawn_task(format!("token refresh of the Reddit account {}", reddit_account_id), async move {
+                            match prewarm_reddit_oauth_token(&refresh_state, &reddit_account_id).await {
+                                Ok(Some(wait_secs)) => {
+                                    let _ = refresh_state
+                                        .scheduler_sender
+                                        .send(SubCommand::RefreshToken { reddit_account_id, wait_secs })
+                                        .await;
+                                }
+                                Ok(None) => {}
+                                Err(e) => eprintln!("Token refresh error for the Reddit account {}: {:?}", reddit_account_id, e),
+                            }
+                        });
+                    }
+                    ScheduledTask::CheckVerification(verification) => {
+                        // A retried subscribe queues the next check from the task.
+                        let check_state = state.clone();
+                        spawn_task(format!("verification check of the subscription {}", verification.subscription_id), async move {
+                            let subscription_id = verification.subscription_id.clone();
+                            if let Err(e) = check_subscription_verification(&check_state, verification).await {
+                                eprintln!("Verification check error for {}: {:?}", subscription_id, e);
+                            }
+                        });
+                    }
+                    ScheduledTask::DeferredEntry(subscription_id, entry) => {
+                        println!("Executing the deferred submission of the video: {}", entry.yt_video_id);
+
+                        // The entry can be deferred again, which is queued from the task.
+                        let deferred_state = state.clone();
+                        spawn_task(format!("deferred submission of the video {} for the subscription {}", entry.yt_video_id, subscription_id), async move {
+                            if let Err(e) = submit_deferred_entry(&deferred_state, &subscription_id, &entry).await {
+                                eprintln!("Deferred submission error for the video {}: {:?}", entry.yt_video_id, e);
+                            }
+                        });
+                    }
+                    ScheduledTask::CleanupFormData => {
+                        // The next cleanup is queued whatever the outcome of this one.
+                        let cleanup_state = state.clone();
+                        spawn_task("form data cleanup".to_string(), async move {
+                            let older_than = Utc::now().timestamp() - form_data_retention_secs(&cleanup_state);
+                            match delete_expired_form_data(&cleanup_state.db_pool, &older_than).await {
+                                Ok(0) => {}
+                                Ok(removed) => println!("Removed {} expired form data rows.", removed),
+                                Err(e) => eprintln!("Form data cleanup error: {:?}", e),
+                            }
+                        });
+                        queue.insert(ScheduledTask::CleanupFormData, Duration::from_secs(state.form_data_ttl_secs.max(60) as u64));
+                    }
+                    ScheduledTask::RetryFailedSubmission(failed_submission_id) => {
+                        // A failed retry queues the next one from the task.
+                        let failed_state = state.clone();
+                        spawn_task(format!("retry of the failed submission {}", failed_submission_id), async move {
+                            if let Err(e) = retry_failed_submission(&failed_state, failed_submission_id).await {
+                                eprintln!("Failed submission retry error for {}: {:?}", failed_submission_id, e);
+                            }
+                        });
+                    }
                 }
             }
         }
@@ -124,5 +388,208 @@ async fn subscribe_to_channel_via_subscription_id(
     )
     .await?;
 
+    mark_subscription_pending(&state.db_pool, subscription_id).await?;
+    schedule_verification_check(
+        state,
+        subscription_id,
+        &subscription.channel_id,
+        &subscription.hmac_secret,
+        0,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// New subscriptions are only stored once verified, renewed ones stay pending until verified,
+/// so either one means the hub hasn't called back.
+async fn check_subscription_verification(
+    state: &Arc<AppState>,
+    verification: Box<PendingVerification>,
+) -> Result<(), ApiError> {
+    let status = query!(
+        r#"
+        SELECT
+            s.status as "status: SubscriptionStatus"
+        FROM
+            subscriptions s
+        WHERE
+            s.id = ?;
+        "#,
+        verification.subscription_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await?
+    .map(|subscription| subscription.status);
+
+    if !matches!(status, None | Some(SubscriptionStatus::Pending)) {
+        return Ok(());
+    }
+
+    let callback_url = format!(
+        "{}/google/subscription/{}",
+        &state.base_url, verification.subscription_id
+    );
+
+    eprintln!(
+        "The subscription {} for the YouTube channel {} wasn't verified within {} seconds, check that {} is reachable.",
+        verification.subscription_id,
+        verification.channel_id,
+        state.verification_timeout_secs,
+        callback_url
+    );
+
+    if verification.attempt >= state.verification_retries {
+        send_failure_alert(
+            state,
+            &format!(
+                "The subscription {} for the YouTube channel {} was never verified by the hub, check that {} is reachable",
+                verification.subscription_id, verification.channel_id, callback_url
+            ),
+        )
+        .await;
+        return Ok(());
+    }
+
+    println!(
+        "Sending the subscribe request for {} again (retry {} of {}).",
+        verification.subscription_id,
+        verification.attempt + 1,
+        state.verification_retries
+    );
+
+    subscribe_to_channel(
+        &callback_url,
+        &verification.channel_id,
+        &verification.hmac_secret,
+    )
+    .await?;
+
+    schedule_verification_check(
+        state,
+        &verification.subscription_id,
+        &verification.channel_id,
+        &verification.hmac_secret,
+        verification.attempt + 1,
+    )
+    .await;
+
     Ok(())
 }
+
+/// The form data is kept at least until the last verification retry, which the hub verifies with the stored form.
+fn form_data_retention_secs(state: &AppState) -> i64 {
+    let verification_window_secs =
+        state.verification_timeout_secs * (i64::from(state.verification_retries) + 1);
+
+    state.form_data_ttl_secs.max(verification_window_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, method},
+    };
+
+    use super::*;
+    use crate::{
+        infrastructure::Settings,
+        server::{
+            MOCK_HUB_URL,
+            test_support::{CHANNEL_ID, OLD_CHANNEL_ID, topic_url, verified_subscription},
+        },
+    };
+
+    /// The topics of the requests the hub received, waits for the worker's spawned tasks to send them.
+    async fn hub_topics(hub: &MockServer, count: usize) -> Vec<String> {
+        for _ in 0..500 {
+            let requests = hub.received_requests().await.unwrap_or_default();
+            if requests.len() >= count {
+                return requests
+                    .iter()
+                    .filter_map(|request| {
+                        url::form_urlencoded::parse(&request.body)
+                            .find(|(key, _)| key == "hub.topic")
+                            .map(|(_, topic)| topic.into_owned())
+                    })
+                    .collect();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        panic!("the hub didn't receive {} requests", count);
+    }
+
+    #[tokio::test]
+    async fn past_due_subscription_is_resubscribed_at_startup() {
+        let hub = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(202))
+            .mount(&hub)
+            .await;
+        MOCK_HUB_URL.set(Some(hub.uri()));
+
+        let (state, receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+        // The lease ran out while the bot was down.
+        sqlx::query("UPDATE subscriptions SET expires = ? WHERE id = ?")
+            .bind(Utc::now().timestamp() - 3600)
+            .bind(&subscription_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        handle_scheduler(&state, receiver).await.unwrap();
+
+        assert_eq!(hub_topics(&hub, 1).await, vec![topic_url(CHANNEL_ID)]);
+    }
+
+    #[tokio::test]
+    async fn failing_resubscribe_does_not_stop_the_later_ones() {
+        let hub = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(OLD_CHANNEL_ID))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&hub)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(CHANNEL_ID))
+            .respond_with(ResponseTemplate::new(202))
+            .mount(&hub)
+            .await;
+        MOCK_HUB_URL.set(Some(hub.uri()));
+
+        let (state, receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let failing_subscription_id = verified_subscription(&state, OLD_CHANNEL_ID).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+        tokio::spawn(run_subscription_worker(state.clone(), receiver));
+
+        for (subscription_id, wait_secs) in [(failing_subscription_id, 0), (subscription_id, 1)] {
+            state
+                .scheduler_sender
+                .send(SubCommand::Schedule {
+                    subscription_id,
+                    wait_secs,
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            hub_topics(&hub, 2).await,
+            vec![topic_url(OLD_CHANNEL_ID), topic_url(CHANNEL_ID)]
+        );
+    }
+
+    #[tokio::test]
+    async fn form_data_outlives_the_verification_retries() {
+        let mut settings = Settings::for_tests();
+        settings.form_data_ttl_secs = 60;
+        settings.verification_timeout_secs = 600;
+        settings.verification_retries = 2;
+        let (state, _receiver) = AppState::for_tests(settings).await;
+
+        assert_eq!(form_data_retention_secs(&state), 1800);
+    }
+}
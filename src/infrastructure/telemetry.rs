@@ -0,0 +1,61 @@
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_exporter_statsd::StatsdBuilder;
+
+use crate::server::ApiError;
+
+// Metric names, shared by all exporters.
+pub const FEEDS_RECEIVED: &str = "feeds_received_total";
+pub const SIGNATURE_FAILURES: &str = "signature_failures_total";
+pub const SUBMISSIONS_POSTED: &str = "submissions_posted_total";
+pub const SUBMISSION_FAILURES: &str = "submission_failures_total";
+pub const TOKEN_REFRESHES: &str = "token_refreshes_total";
+pub const RESUBSCRIPTIONS: &str = "resubscriptions_total";
+pub const REDDIT_SUBMIT_DURATION: &str = "reddit_submit_duration_seconds";
+
+pub const METRICS_PREFIX: &str = "reddit_youtube_bot";
+
+/// The StatsD exporter's builder for `addr` (host:port).
+pub fn statsd_builder(addr: &str) -> Result<StatsdBuilder, ApiError> {
+    let (host, port) = addr
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+        .ok_or(ApiError::InternalError(format!(
+            "The StatsD address has to be in the form host:port, the input was: {}",
+            addr
+        )))?;
+
+    Ok(StatsdBuilder::from(host, port))
+}
+
+/// Installs the StatsD exporter as the global metrics recorder, metrics are sent over UDP to `addr` (host:port).
+pub fn install_statsd_exporter(addr: &str) -> Result<(), ApiError> {
+    let recorder = statsd_builder(addr)?
+        .build(Some(METRICS_PREFIX))
+        .map_err(|e| ApiError::InternalError(format!("StatsD exporter error: {}", e)))?;
+
+    metrics::set_global_recorder(recorder).map_err(|e| {
+        ApiError::InternalError(format!("Could not install the StatsD exporter: {}", e))
+    })?;
+
+    println!("Sending metrics to the StatsD endpoint: {}", addr);
+
+    Ok(())
+}
+
+/// Installs the Prometheus exporter as the global metrics recorder, the handle renders the `/metrics` response.
+pub fn install_prometheus_exporter() -> Result<PrometheusHandle, ApiError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(REDDIT_SUBMIT_DURATION.to_string()),
+            &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0],
+        )
+        .map_err(|e| ApiError::InternalError(format!("Prometheus exporter error: {}", e)))?
+        .install_recorder()
+        .map_err(|e| {
+            ApiError::InternalError(format!("Could not install the Prometheus exporter: {}", e))
+        })?;
+
+    println!("Serving Prometheus metrics on /metrics");
+
+    Ok(handle)
+}
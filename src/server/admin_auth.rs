@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::server::{ErrorResponse, shared::AdminCredentials};
+
+// Google's hub and Reddit call these, so they can't send the admin credentials.
+const PUBLIC_PATH_PREFIXES: [&str; 2] = ["/google/subscription/", "/reddit/callback"];
+
+/// Rejects requests without the admin's HTTP Basic Auth credentials, except for the external callbacks.
+pub async fn require_admin_auth(
+    State(credentials): State<Arc<AdminCredentials>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+
+    if PUBLIC_PATH_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        || is_authorized(&request, &credentials)
+    {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            format!("Basic realm=\"{}\"", env!("CARGO_PKG_NAME")),
+        )],
+        Json(ErrorResponse {
+            error: "unauthorized",
+            message: "Admin credentials are required".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+fn is_authorized(request: &Request, credentials: &AdminCredentials) -> bool {
+    let Some(encoded) = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+        .map(|(_, encoded)| encoded.trim())
+    else {
+        return false;
+    };
+
+    let Ok(decoded) = STANDARD.decode(encoded) else {
+        return false;
+    };
+
+    let expected = format!("{}:{}", credentials.username, credentials.password);
+
+    constant_time_eq(&decoded, expected.as_bytes())
+}
+
+/// Compares every byte, so the response time doesn't tell how much of the password matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
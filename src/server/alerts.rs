@@ -0,0 +1,147 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    infrastructure::{AlertWebhookFormat, AppState},
+    server::shared::HTTP_CLIENT,
+};
+
+/// Added to the response of an `ApiError::DatabaseStorage`, e.g. a full disk.
+#[derive(Clone, Debug)]
+pub struct DatabaseStorageFailure(pub String);
+
+/// A full disk fails every request, so the storage failures are only alerted once in a while.
+const STORAGE_ALERT_INTERVAL_SECS: i64 = 15 * 60;
+static LAST_STORAGE_ALERT_AT: AtomicI64 = AtomicI64::new(0);
+
+/// Sends an alert when a request failed on the database storage, which won't fix itself.
+pub async fn alert_on_storage_failure(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+
+    if let Some(DatabaseStorageFailure(message)) = response.extensions().get() {
+        let now = Utc::now().timestamp();
+        let last_alert_at = LAST_STORAGE_ALERT_AT.load(Ordering::Relaxed);
+
+        if now - last_alert_at >= STORAGE_ALERT_INTERVAL_SECS
+            && LAST_STORAGE_ALERT_AT
+                .compare_exchange(last_alert_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            send_failure_alert(
+                &state,
+                &format!(
+                    "The database storage failed, e.g. the disk is full: {}",
+                    message
+                ),
+            )
+            .await;
+        }
+    }
+
+    response
+}
+
+// Discord rejects messages over 2000 characters.
+const MAX_ALERT_LENGTH: usize = 1900;
+
+/// Posts the failure to the configured alert webhook. This is best-effort,
+/// a failed notification is only logged so it never hides the original error.
+pub async fn send_failure_alert(state: &AppState, message: &str) {
+    let Some(webhook) = &state.alert_webhook else {
+        return;
+    };
+
+    let message: String = if message.chars().count() > MAX_ALERT_LENGTH {
+        format!(
+            "{}...",
+            message.chars().take(MAX_ALERT_LENGTH).collect::<String>()
+        )
+    } else {
+        message.to_string()
+    };
+
+    let payload = match webhook.format {
+        AlertWebhookFormat::Discord => json!({ "content": message }),
+        AlertWebhookFormat::Slack => json!({ "text": message }),
+    };
+
+    let alert_result = HTTP_CLIENT
+        .post(&webhook.url)
+        .json(&payload)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    if let Err(e) = alert_result {
+        eprintln!("Could not send the failure alert to the webhook: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+    use super::*;
+    use crate::{
+        infrastructure::{AlertWebhook, Settings},
+        server::ApiError,
+    };
+
+    #[tokio::test]
+    async fn storage_failure_sends_an_alert() {
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&webhook)
+            .await;
+
+        let mut settings = Settings::for_tests();
+        settings.alert_webhook = Some(AlertWebhook {
+            url: webhook.uri(),
+            format: AlertWebhookFormat::Discord,
+        });
+        let (state, _receiver) = AppState::for_tests(settings).await;
+
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    Err::<(), _>(ApiError::DatabaseStorage(
+                        "database or disk is full".to_string(),
+                    ))
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                state,
+                alert_on_storage_failure,
+            ));
+
+        let response = router
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::INSUFFICIENT_STORAGE
+        );
+        webhook.verify().await;
+    }
+}
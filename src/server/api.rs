@@ -0,0 +1,1401 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    infrastructure::AppState,
+    server::{
+        ApiError, ErrorResponse,
+        google::{fetch_channel_name, schedule_verification_check},
+        reddit::{force_refresh_reddit_oauth_token, import_reddit_account},
+        repository::{
+            FailedSubmissionRecord, SubmissionRecord, Subscription,
+            count_failed_submissions_for_subscription, count_submissions_for_subscription,
+            count_subscriptions, fetch_failed_submissions_for_subscription,
+            fetch_submissions_for_subscription, fetch_subscriptions_page, get_subreddit_by_id,
+            get_subscription_details, mark_subscription_pending, reddit_account_exists,
+            replace_subscription_links, update_subreddit, update_subscription_channel,
+            update_subscription_crossposting, update_subscription_posting_cutoff,
+            update_subscription_settings,
+        },
+        shared::{
+            CrosspostSettings, DescriptionComment, FailedSubmissionStatus, RedditOAuthToken,
+            SimpleEntry, Subreddit, SubscriptionStatus, TitleFilters, reddit_submission_permalink,
+            subscribe_to_channel, unsubscribe_from_channel, validate_channel_id,
+            validate_title_template,
+        },
+    },
+};
+
+pub fn router() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::new()
+        .routes(routes!(get_subreddit, patch_subreddit))
+        .routes(routes!(list_subscriptions))
+        .routes(routes!(put_subscription))
+        .routes(routes!(remap_subscription))
+        .routes(routes!(put_posting_cutoff))
+        .routes(routes!(put_crossposting))
+        .routes(routes!(list_submissions))
+        .routes(routes!(list_failed_submissions))
+        .routes(routes!(get_scheduler))
+        .routes(routes!(refresh_account_token))
+        .routes(routes!(import_account))
+}
+
+/// Partial subreddit settings, only the provided fields are changed.
+/// A field set to `null` or an empty string clears the stored value.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct SubredditPatch {
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub title_prefix: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub title_suffix: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub title_template: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub flair_id: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub flair_text: Option<Option<String>>,
+    pub nsfw: Option<bool>,
+    pub spoiler: Option<bool>,
+    #[serde(default, deserialize_with = "present_number")]
+    #[schema(value_type = Option<i64>)]
+    pub min_account_age_days: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "present_number")]
+    #[schema(value_type = Option<i64>)]
+    pub min_karma: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub comment_template: Option<Option<String>>,
+    pub sticky_comment: Option<bool>,
+    pub thumbnail_post: Option<bool>,
+    /// Clearing it submits videos to the subreddit again.
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>)]
+    pub posting_disabled_reason: Option<Option<String>>,
+}
+
+/// Wraps a present field in `Some` so it can be told apart from a missing one,
+/// empty strings are treated the same as `null`.
+fn present_field<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(Some(value.filter(|s| !s.trim().is_empty())))
+}
+
+/// Same as `present_field` for numeric fields.
+fn present_number<'de, D>(deserializer: D) -> Result<Option<Option<i64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(Option::<i64>::deserialize(deserializer)?))
+}
+
+const MAX_TITLE_AFFIX_LENGTH: usize = 100;
+/// Reddit's limit for a flair's text.
+const MAX_FLAIR_TEXT_LENGTH: usize = 64;
+
+impl SubredditPatch {
+    fn validate_affix(name: &str, value: &Option<String>) -> Result<(), ApiError> {
+        if let Some(value) = value {
+            if value.contains(['\n', '\r']) {
+                return Err(ApiError::BadRequest(format!(
+                    "The {} can't contain line breaks",
+                    name
+                )));
+            }
+
+            if value.chars().count() > MAX_TITLE_AFFIX_LENGTH {
+                return Err(ApiError::BadRequest(format!(
+                    "The {} can be at most {} characters long",
+                    name, MAX_TITLE_AFFIX_LENGTH
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_minimum(name: &str, value: &Option<i64>) -> Result<(), ApiError> {
+        if let Some(value) = value
+            && *value < 0
+        {
+            return Err(ApiError::BadRequest(format!(
+                "The {} can't be negative, the input was: {}",
+                name, value
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn apply(self, subreddit: &mut Subreddit) -> Result<(), ApiError> {
+        if let Some(title_prefix) = self.title_prefix {
+            Self::validate_affix("title prefix", &title_prefix)?;
+            subreddit.title_prefix = title_prefix;
+        }
+
+        if let Some(title_suffix) = self.title_suffix {
+            Self::validate_affix("title suffix", &title_suffix)?;
+            subreddit.title_suffix = title_suffix;
+        }
+
+        if let Some(title_template) = self.title_template {
+            if let Some(template) = &title_template {
+                validate_title_template(template)?;
+            }
+            subreddit.title_template = title_template;
+        }
+
+        if let Some(flair_id) = self.flair_id {
+            if let Some(id) = &flair_id {
+                Uuid::try_parse(id.trim()).map_err(|_| {
+                    ApiError::BadRequest(format!(
+                        "The flair id has to be a Reddit flair template id (UUID), the input was: {}",
+                        id
+                    ))
+                })?;
+            }
+            subreddit.flair_id = flair_id.map(|id| id.trim().to_string());
+        }
+
+        if let Some(flair_text) = self.flair_text {
+            let flair_text = flair_text.map(|text| text.trim().to_string());
+            if let Some(text) = &flair_text
+                && text.chars().count() > MAX_FLAIR_TEXT_LENGTH
+            {
+                return Err(ApiError::BadRequest(format!(
+                    "The flair text can be at most {} characters long",
+                    MAX_FLAIR_TEXT_LENGTH
+                )));
+            }
+            subreddit.flair_text = flair_text;
+        }
+
+        if let Some(nsfw) = self.nsfw {
+            subreddit.nsfw = nsfw;
+        }
+
+        if let Some(spoiler) = self.spoiler {
+            subreddit.spoiler = spoiler;
+        }
+
+        if let Some(min_account_age_days) = self.min_account_age_days {
+            Self::validate_minimum("minimum account age", &min_account_age_days)?;
+            subreddit.min_account_age_days = min_account_age_days;
+        }
+
+        if let Some(min_karma) = self.min_karma {
+            Self::validate_minimum("minimum karma", &min_karma)?;
+            subreddit.min_karma = min_karma;
+        }
+
+        if let Some(comment_template) = self.comment_template {
+            if let Some(template) = &comment_template {
+                validate_title_template(template)?;
+            }
+            subreddit.comment_template = comment_template;
+        }
+
+        if let Some(sticky_comment) = self.sticky_comment {
+            subreddit.sticky_comment = sticky_comment;
+        }
+
+        if let Some(thumbnail_post) = self.thumbnail_post {
+            subreddit.thumbnail_post = thumbnail_post;
+        }
+
+        if let Some(posting_disabled_reason) = self.posting_disabled_reason {
+            subreddit.posting_disabled_reason =
+                posting_disabled_reason.map(|reason| reason.trim().to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Get subreddit settings
+#[utoipa::path(
+        get,
+        path = "/subreddits/{id}",
+        params(
+            ("id" = i64, Path, description = "Subreddit id", example = "1"),
+        ),
+        description = "Get the submission settings of a registered subreddit",
+        responses(
+            (status = 200, description = "The subreddit settings.", body = Subreddit),
+            (status = 404, description = "Subreddit doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn get_subreddit(
+    State(state): State<Arc<AppState>>,
+    Path(subreddit_id): Path<i64>,
+) -> Result<Json<Subreddit>, ApiError> {
+    let subreddit = get_subreddit_by_id(&state.db_pool, &subreddit_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subreddit doesn't exist".into()))?;
+
+    Ok(Json(subreddit))
+}
+
+/// Update subreddit settings
+#[utoipa::path(
+        patch,
+        path = "/subreddits/{id}",
+        params(
+            ("id" = i64, Path, description = "Subreddit id", example = "1"),
+        ),
+        request_body(content = SubredditPatch, description = "The subreddit settings to change, omitted fields are left as is", content_type = "application/json"),
+        description = "Change a subset of the submission settings of a registered subreddit",
+        responses(
+            (status = 200, description = "The updated subreddit settings.", body = Subreddit),
+            (status = 400, description = "Invalid settings.", body = ErrorResponse),
+            (status = 404, description = "Subreddit doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn patch_subreddit(
+    State(state): State<Arc<AppState>>,
+    Path(subreddit_id): Path<i64>,
+    Json(patch): Json<SubredditPatch>,
+) -> Result<Json<Subreddit>, ApiError> {
+    let mut subreddit = get_subreddit_by_id(&state.db_pool, &subreddit_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subreddit doesn't exist".into()))?;
+
+    patch.apply(&mut subreddit)?;
+
+    update_subreddit(&state.db_pool, &subreddit).await?;
+
+    println!("Updated the settings for the {} subreddit.", subreddit.name);
+
+    Ok(Json(subreddit))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriptionListQuery {
+    pub channel_id: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// A subscription without its HMAC secret.
+#[derive(Serialize, ToSchema)]
+pub struct SubscriptionSummary {
+    pub id: String,
+    pub channel_id: String,
+    pub channel_name: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub post_shorts: bool,
+    pub min_duration_seconds: Option<i64>,
+    pub title_filters: Option<TitleFilters>,
+    pub posting_cutoff: Option<DateTime<Utc>>,
+    pub status: SubscriptionStatus,
+    pub crossposting: Option<CrosspostSettings>,
+    pub min_post_interval_seconds: Option<i64>,
+    pub description_comment: Option<DescriptionComment>,
+}
+
+impl SubscriptionSummary {
+    fn convert(subscription: Subscription) -> Result<Self, ApiError> {
+        Ok(SubscriptionSummary {
+            title_filters: subscription
+                .title_filters
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            crossposting: subscription
+                .crossposting
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            description_comment: subscription
+                .description_comment
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            id: subscription.id,
+            channel_id: subscription.channel_id,
+            channel_name: subscription.channel_name,
+            expires_at: subscription.expires.and_then(DateTime::from_timestamp_secs),
+            post_shorts: subscription.post_shorts,
+            min_duration_seconds: subscription.min_duration_seconds,
+            posting_cutoff: subscription
+                .posting_cutoff
+                .and_then(DateTime::from_timestamp_secs),
+            status: subscription.status,
+            min_post_interval_seconds: subscription.min_post_interval_seconds,
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriptionList {
+    /// The number of subscriptions matching the filter, before the limit and offset.
+    pub total: usize,
+    pub subscriptions: Vec<SubscriptionSummary>,
+}
+
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 500;
+
+/// List subscriptions
+#[utoipa::path(
+        get,
+        path = "/subscriptions",
+        params(
+            ("channel_id" = Option<String>, Query, description = "Only list the subscription of this YouTube channel", example = "UCBR8-60-B28hp2BmDPdntcQ"),
+            ("limit" = Option<usize>, Query, description = "The maximum number of subscriptions to return, 100 by default and at most 500", example = 100),
+            ("offset" = Option<usize>, Query, description = "The number of subscriptions to skip", example = 0),
+        ),
+        description = "List the subscriptions, the HMAC secrets are left out",
+        responses(
+            (status = 200, description = "The subscriptions.", body = SubscriptionList),
+            (status = 400, description = "Invalid limit.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn list_subscriptions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubscriptionListQuery>,
+) -> Result<Json<SubscriptionList>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit > MAX_LIST_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "The limit can be at most {}, the input was: {}",
+            MAX_LIST_LIMIT, limit
+        )));
+    }
+
+    let channel_id = query.channel_id.as_deref().map(str::trim);
+
+    let total = count_subscriptions(&state.db_pool, channel_id).await? as usize;
+
+    let subscriptions = fetch_subscriptions_page(
+        &state.db_pool,
+        channel_id,
+        limit as i64,
+        query.offset.unwrap_or(0) as i64,
+    )
+    .await?
+    .into_iter()
+    .map(SubscriptionSummary::convert)
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(SubscriptionList {
+        total,
+        subscriptions,
+    }))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubmissionListQuery {
+    pub subscription_id: String,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Checks the subscription exists and returns the limit.
+async fn validate_submission_list_query(
+    state: &AppState,
+    query: &SubmissionListQuery,
+) -> Result<usize, ApiError> {
+    Uuid::try_parse(&query.subscription_id).map_err(|_| {
+        ApiError::BadRequest(format!(
+            "Invalid subscription id, the input was: {}",
+            query.subscription_id
+        ))
+    })?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit > MAX_LIST_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "The limit can be at most {}, the input was: {}",
+            MAX_LIST_LIMIT, limit
+        )));
+    }
+
+    get_subscription_details(&state.db_pool, &query.subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    Ok(limit)
+}
+
+/// A video the bot submitted to a subreddit.
+#[derive(Serialize, ToSchema)]
+pub struct SubmissionSummary {
+    /// The fullname of the Reddit submission.
+    #[schema(example = "t3_1abcde")]
+    pub id: String,
+    pub video_id: String,
+    pub subreddit_id: i64,
+    pub subreddit_name: String,
+    /// The Reddit account the video was submitted with.
+    pub reddit_username: String,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub stickied: bool,
+    #[schema(example = "https://redd.it/1abcde")]
+    pub permalink: String,
+}
+
+impl SubmissionSummary {
+    fn convert(submission: SubmissionRecord) -> Self {
+        SubmissionSummary {
+            permalink: reddit_submission_permalink(&submission.id),
+            id: submission.id,
+            video_id: submission.video_id,
+            subreddit_id: submission.subreddit_id,
+            subreddit_name: submission.subreddit_name,
+            reddit_username: submission.reddit_username,
+            submitted_at: DateTime::from_timestamp_secs(submission.created_at),
+            stickied: submission.stickied,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubmissionList {
+    /// The number of submissions of the subscription, before the limit and offset.
+    pub total: usize,
+    pub submissions: Vec<SubmissionSummary>,
+}
+
+/// List submissions
+#[utoipa::path(
+        get,
+        path = "/submissions",
+        params(
+            ("subscription_id" = String, Query, description = "The subscription the videos were submitted for", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+            ("limit" = Option<usize>, Query, description = "The maximum number of submissions to return, 100 by default and at most 500", example = 100),
+            ("offset" = Option<usize>, Query, description = "The number of submissions to skip", example = 0),
+        ),
+        description = "List the submissions of a subscription, newest first",
+        responses(
+            (status = 200, description = "The submissions.", body = SubmissionList),
+            (status = 400, description = "Invalid subscription id or limit.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn list_submissions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubmissionListQuery>,
+) -> Result<Json<SubmissionList>, ApiError> {
+    let limit = validate_submission_list_query(&state, &query).await?;
+
+    let total = count_submissions_for_subscription(&state.db_pool, &query.subscription_id).await?;
+
+    let submissions = fetch_submissions_for_subscription(
+        &state.db_pool,
+        &query.subscription_id,
+        limit as i64,
+        query.offset.unwrap_or(0) as i64,
+    )
+    .await?
+    .into_iter()
+    .map(SubmissionSummary::convert)
+    .collect();
+
+    Ok(Json(SubmissionList {
+        total: total as usize,
+        submissions,
+    }))
+}
+
+/// A submission that failed for a reason that can pass, retried by the scheduler.
+#[derive(Serialize, ToSchema)]
+pub struct FailedSubmissionSummary {
+    pub id: i64,
+    pub video_id: String,
+    pub title: Option<String>,
+    pub subreddit_id: i64,
+    pub subreddit_name: String,
+    pub reddit_username: String,
+    /// Including the first submission.
+    pub attempts: i64,
+    pub last_error: String,
+    /// Reddit's error code when Reddit rejected the submission, e.g. `SUBREDDIT_NOTALLOWED`.
+    pub error_code: Option<String>,
+    pub status: FailedSubmissionStatus,
+    /// Only set while the status is pending.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub first_failed_at: Option<DateTime<Utc>>,
+}
+
+impl FailedSubmissionSummary {
+    fn convert(failed_submission: FailedSubmissionRecord) -> Self {
+        FailedSubmissionSummary {
+            title: serde_json::from_str::<SimpleEntry>(&failed_submission.entry)
+                .ok()
+                .map(|entry| entry.title),
+            id: failed_submission.id,
+            video_id: failed_submission.video_id,
+            subreddit_id: failed_submission.subreddit_id,
+            subreddit_name: failed_submission.subreddit_name,
+            reddit_username: failed_submission.reddit_username,
+            attempts: failed_submission.attempts,
+            last_error: failed_submission.last_error,
+            error_code: failed_submission.error_code,
+            status: failed_submission.status,
+            next_attempt_at: failed_submission
+                .next_attempt_at
+                .and_then(DateTime::from_timestamp_secs),
+            first_failed_at: DateTime::from_timestamp_secs(failed_submission.created_at),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FailedSubmissionList {
+    /// The number of failed submissions of the subscription, before the limit and offset.
+    pub total: usize,
+    pub failed_submissions: Vec<FailedSubmissionSummary>,
+}
+
+/// List failed submissions
+#[utoipa::path(
+        get,
+        path = "/submissions/failed",
+        params(
+            ("subscription_id" = String, Query, description = "The subscription the videos were submitted for", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+            ("limit" = Option<usize>, Query, description = "The maximum number of failed submissions to return, 100 by default and at most 500", example = 100),
+            ("offset" = Option<usize>, Query, description = "The number of failed submissions to skip", example = 0),
+        ),
+        description = "List the submissions of a subscription that are waiting for a retry or ran out of retries, latest first",
+        responses(
+            (status = 200, description = "The failed submissions.", body = FailedSubmissionList),
+            (status = 400, description = "Invalid subscription id or limit.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn list_failed_submissions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubmissionListQuery>,
+) -> Result<Json<FailedSubmissionList>, ApiError> {
+    let limit = validate_submission_list_query(&state, &query).await?;
+
+    let total =
+        count_failed_submissions_for_subscription(&state.db_pool, &query.subscription_id).await?;
+
+    let failed_submissions = fetch_failed_submissions_for_subscription(
+        &state.db_pool,
+        &query.subscription_id,
+        limit as i64,
+        query.offset.unwrap_or(0) as i64,
+    )
+    .await?
+    .into_iter()
+    .map(FailedSubmissionSummary::convert)
+    .collect();
+
+    Ok(Json(FailedSubmissionList {
+        total: total as usize,
+        failed_submissions,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ScheduledResubscription {
+    pub subscription_id: String,
+    /// The resubscribe is sent an hour before the lease expires.
+    pub resubscribe_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SchedulerStatus {
+    /// Soonest first.
+    pub resubscriptions: Vec<ScheduledResubscription>,
+}
+
+/// Scheduled resubscriptions
+#[utoipa::path(
+        get,
+        path = "/scheduler",
+        description = "List the resubscribes queued in the scheduler, e.g. to confirm a new subscription was picked up",
+        responses(
+            (status = 200, description = "The queued resubscribes.", body = SchedulerStatus),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn get_scheduler(State(state): State<Arc<AppState>>) -> Json<SchedulerStatus> {
+    let resubscriptions = state
+        .scheduled_resubscriptions
+        .snapshot()
+        .into_iter()
+        .map(|(subscription_id, due_at)| ScheduledResubscription {
+            subscription_id,
+            resubscribe_at: DateTime::from_timestamp_secs(due_at),
+        })
+        .collect();
+
+    Json(SchedulerStatus { resubscriptions })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RefreshedToken {
+    pub reddit_account_id: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Refresh a Reddit account's OAuth token
+#[utoipa::path(
+        post,
+        path = "/accounts/{id}/refresh",
+        params(
+            ("id" = String, Path, description = "Reddit account id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+        ),
+        description = "Refresh the OAuth token of a Reddit account now instead of waiting for it to expire, e.g. when Reddit keeps rejecting it",
+        responses(
+            (status = 200, description = "The token was refreshed.", body = RefreshedToken),
+            (status = 400, description = "Invalid account id, or the account was authorized temporarily and has no refresh token.", body = ErrorResponse),
+            (status = 404, description = "Reddit account doesn't exist.", body = ErrorResponse),
+            (status = 500, description = "Reddit refused the refresh.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn refresh_account_token(
+    State(state): State<Arc<AppState>>,
+    Path(reddit_account_id): Path<String>,
+) -> Result<Json<RefreshedToken>, ApiError> {
+    Uuid::try_parse(&reddit_account_id).map_err(|_| {
+        ApiError::BadRequest(format!(
+            "Invalid Reddit account id, the input was: {}",
+            reddit_account_id
+        ))
+    })?;
+
+    if !reddit_account_exists(&state.db_pool, &reddit_account_id).await? {
+        return Err(ApiError::NotFound("Reddit account doesn't exist".into()));
+    }
+
+    let expires_at = force_refresh_reddit_oauth_token(&state, &reddit_account_id).await?;
+
+    Ok(Json(RefreshedToken {
+        reddit_account_id,
+        expires_at: DateTime::from_timestamp_secs(expires_at),
+    }))
+}
+
+/// Reddit access tokens are valid for an hour.
+const REDDIT_ACCESS_TOKEN_LIFETIME_SECS: i64 = 60 * 60;
+
+/// An OAuth token obtained outside the bot's authorization flow, e.g. from a database backup.
+#[derive(Deserialize, ToSchema)]
+pub struct RedditAccountImport {
+    pub access_token: String,
+    /// Without it the account stops working once the access token expires.
+    pub refresh_token: Option<String>,
+    /// The scopes the token was granted, space or comma separated.
+    #[schema(example = "identity submit history")]
+    pub scope: String,
+    /// Seconds until the access token expires, defaults to an hour.
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub moderate_submissions: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportedRedditAccount {
+    pub reddit_account_id: String,
+    pub username: String,
+    /// False when an account with the username already existed and got the new token.
+    pub created: bool,
+}
+
+/// Import a Reddit account from an OAuth token
+#[utoipa::path(
+        post,
+        path = "/accounts/import",
+        request_body(content = RedditAccountImport, description = "The account's OAuth token", content_type = "application/json"),
+        description = "Create a Reddit account, or update the token of the account with the same username, from a known OAuth token instead of authorizing it in the browser, e.g. to restore the accounts after losing the database. The token is checked with Reddit's /api/v1/me. Only available when the admin credentials are configured.",
+        responses(
+            (status = 200, description = "The account was created or updated.", body = ImportedRedditAccount),
+            (status = 400, description = "The admin credentials aren't configured, the token is missing scopes, or Reddit rejected it.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn import_account(
+    State(state): State<Arc<AppState>>,
+    Json(account_import): Json<RedditAccountImport>,
+) -> Result<Json<ImportedRedditAccount>, ApiError> {
+    // Whoever can call it gets an account to submit with, so it's never open to everyone.
+    if !state.admin_auth_enabled {
+        return Err(ApiError::BadRequest(
+            "Importing Reddit accounts needs the ADMIN_USERNAME and ADMIN_PASSWORD to be set"
+                .into(),
+        ));
+    }
+
+    if account_import.access_token.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "The access token can't be empty".into(),
+        ));
+    }
+
+    let oauth_token = RedditOAuthToken {
+        access_token: account_import.access_token.trim().to_string(),
+        token_type: "bearer".to_string(),
+        expires_in: account_import
+            .expires_in
+            .unwrap_or(REDDIT_ACCESS_TOKEN_LIFETIME_SECS)
+            .max(0),
+        scope: account_import.scope,
+        refresh_token: account_import
+            .refresh_token
+            .map(|refresh_token| refresh_token.trim().to_string())
+            .filter(|refresh_token| !refresh_token.is_empty()),
+    };
+
+    let (reddit_account_id, username, created) =
+        import_reddit_account(&state, oauth_token, account_import.moderate_submissions).await?;
+
+    Ok(Json(ImportedRedditAccount {
+        reddit_account_id,
+        username,
+        created,
+    }))
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct SubscriptionRemap {
+    /// The YouTube channel id the subscription should follow from now on.
+    pub channel_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RemappedSubscription {
+    pub id: String,
+    pub channel_id: String,
+    pub channel_name: String,
+}
+
+/// Remap subscription to a new channel
+#[utoipa::path(
+        post,
+        path = "/subscriptions/{id}/remap",
+        params(
+            ("id" = String, Path, description = "Subscription id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+        ),
+        request_body(content = SubscriptionRemap, description = "The new YouTube channel id", content_type = "application/json"),
+        description = "Move a subscription to a new YouTube channel, e.g. when a creator migrates channels. The new channel is subscribed and then the old one unsubscribed, the linked Reddit accounts and subreddits are kept. If the hub refuses the new channel the subscription keeps following the old one.",
+        responses(
+            (status = 200, description = "The remapped subscription.", body = RemappedSubscription),
+            (status = 400, description = "Invalid channel id.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn remap_subscription(
+    State(state): State<Arc<AppState>>,
+    Path(subscription_id): Path<String>,
+    Json(remap): Json<SubscriptionRemap>,
+) -> Result<Json<RemappedSubscription>, ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    let new_channel_id = remap.channel_id.trim().to_string();
+    validate_channel_id(&new_channel_id)?;
+
+    if new_channel_id == subscription.channel_id {
+        return Err(ApiError::BadRequest(format!(
+            "The subscription already follows the YouTube channel: {}",
+            new_channel_id
+        )));
+    }
+
+    let new_channel_name = fetch_channel_name(&state, &new_channel_id).await?;
+
+    println!(
+        "Remapping the subscription {} from https://www.youtube.com/channel/{} to https://www.youtube.com/channel/{}",
+        subscription.id, subscription.channel_id, new_channel_id
+    );
+
+    let callback_url = format!(
+        "{}/google/subscription/{}",
+        &state.base_url, subscription.id
+    );
+
+    // The row follows the new channel before the hub verifies its subscribe on the same callback,
+    // the old channel stays subscribed until the new one is, so a refused subscribe can be rolled back.
+    update_subscription_channel(
+        &state.db_pool,
+        &subscription.id,
+        &new_channel_id,
+        &new_channel_name,
+    )
+    .await?;
+
+    if let Err(e) =
+        subscribe_to_channel(&callback_url, &new_channel_id, &subscription.hmac_secret).await
+    {
+        update_subscription_channel(
+            &state.db_pool,
+            &subscription.id,
+            &subscription.channel_id,
+            &subscription.channel_name,
+        )
+        .await?;
+
+        return Err(e);
+    }
+
+    // The old channel's lease runs out on its own if the hub can't be reached now.
+    if let Err(e) = unsubscribe_from_channel(&callback_url, &subscription.channel_id).await {
+        eprintln!(
+            "Could not unsubscribe the remapped subscription {} from the old YouTube channel {}: {}",
+            subscription.id, subscription.channel_id, e
+        );
+    }
+
+    mark_subscription_pending(&state.db_pool, &subscription.id).await?;
+    schedule_verification_check(
+        &state,
+        &subscription.id,
+        &new_channel_id,
+        &subscription.hmac_secret,
+        0,
+    )
+    .await;
+
+    Ok(Json(RemappedSubscription {
+        id: subscription.id,
+        channel_id: new_channel_id,
+        channel_name: new_channel_name,
+    }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct PostingCutoff {
+    /// Videos published before this time aren't submitted, `null` submits every video.
+    pub posting_cutoff: Option<DateTime<Utc>>,
+}
+
+/// Set the posting cutoff of a subscription
+#[utoipa::path(
+        put,
+        path = "/subscriptions/{id}/posting_cutoff",
+        params(
+            ("id" = String, Path, description = "Subscription id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+        ),
+        request_body(content = PostingCutoff, description = "The new posting cutoff", content_type = "application/json"),
+        description = "Change from when the subscription's videos are submitted, e.g. move it back to backfill older videos on the next catch-up",
+        responses(
+            (status = 200, description = "The updated posting cutoff.", body = PostingCutoff),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn put_posting_cutoff(
+    State(state): State<Arc<AppState>>,
+    Path(subscription_id): Path<String>,
+    Json(cutoff): Json<PostingCutoff>,
+) -> Result<Json<PostingCutoff>, ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    update_subscription_posting_cutoff(
+        &state.db_pool,
+        &subscription.id,
+        &cutoff.posting_cutoff.map(|cutoff| cutoff.timestamp()),
+    )
+    .await?;
+
+    println!(
+        "Updated the posting cutoff of the subscription {} to {:?}.",
+        subscription.id, cutoff.posting_cutoff
+    );
+
+    Ok(Json(cutoff))
+}
+
+/// Set the crosspost settings of a subscription
+#[utoipa::path(
+        put,
+        path = "/subscriptions/{id}/crossposting",
+        params(
+            ("id" = String, Path, description = "Subscription id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+        ),
+        request_body(content = CrosspostSettings, description = "The primary subreddit and the subreddits crossposting its submission", content_type = "application/json"),
+        description = "Submit the videos once to the primary subreddit and crosspost them to the other subreddits, subreddits disallowing crossposts get a link post instead. An empty list submits links to every subreddit",
+        responses(
+            (status = 200, description = "The updated crosspost settings.", body = CrosspostSettings),
+            (status = 400, description = "Invalid crosspost settings.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn put_crossposting(
+    State(state): State<Arc<AppState>>,
+    Path(subscription_id): Path<String>,
+    Json(crossposting): Json<CrosspostSettings>,
+) -> Result<Json<CrosspostSettings>, ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    crossposting.validate()?;
+
+    for subreddit_id in crossposting
+        .primary_subreddit_id
+        .iter()
+        .chain(&crossposting.crosspost_subreddit_ids)
+    {
+        get_subreddit_by_id(&state.db_pool, subreddit_id)
+            .await?
+            .ok_or_else(|| {
+                ApiError::BadRequest(format!("Subreddit {} doesn't exist", subreddit_id))
+            })?;
+    }
+
+    update_subscription_crossposting(&state.db_pool, &subscription.id, &crossposting.to_column()?)
+        .await?;
+
+    println!(
+        "Updated the crosspost settings of the subscription {} to {:?}.",
+        subscription.id, crossposting
+    );
+
+    Ok(Json(crossposting))
+}
+
+/// A Reddit account and subreddit pair the subscription's videos are submitted with.
+/// Each subreddit gets a video once, so a subreddit can only have one account per subscription.
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SubscriptionTarget {
+    #[schema(example = "019ba504-70f5-7f35-9c2c-2f02b992af7e")]
+    pub reddit_account_id: String,
+    #[schema(example = 1)]
+    pub subreddit_id: i64,
+    /// Overrides the Reddit account's `moderate_submissions` on this subreddit, `null` uses the account's.
+    #[serde(default)]
+    pub moderate_submissions: Option<bool>,
+}
+
+/// The editable settings of a subscription. The HMAC secret and callback are bound to the hub
+/// subscription so they can't be changed here, the subreddits' title prefix/suffix and flair
+/// are changed with `PATCH /api/subreddits/{id}`.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct SubscriptionSettings {
+    pub post_shorts: bool,
+    pub min_duration_seconds: Option<i64>,
+    #[serde(default)]
+    pub title_filters: TitleFilters,
+    /// Minimum seconds between the subscription's posts, `null` uses the bot's default.
+    pub min_post_interval_seconds: Option<i64>,
+    /// Comments the video description under each submission, `null` doesn't. Needs a YouTube API key.
+    #[serde(default)]
+    pub description_comment: Option<DescriptionComment>,
+    /// Replaces the Reddit account and subreddit pairs when given, they're kept as is otherwise.
+    /// An account can post to several subreddits, but every subreddit is listed at most once.
+    pub targets: Option<Vec<SubscriptionTarget>>,
+}
+
+/// Edit a subscription
+#[utoipa::path(
+        put,
+        path = "/subscriptions/{id}",
+        params(
+            ("id" = String, Path, description = "Subscription id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e"),
+        ),
+        request_body(content = SubscriptionSettings, description = "The new subscription settings", content_type = "application/json"),
+        description = "Change the settings of a subscription in place, without subscribing to the channel again",
+        responses(
+            (status = 200, description = "The updated subscription.", body = SubscriptionSummary),
+            (status = 400, description = "Invalid subscription settings.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exist.", body = ErrorResponse),
+        ),
+        tag = "api"
+    )]
+#[axum::debug_handler]
+async fn put_subscription(
+    State(state): State<Arc<AppState>>,
+    Path(subscription_id): Path<String>,
+    Json(settings): Json<SubscriptionSettings>,
+) -> Result<Json<SubscriptionSummary>, ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    if let Some(min_duration_seconds) = settings.min_duration_seconds
+        && min_duration_seconds < 0
+    {
+        return Err(ApiError::BadRequest(format!(
+            "The minimum duration can't be negative, the input was: {}",
+            min_duration_seconds
+        )));
+    }
+
+    if let Some(min_post_interval_seconds) = settings.min_post_interval_seconds
+        && min_post_interval_seconds < 0
+    {
+        return Err(ApiError::BadRequest(format!(
+            "The minimum post interval can't be negative, the input was: {}",
+            min_post_interval_seconds
+        )));
+    }
+
+    settings.title_filters.validate()?;
+
+    if settings.description_comment.is_some() && state.youtube_api_key.is_none() {
+        return Err(ApiError::BadRequest(
+            "Commenting the video description needs a YOUTUBE_API_KEY to fetch the description"
+                .into(),
+        ));
+    }
+
+    if let Some(targets) = &settings.targets {
+        for (index, target) in targets.iter().enumerate() {
+            if targets[..index]
+                .iter()
+                .any(|other| other.subreddit_id == target.subreddit_id)
+            {
+                return Err(ApiError::BadRequest(format!(
+                    "The subreddit {} is listed more than once, a subreddit can only have one Reddit account per subscription",
+                    target.subreddit_id
+                )));
+            }
+
+            if !reddit_account_exists(&state.db_pool, &target.reddit_account_id).await? {
+                return Err(ApiError::BadRequest(format!(
+                    "No Reddit account found for the id: {}",
+                    target.reddit_account_id
+                )));
+            }
+
+            get_subreddit_by_id(&state.db_pool, &target.subreddit_id)
+                .await?
+                .ok_or_else(|| {
+                    ApiError::BadRequest(format!("Subreddit {} doesn't exist", target.subreddit_id))
+                })?;
+        }
+    }
+
+    update_subscription_settings(
+        &state.db_pool,
+        &subscription.id,
+        &settings.post_shorts,
+        &settings.min_duration_seconds,
+        &settings.title_filters.to_column()?,
+        &settings.min_post_interval_seconds,
+        &settings
+            .description_comment
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?,
+    )
+    .await?;
+
+    if let Some(targets) = &settings.targets {
+        let links: Vec<(String, i64, Option<bool>)> = targets
+            .iter()
+            .map(|target| {
+                (
+                    target.reddit_account_id.clone(),
+                    target.subreddit_id,
+                    target.moderate_submissions,
+                )
+            })
+            .collect();
+
+        replace_subscription_links(&state.db_pool, &subscription.id, &links).await?;
+    }
+
+    println!(
+        "Updated the settings of the subscription {}.",
+        subscription.id
+    );
+
+    let subscription = get_subscription_details(&state.db_pool, &subscription.id)
+        .await?
+        .ok_or(ApiError::NotFound("Subscription doesn't exist".into()))?;
+
+    Ok(Json(SubscriptionSummary::convert(subscription)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, method},
+    };
+
+    use super::*;
+    use crate::{
+        infrastructure::Settings,
+        server::{
+            repository::{
+                fetch_reddit_accounts_for_subscription, fetch_subreddits_for_reddit_account,
+            },
+            shared::{CachedChannel, MOCK_HUB_URL},
+            test_support::{
+                CHANNEL_ID, OLD_CHANNEL_ID, linked_subscription, reddit_account, subreddit,
+                topic_url, verified_subscription,
+            },
+        },
+    };
+
+    /// The hub's `(hub.mode, hub.topic)` of every request it received, in order.
+    async fn hub_requests(hub: &MockServer) -> Vec<(String, String)> {
+        hub.received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let form: Vec<(String, String)> = url::form_urlencoded::parse(&request.body)
+                    .into_owned()
+                    .collect();
+                let field = |name: &str| {
+                    form.iter()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or_default()
+                };
+
+                (field("hub.mode"), field("hub.topic"))
+            })
+            .collect()
+    }
+
+    async fn remap_to_new_channel(
+        state: &Arc<AppState>,
+        subscription_id: &str,
+    ) -> Result<Json<RemappedSubscription>, ApiError> {
+        state
+            .channels
+            .insert(CHANNEL_ID, CachedChannel::new(CHANNEL_ID, "New channel"))
+            .await;
+
+        remap_subscription(
+            State(state.clone()),
+            Path(subscription_id.to_string()),
+            Json(SubscriptionRemap {
+                channel_id: CHANNEL_ID.to_string(),
+            }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn remap_subscribes_the_new_topic_and_unsubscribes_the_old_one() {
+        let hub = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(202))
+            .mount(&hub)
+            .await;
+        MOCK_HUB_URL.set(Some(hub.uri()));
+
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, OLD_CHANNEL_ID, &reddit_account_id, &subreddit).await;
+
+        let Json(remapped) = remap_to_new_channel(&state, &subscription_id)
+            .await
+            .unwrap();
+        assert_eq!(remapped.channel_id, CHANNEL_ID);
+
+        assert_eq!(
+            hub_requests(&hub).await,
+            vec![
+                ("subscribe".to_string(), topic_url(CHANNEL_ID)),
+                ("unsubscribe".to_string(), topic_url(OLD_CHANNEL_ID)),
+            ]
+        );
+
+        let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(subscription.channel_id, CHANNEL_ID);
+        assert_eq!(subscription.channel_name, "New channel");
+
+        let reddit_accounts =
+            fetch_reddit_accounts_for_subscription(&state.db_pool, &subscription_id)
+                .await
+                .unwrap();
+        assert_eq!(reddit_accounts.len(), 1);
+        assert_eq!(reddit_accounts[0].id, reddit_account_id);
+
+        let subreddits = fetch_subreddits_for_reddit_account(
+            &state.db_pool,
+            &subscription_id,
+            &reddit_account_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!(subreddits.len(), 1);
+        assert_eq!(subreddits[0].id, subreddit.id);
+    }
+
+    #[tokio::test]
+    async fn refused_remap_keeps_the_old_channel_subscribed() {
+        let hub = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("hub.mode=subscribe"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("Invalid topic"))
+            .mount(&hub)
+            .await;
+        MOCK_HUB_URL.set(Some(hub.uri()));
+
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, OLD_CHANNEL_ID, &reddit_account_id, &subreddit).await;
+
+        assert!(
+            remap_to_new_channel(&state, &subscription_id)
+                .await
+                .is_err()
+        );
+
+        // Only the refused subscribe was sent, the old channel was never unsubscribed.
+        assert_eq!(
+            hub_requests(&hub).await,
+            vec![("subscribe".to_string(), topic_url(CHANNEL_ID))]
+        );
+
+        let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(subscription.channel_id, OLD_CHANNEL_ID);
+        assert_eq!(subscription.channel_name, "Channel");
+    }
+
+    #[tokio::test]
+    async fn patch_subreddit_only_changes_the_given_fields() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subreddit = subreddit(&state, "videos").await;
+        let mut expected = serde_json::to_value(&subreddit).unwrap();
+
+        let patch: SubredditPatch =
+            serde_json::from_str(r#"{"title_prefix": "[Video]", "nsfw": true, "min_karma": 100}"#)
+                .unwrap();
+        let Json(patched) = patch_subreddit(State(state.clone()), Path(subreddit.id), Json(patch))
+            .await
+            .unwrap();
+
+        expected["title_prefix"] = "[Video]".into();
+        expected["nsfw"] = true.into();
+        expected["min_karma"] = 100.into();
+        assert_eq!(serde_json::to_value(&patched).unwrap(), expected);
+
+        let Json(stored) = get_subreddit(State(state.clone()), Path(subreddit.id))
+            .await
+            .unwrap();
+        assert_eq!(serde_json::to_value(&stored).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn patch_accepts_the_date_template_variable() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subreddit = subreddit(&state, "videos").await;
+
+        let patch: SubredditPatch =
+            serde_json::from_str(r#"{"title_template": "[{channel_name}] {title} ({date})"}"#)
+                .unwrap();
+        let Json(patched) = patch_subreddit(State(state.clone()), Path(subreddit.id), Json(patch))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            patched.title_template.as_deref(),
+            Some("[{channel_name}] {title} ({date})")
+        );
+    }
+
+    #[tokio::test]
+    async fn patch_with_an_unknown_template_variable_is_refused() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subreddit = subreddit(&state, "videos").await;
+
+        let patch: SubredditPatch =
+            serde_json::from_str(r#"{"title_template": "{title} ({foo})"}"#).unwrap();
+        let result = patch_subreddit(State(state.clone()), Path(subreddit.id), Json(patch)).await;
+
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+        let Json(stored) = get_subreddit(State(state.clone()), Path(subreddit.id))
+            .await
+            .unwrap();
+        assert!(stored.title_template.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_missing_subreddit_is_not_found() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let result = get_subreddit(State(state.clone()), Path(404)).await;
+
+        assert!(matches!(result, Err(ApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn list_subscriptions_filters_and_pages_in_the_database() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        for channel_id in [CHANNEL_ID, CHANNEL_ID, OLD_CHANNEL_ID] {
+            verified_subscription(&state, channel_id).await;
+        }
+
+        let Json(list) = list_subscriptions(
+            State(state.clone()),
+            Query(SubscriptionListQuery {
+                channel_id: Some(format!(" {} ", CHANNEL_ID)),
+                limit: Some(1),
+                offset: Some(1),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(list.total, 2);
+        assert_eq!(list.subscriptions.len(), 1);
+        assert_eq!(list.subscriptions[0].channel_id, CHANNEL_ID);
+
+        let Json(list) = list_subscriptions(
+            State(state.clone()),
+            Query(SubscriptionListQuery {
+                channel_id: None,
+                limit: None,
+                offset: Some(3),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(list.total, 3);
+        assert!(list.subscriptions.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use std::{path::Path, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    infrastructure::{AppState, Settings},
+    server::{
+        ApiError,
+        forms::{YouTubeSubscribeForm, subscribe_from_form},
+        shared::{configure_http_client, configure_hub_test_mode, configure_reddit_urls},
+    },
+};
+
+/// One channel to subscribe to, a CSV row or an element of a JSON array.
+/// Lists are comma separated in both formats, CSV fields containing commas have to be quoted.
+#[derive(Deserialize, Debug)]
+struct BulkSubscription {
+    /// The channel id, or the topic URL with the channel id.
+    channel: String,
+    #[serde(default)]
+    post_shorts: Option<bool>,
+    #[serde(default)]
+    reddit_account_ids: Option<String>,
+    #[serde(default)]
+    subreddits: Option<String>,
+    #[serde(default)]
+    flair_id: Option<String>,
+    #[serde(default)]
+    title_prefix: Option<String>,
+    #[serde(default)]
+    title_suffix: Option<String>,
+}
+
+impl BulkSubscription {
+    /// The same form the frontend submits, so the line is validated like a subscription made there.
+    fn to_form(&self) -> YouTubeSubscribeForm {
+        let channel = self.channel.trim();
+        let topic_url = if channel.starts_with("https://") {
+            channel.to_string()
+        } else {
+            format!(
+                "https://www.youtube.com/xml/feeds/videos.xml?channel_id={}",
+                channel
+            )
+        };
+
+        YouTubeSubscribeForm {
+            topic_url,
+            hmac_secret: String::new(),
+            post_shorts: self.post_shorts.unwrap_or(false),
+            min_duration_seconds: None,
+            reddit_account_ids: split_list(&self.reddit_account_ids),
+            subreddit_names: split_list(&self.subreddits),
+            submission_title_prefix: non_empty(&self.title_prefix),
+            submission_title_suffix: non_empty(&self.title_suffix),
+            submission_flair_id: non_empty(&self.flair_id),
+            include_title_patterns: Vec::new(),
+            exclude_title_patterns: Vec::new(),
+            posting_cutoff: None,
+            idempotency_key: None,
+        }
+    }
+}
+
+fn split_list(list: &Option<String>) -> Vec<String> {
+    list.as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_ref()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Subscribes to every channel in the CSV or JSON file without starting the server. A failed line
+/// doesn't stop the others, each is printed and any failed one fails the command.
+/// The hub verifies the subscriptions by calling the running server, which has to use the same database.
+pub async fn bulk_subscribe(file: &Path, app_settings: Settings) -> Result<(), ApiError> {
+    configure_http_client(
+        app_settings.http_timeouts,
+        app_settings.reddit_operator.as_deref(),
+    );
+    configure_reddit_urls(app_settings.reddit_urls.clone());
+    configure_hub_test_mode(app_settings.hub_test_mode);
+
+    let lines = read_bulk_subscriptions(file)?;
+
+    // The verification checks aren't scheduled as there is no scheduler, the server's hub callback still verifies them.
+    let (state, _receiver) = AppState::new(app_settings, None).await;
+
+    sqlx::migrate!().run(&state.db_pool).await?;
+
+    let mut failed = 0;
+    for (line, bulk_subscription) in &lines {
+        match subscribe_line(&state, bulk_subscription).await {
+            Ok((channel, subscription_id)) => println!(
+                "[PASS] Line {}: {} subscribed, pending the hub's verification of the subscription {}",
+                line, channel, subscription_id
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("[FAIL] Line {}: {}", line, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(ApiError::InternalError(format!(
+            "{} of {} subscriptions failed",
+            failed,
+            lines.len()
+        )));
+    }
+
+    Ok(())
+}
+
+async fn subscribe_line(
+    state: &Arc<AppState>,
+    bulk_subscription: &Result<BulkSubscription, String>,
+) -> Result<(String, String), ApiError> {
+    let bulk_subscription = bulk_subscription
+        .as_ref()
+        .map_err(|e| ApiError::BadRequest(e.clone()))?;
+
+    let subscription_id = subscribe_from_form(state, &bulk_subscription.to_form()).await?;
+
+    Ok((
+        bulk_subscription.channel.trim().to_string(),
+        subscription_id,
+    ))
+}
+
+/// The line number in a CSV file or the position in a JSON array, with the parsed line.
+/// A line that can't be parsed is kept as its error, so it's reported with the others.
+type BulkLine = (u64, Result<BulkSubscription, String>);
+
+fn read_bulk_subscriptions(file: &Path) -> Result<Vec<BulkLine>, ApiError> {
+    let contents = std::fs::read_to_string(file).map_err(|e| {
+        ApiError::BadRequest(format!("Could not read the file {}: {}", file.display(), e))
+    })?;
+
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_deref() {
+        Some("json") => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(&contents).map_err(|e| {
+                ApiError::BadRequest(format!(
+                    "The file {} has to be a JSON array: {}",
+                    file.display(),
+                    e
+                ))
+            })?;
+
+            Ok(values
+                .into_iter()
+                .zip(1..)
+                .map(|(value, position)| {
+                    (
+                        position,
+                        serde_json::from_value(value).map_err(|e| e.to_string()),
+                    )
+                })
+                .collect())
+        }
+        Some("csv") => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(contents.as_bytes());
+            let headers = reader
+                .headers()
+                .map_err(|e| {
+                    ApiError::BadRequest(format!(
+                        "The file {} has no CSV header: {}",
+                        file.display(),
+                        e
+                    ))
+                })?
+                .clone();
+
+            Ok(reader
+                .records()
+                .map(|record| match record {
+                    Ok(record) => (
+                        record.position().map_or(0, |position| position.line()),
+                        record
+                            .deserialize(Some(&headers))
+                            .map_err(|e| e.to_string()),
+                    ),
+                    Err(e) => (
+                        e.position().map_or(0, |position| position.line()),
+                        Err(e.to_string()),
+                    ),
+                })
+                .collect())
+        }
+        _ => Err(ApiError::BadRequest(format!(
+            "The file {} has to be a .csv or .json file",
+            file.display()
+        ))),
+    }
+}
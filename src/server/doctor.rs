@@ -0,0 +1,154 @@
+use std::{collections::HashSet, str::FromStr};
+
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::{
+    infrastructure::Settings,
+    server::{
+        ApiError,
+        shared::{
+            HTTP_CLIENT, PUBSUBHUBBUB_HUB_URL, RedditCredentials, configure_http_client,
+            configure_reddit_urls, reddit_oauth_url, reddit_url,
+        },
+    },
+};
+
+/// Checks the database, the Reddit client credentials and the hub without starting the server.
+/// Every check is run and printed, any failed one fails the command.
+pub async fn doctor(app_settings: Settings) -> Result<(), ApiError> {
+    configure_http_client(
+        app_settings.http_timeouts,
+        app_settings.reddit_operator.as_deref(),
+    );
+    configure_reddit_urls(app_settings.reddit_urls.clone());
+
+    let results = [
+        ("Database", check_database(&app_settings.database_url).await),
+        (
+            "Reddit credentials",
+            check_reddit_credentials(&app_settings.reddit_credentials).await,
+        ),
+        ("PubSubHubbub hub", check_hub().await),
+    ];
+
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(details) => println!("[PASS] {}: {}", name, details),
+            Err(e) => {
+                failed += 1;
+                println!("[FAIL] {}: {}", name, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(ApiError::InternalError(format!(
+            "{} of {} checks failed",
+            failed,
+            results.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// The database isn't created or migrated here, that's left to `start`.
+async fn check_database(database_url: &str) -> Result<String, ApiError> {
+    let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(false);
+
+    let pool: SqlitePool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    let applied: HashSet<i64> =
+        sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success = 1;")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| {
+                ApiError::InternalError(format!(
+                    "The database isn't migrated, run `sqlx migrate run` or start the bot once: {}",
+                    e
+                ))
+            })?
+            .into_iter()
+            .collect();
+
+    let migrator = sqlx::migrate!();
+    let pending: Vec<String> = migrator
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| migration.description.to_string())
+        .collect();
+
+    if !pending.is_empty() {
+        return Err(ApiError::InternalError(format!(
+            "{} migrations aren't applied yet ({}), run `sqlx migrate run` or start the bot once",
+            pending.len(),
+            pending.join(", ")
+        )));
+    }
+
+    Ok(format!(
+        "reachable, all {} migrations applied",
+        migrator.iter().count()
+    ))
+}
+
+/// An app-only token is enough to prove the client id and secret, no Reddit account is needed.
+async fn check_reddit_credentials(
+    reddit_credentials: &RedditCredentials,
+) -> Result<String, ApiError> {
+    let client = &HTTP_CLIENT;
+
+    let token_response = client
+        .post(reddit_url("/api/v1/access_token"))
+        .basic_auth(
+            &reddit_credentials.client_id,
+            Some(&reddit_credentials.client_secret),
+        )
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await?;
+
+    let status = token_response.status();
+    let token_response = token_response.text().await?;
+
+    let access_token = serde_json::from_str::<serde_json::Value>(&token_response)
+        .ok()
+        .and_then(|token| token["access_token"].as_str().map(str::to_string))
+        .ok_or(ApiError::BadRequest(format!(
+            "Reddit didn't accept the CLIENT_ID and CLIENT_SECRET, the response was {}: {}",
+            status, token_response
+        )))?;
+
+    client
+        .get(reddit_oauth_url("/api/v1/scopes"))
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok("the client credentials were accepted".to_string())
+}
+
+/// A GET doesn't change anything on the hub, any answer that isn't a server error means it's reachable.
+async fn check_hub() -> Result<String, ApiError> {
+    let hub_response = HTTP_CLIENT.get(PUBSUBHUBBUB_HUB_URL).send().await?;
+
+    if hub_response.status().is_server_error() {
+        return Err(ApiError::InternalError(format!(
+            "The hub answered with {}",
+            hub_response.status()
+        )));
+    }
+
+    Ok(format!(
+        "reachable, answered with {}",
+        hub_response.status()
+    ))
+}
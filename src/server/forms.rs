@@ -3,7 +3,8 @@ use std::{
     sync::{Arc, LazyLock},
 };
 
-use axum::{Form, extract::State, response::Redirect};
+use axum::{Form, extract::State, http::HeaderMap, response::Redirect};
+use chrono::{NaiveDate, NaiveTime};
 
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -11,13 +12,22 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
 use crate::{
-    infrastructure::AppState,
+    infrastructure::{AppState, DuplicateSubscriptionAction},
     server::{
-        ApiError,
-        repository::{register_subreddit_form, register_subscription_link, save_form_data},
+        ApiError, ErrorResponse,
+        google::{fetch_channel_name_or_id, schedule_verification_check},
+        repository::{
+            NewSubreddit, UpdatedSubscription, discard_pending_hmac_secret,
+            link_subscription_targets, mark_subscription_pending, reddit_account_exists,
+            register_subreddit_form, register_subscription_link, save_form_data,
+            subscription_exists_for_channel, update_subscription_channel_name,
+            update_subscription_for_channel, update_subscription_link_moderation,
+        },
         shared::{
-            FormType, RedditAuthorization, RedditAuthorizeDuration, YouTubeSubscription,
-            extract_channel_id_from_topic_url, subscribe_to_channel,
+            FormType, RedditAuthorization, RedditAuthorizeDuration, TitleFilters,
+            YouTubeSubscription, check_callback_reachable, extract_channel_id_from_topic_url,
+            hub_test_mode, reddit_url, subscribe_to_channel, validate_callback_url,
+            validate_subreddit_name, validate_title_template,
         },
     },
 };
@@ -121,14 +131,15 @@ impl RedditAuthorizeForm {
         description = "Redirect to Reddit authorize URL via from input",
         responses(
             (status = 303, description = "Reddit authorize URL redirect."),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Invalid form data.", body = ErrorResponse),
+            (status = 500, description = "Internal server error.", body = ErrorResponse),
         ),
         tag = "forms"
     )]
 #[axum::debug_handler]
 async fn reddit_authorize_submission(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Form(form_input): Form<RedditAuthorizeForm>,
 ) -> Result<Redirect, ApiError> {
     let reddit_authorization = RedditAuthorizeForm::validate(&form_input)?;
@@ -139,14 +150,17 @@ async fn reddit_authorize_submission(
         &state.db_pool,
         &uuid.to_string(),
         &authorize_submission_json_str,
+        None,
     )
     .await?;
 
     let authorize_url = format!(
-        "https://www.reddit.com/api/v1/authorize?client_id={client_id}&response_type=code&state={state_string}&redirect_uri={redirect_url}&duration={duration}&scope={scope_string}",
+        "{authorize_url}?client_id={client_id}&response_type=code&state={state_string}&redirect_uri={redirect_url}&duration={duration}&scope={scope_string}",
+        authorize_url = reddit_url("/api/v1/authorize"),
         client_id = state.reddit_credentials.client_id,
         state_string = uuid,
-        redirect_url = format!("{}/reddit/callback", &state.base_url),
+        // Has to be the same as the one the callback exchanges the code with.
+        redirect_url = format!("{}/reddit/callback", state.external_base_url(&headers)),
         duration = reddit_authorization.duration,
         scope_string = reddit_authorization.scopes
     );
@@ -157,23 +171,132 @@ async fn reddit_authorize_submission(
 #[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
 pub struct YouTubeSubscribeForm {
     pub topic_url: String,
+    /// Generated when left empty.
+    #[serde(default)]
     pub hmac_secret: String,
     pub post_shorts: bool,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub min_duration_seconds: Option<String>,
+    /// Comma separated ids of the Reddit accounts that should submit the channel's videos.
+    #[serde(default, deserialize_with = "comma_separated")]
+    #[schema(value_type = String)]
+    pub reddit_account_ids: Vec<String>,
+    /// Comma separated names of the subreddits the chosen Reddit accounts submit the channel's videos to.
+    #[serde(default, deserialize_with = "comma_separated")]
+    #[schema(value_type = String)]
+    pub subreddit_names: Vec<String>,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub submission_title_prefix: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub submission_title_suffix: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub submission_flair_id: Option<String>,
+    /// Regexes, one per line, of which a video title has to match at least one to be submitted.
+    #[serde(default, deserialize_with = "line_separated")]
+    #[schema(value_type = String)]
+    pub include_title_patterns: Vec<String>,
+    /// Regexes, one per line, of which a video title can't match any to be submitted.
+    #[serde(default, deserialize_with = "line_separated")]
+    #[schema(value_type = String)]
+    pub exclude_title_patterns: Vec<String>,
+    /// Date (YYYY-MM-DD) from which videos are submitted, defaults to when the subscription is created.
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub posting_cutoff: Option<String>,
+    /// Identifies repeated submissions of the same form, e.g. a double click. Defaults to the channel and callback base URL.
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub idempotency_key: Option<String>,
 }
 
 impl YouTubeSubscribeForm {
     fn validate(subscription: &Self) -> Result<(YouTubeSubscription, String), ApiError> {
         let topic_url = subscription.topic_url.trim();
-        let hmac_secret = subscription.hmac_secret.trim();
         let channel_id = extract_channel_id_from_topic_url(&subscription.topic_url)?;
 
-        if topic_url.is_empty() || hmac_secret.is_empty() || channel_id.is_empty() {
+        if topic_url.is_empty() || channel_id.is_empty() {
             return Err(ApiError::BadRequest(format!(
-                "Topic URL, HMAC secret, callback url or channel id input was empty. Inputted Topic URL: '{}' HMAC secret: '{}' channel id '{}'",
-                topic_url, hmac_secret, channel_id
+                "Topic URL, callback url or channel id input was empty. Inputted Topic URL: '{}' channel id '{}'",
+                topic_url, channel_id
             )));
         }
 
+        let hmac_secret = match subscription.hmac_secret.trim() {
+            "" => {
+                println!(
+                    "No HMAC secret was given for the YouTube channel {}, generated one, it's shown on the subscription's page.",
+                    channel_id
+                );
+                generate_hmac_secret()
+            }
+            hmac_secret => {
+                validate_hmac_secret(hmac_secret)?;
+                hmac_secret.to_string()
+            }
+        };
+
+        let min_duration_seconds = subscription
+            .min_duration_seconds
+            .as_ref()
+            .map(|seconds| match seconds.trim().parse::<i64>() {
+                Ok(seconds) if seconds >= 0 => Ok(seconds),
+                _ => Err(ApiError::BadRequest(format!(
+                    "The minimum duration has to be a whole number of seconds, the input was: {}",
+                    seconds
+                ))),
+            })
+            .transpose()?;
+
+        let mut reddit_account_ids: Vec<String> = Vec::new();
+
+        for reddit_account_id in &subscription.reddit_account_ids {
+            Uuid::try_parse(reddit_account_id).map_err(|_| {
+                ApiError::BadRequest(format!(
+                    "Invalid Reddit account id, the input was: {}",
+                    reddit_account_id
+                ))
+            })?;
+
+            if !reddit_account_ids.contains(reddit_account_id) {
+                reddit_account_ids.push(reddit_account_id.clone());
+            }
+        }
+
+        for subreddit_name in &subscription.subreddit_names {
+            validate_subreddit_name(normalize_subreddit_name(subreddit_name))?;
+        }
+
+        if !subscription.subreddit_names.is_empty() && reddit_account_ids.is_empty() {
+            return Err(ApiError::BadRequest(
+                "Choose at least one Reddit account to submit to the chosen subreddits".into(),
+            ));
+        }
+
+        if let Some(flair_id) = &subscription.submission_flair_id {
+            Uuid::try_parse(flair_id.trim()).map_err(|_| {
+                ApiError::BadRequest(format!(
+                    "The flair id has to be a Reddit flair template id (UUID), the input was: {}",
+                    flair_id
+                ))
+            })?;
+        }
+
+        let title_filters = TitleFilters {
+            include: subscription.include_title_patterns.clone(),
+            exclude: subscription.exclude_title_patterns.clone(),
+        };
+        title_filters.validate()?;
+
+        let posting_cutoff = subscription
+            .posting_cutoff
+            .as_ref()
+            .map(|date| match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+                Ok(date) => Ok(date.and_time(NaiveTime::MIN).and_utc().timestamp()),
+                Err(_) => Err(ApiError::BadRequest(format!(
+                    "The posting cutoff has to be a date in the YYYY-MM-DD format, the input was: {}",
+                    date
+                ))),
+            })
+            .transpose()?;
+
         let uuid_str = Uuid::now_v7().to_string();
 
         Ok((
@@ -181,8 +304,14 @@ impl YouTubeSubscribeForm {
                 r#type: FormType::Youtube,
                 topic_url: topic_url.to_string(),
                 channel_id: channel_id.to_string(),
-                hmac_secret: hmac_secret.to_string(),
+                hmac_secret,
                 post_shorts: subscription.post_shorts,
+                min_duration_seconds,
+                reddit_account_ids,
+                subreddit_ids: Vec::new(),
+                title_filters,
+                posting_cutoff,
+                channel_name: None,
             },
             uuid_str,
         ))
@@ -197,8 +326,9 @@ impl YouTubeSubscribeForm {
         description = "Subscribe to a YouTube channel via form input",
         responses(
             (status = 303, description = "Successfully subscribed to Youtube channel redirect to home page."),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Invalid form data.", body = ErrorResponse),
+            (status = 409, description = "The YouTube channel already has a subscription.", body = ErrorResponse),
+            (status = 500, description = "Internal server error.", body = ErrorResponse),
         ),
         tag = "forms"
     )]
@@ -207,24 +337,159 @@ async fn youtube_channel_subscribe(
     State(state): State<Arc<AppState>>,
     Form(form_input): Form<YouTubeSubscribeForm>,
 ) -> Result<Redirect, ApiError> {
-    let (subscription, uuid_str) = YouTubeSubscribeForm::validate(&form_input)?;
+    subscribe_from_form(&state, &form_input).await?;
+
+    Ok(Redirect::to(&state.base_url))
+}
+
+/// Validates the subscribe form, registers its subreddits and subscribes to the channel at the hub.
+/// Returns the id of the subscription, which is pending until the hub verifies it.
+pub async fn subscribe_from_form(
+    state: &Arc<AppState>,
+    form_input: &YouTubeSubscribeForm,
+) -> Result<String, ApiError> {
+    let (mut subscription, uuid_str) = YouTubeSubscribeForm::validate(form_input)?;
     println!(
         "New YouTube subscription request for YouTube channel: https://www.youtube.com/channel/{}",
         &subscription.channel_id
     );
 
-    let subscription_json_str = serde_json::to_string(&subscription)?;
+    for reddit_account_id in &subscription.reddit_account_ids {
+        if !reddit_account_exists(&state.db_pool, reddit_account_id).await? {
+            return Err(ApiError::BadRequest(format!(
+                "No Reddit account found for the id: {}",
+                reddit_account_id
+            )));
+        }
+    }
+
+    // The title affixes and flair only apply to subreddits that aren't registered yet.
+    for subreddit_name in &form_input.subreddit_names {
+        let subreddit_id = register_subreddit_form(
+            &state.db_pool,
+            &NewSubreddit {
+                name: normalize_subreddit_name(subreddit_name).to_string(),
+                title_prefix: form_input.submission_title_prefix.clone(),
+                title_suffix: form_input.submission_title_suffix.clone(),
+                title_template: None,
+                flair_id: form_input
+                    .submission_flair_id
+                    .as_ref()
+                    .map(|id| id.trim().to_string()),
+                nsfw: false,
+                spoiler: false,
+            },
+        )
+        .await?;
+
+        if !subscription.subreddit_ids.contains(&subreddit_id) {
+            subscription.subreddit_ids.push(subreddit_id);
+        }
+    }
+
+    subscription.channel_name =
+        Some(fetch_channel_name_or_id(state, &subscription.channel_id).await);
+
+    // Every subscription callback shares the base URL, so checking the new one covers them all.
+    let callback_url = format!("{}/google/subscription/{}", &state.base_url, uuid_str);
+
+    // The test mode doesn't involve the hub, so a local base URL is fine.
+    if !hub_test_mode() {
+        validate_callback_url(&callback_url)?;
+    }
+
+    if state.check_callback_reachability {
+        check_callback_reachable(&callback_url).await?;
+    }
+
+    let callback_id = if subscription_exists_for_channel(&state.db_pool, &subscription.channel_id)
+        .await?
+    {
+        match state.duplicate_subscription_action {
+            DuplicateSubscriptionAction::Reject => {
+                return Err(ApiError::Conflict(format!(
+                    "A subscription already exists for the YouTube channel: {}",
+                    subscription.channel_id
+                )));
+            }
+            DuplicateSubscriptionAction::Update => {
+                // A blank secret field keeps the stored secret instead of the generated one.
+                let new_hmac_secret = (!form_input.hmac_secret.trim().is_empty())
+                    .then_some(&subscription.hmac_secret);
+                let UpdatedSubscription {
+                    id: subscription_id,
+                    hmac_secret,
+                } = update_subscription_for_channel(&state.db_pool, &subscription, new_hmac_secret)
+                    .await?;
+                if new_hmac_secret.is_none() {
+                    subscription.hmac_secret = hmac_secret;
+                }
+                if let Some(channel_name) = &subscription.channel_name {
+                    update_subscription_channel_name(
+                        &state.db_pool,
+                        &subscription_id,
+                        channel_name,
+                    )
+                    .await?;
+                }
+                println!(
+                    "Updated the existing subscription {} instead of adding a duplicate.",
+                    subscription_id
+                );
+
+                link_subscription_targets(&state.db_pool, &subscription_id, &subscription).await?;
+
+                subscription_id
+            }
+        }
+    } else {
+        let subscription_json_str = serde_json::to_string(&subscription)?;
+        let idempotency_key = form_input.idempotency_key.clone().unwrap_or(format!(
+            "{}|{}/google/subscription",
+            subscription.channel_id, &state.base_url
+        ));
+
+        let form_id = save_form_data(
+            &state.db_pool,
+            &uuid_str,
+            &subscription_json_str,
+            Some(&idempotency_key),
+        )
+        .await?;
+
+        // Subscribing again with the same callback is harmless, the hub only verifies it once more.
+        if form_id != uuid_str {
+            println!(
+                "Repeated subscribe request for the YouTube channel {}, reusing the pending subscription: {}",
+                subscription.channel_id, form_id
+            );
+        }
+
+        form_id
+    };
 
-    save_form_data(&state.db_pool, &uuid_str, &subscription_json_str).await?;
+    if let Err(e) = subscribe_to_channel(
+        &format!("{}/google/subscription/{}", &state.base_url, callback_id),
+        &subscription.channel_id,
+        &subscription.hmac_secret,
+    )
+    .await
+    {
+        discard_pending_hmac_secret(&state.db_pool, &callback_id).await?;
+        return Err(e);
+    }
 
-    subscribe_to_channel(
-        &format!("{}/google/subscription/{}", &state.base_url, uuid_str),
+    mark_subscription_pending(&state.db_pool, &callback_id).await?;
+    schedule_verification_check(
+        state,
+        &callback_id,
         &subscription.channel_id,
         &subscription.hmac_secret,
+        0,
     )
-    .await?;
+    .await;
 
-    Ok(Redirect::to(&state.base_url))
+    Ok(callback_id)
 }
 
 #[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
@@ -234,8 +499,77 @@ struct RegisterSubredditForm {
     pub submission_title_prefix: Option<String>,
     #[serde(deserialize_with = "empty_string_is_none")]
     pub submission_title_suffix: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub submission_title_template: Option<String>,
     #[serde(deserialize_with = "empty_string_is_none")]
     pub submission_flair_id: Option<String>,
+    #[serde(default)]
+    pub nsfw: bool,
+    #[serde(default)]
+    pub spoiler: bool,
+}
+
+const MIN_HMAC_SECRET_LENGTH: usize = 20;
+// The hub only accepts secrets shorter than 200 bytes.
+const MAX_HMAC_SECRET_LENGTH: usize = 199;
+const MIN_HMAC_SECRET_DISTINCT_CHARS: usize = 8;
+
+/// The secret is all that stops forged video notifications, so short or repetitive ones are rejected.
+fn validate_hmac_secret(hmac_secret: &str) -> Result<(), ApiError> {
+    if hmac_secret.len() < MIN_HMAC_SECRET_LENGTH || hmac_secret.len() > MAX_HMAC_SECRET_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "The HMAC secret has to be between {} and {} characters long, it was {} characters. Leave it empty to have a strong secret generated.",
+            MIN_HMAC_SECRET_LENGTH,
+            MAX_HMAC_SECRET_LENGTH,
+            hmac_secret.len()
+        )));
+    }
+
+    let distinct_chars = hmac_secret.chars().collect::<HashSet<char>>().len();
+    if distinct_chars < MIN_HMAC_SECRET_DISTINCT_CHARS {
+        return Err(ApiError::BadRequest(format!(
+            "The HMAC secret is too repetitive, it needs at least {} different characters. Leave it empty to have a strong secret generated.",
+            MIN_HMAC_SECRET_DISTINCT_CHARS
+        )));
+    }
+
+    Ok(())
+}
+
+/// 64 random hex characters.
+fn generate_hmac_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Accepts subreddit names written as `r/name` or `/r/name` as well.
+fn normalize_subreddit_name(name: &str) -> &str {
+    let name = name.trim();
+    name.strip_prefix("/r/")
+        .or_else(|| name.strip_prefix("r/"))
+        .unwrap_or(name)
+}
+
+fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(s.split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect())
+}
+
+// Regexes can contain commas, so they're entered one per line instead.
+fn line_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(s.lines()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect())
 }
 
 fn empty_string_is_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
@@ -246,6 +580,15 @@ where
     Ok(s.filter(|s| !s.trim().is_empty()))
 }
 
+fn empty_string_is_none_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    empty_string_is_none(deserializer)?
+        .map(|s| s.trim().parse::<bool>().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
 /// Register a new subreddit
 #[utoipa::path(
         post,
@@ -254,8 +597,8 @@ where
         description = "Register a new subreddit to submit videos to.",
         responses(
             (status = 303, description = "Successfully registered a new subreddit."),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Invalid form data.", body = ErrorResponse),
+            (status = 500, description = "Internal server error.", body = ErrorResponse),
         ),
         tag = "forms"
     )]
@@ -264,19 +607,28 @@ async fn register_subreddit(
     State(state): State<Arc<AppState>>,
     Form(form_input): Form<RegisterSubredditForm>,
 ) -> Result<Redirect, ApiError> {
+    let subreddit_name = normalize_subreddit_name(&form_input.subreddit_name);
+    validate_subreddit_name(subreddit_name)?;
+
+    if let Some(template) = &form_input.submission_title_template {
+        validate_title_template(template)?;
+    }
+
     register_subreddit_form(
         &state.db_pool,
-        &form_input.subreddit_name,
-        &form_input.submission_title_prefix,
-        &form_input.submission_title_suffix,
-        &form_input.submission_flair_id,
+        &NewSubreddit {
+            name: subreddit_name.to_string(),
+            title_prefix: form_input.submission_title_prefix,
+            title_suffix: form_input.submission_title_suffix,
+            title_template: form_input.submission_title_template,
+            flair_id: form_input.submission_flair_id,
+            nsfw: form_input.nsfw,
+            spoiler: form_input.spoiler,
+        },
     )
     .await?;
 
-    println!(
-        "Successfully registered {} to the DB.",
-        &form_input.subreddit_name
-    );
+    println!("Successfully registered {} to the DB.", subreddit_name);
 
     Ok(Redirect::to(&state.base_url))
 }
@@ -286,6 +638,9 @@ struct LinkSubscriptionForm {
     pub subscription_id: String,
     pub reddit_account_id: String,
     pub subreddit_id: i64,
+    /// Overrides the Reddit account's moderation on the subreddit, empty uses the account's setting.
+    #[serde(default, deserialize_with = "empty_string_is_none_bool")]
+    pub moderate_submissions: Option<bool>,
 }
 
 /// Link subscription to reddit account with subreddit
@@ -296,8 +651,8 @@ struct LinkSubscriptionForm {
         description = "Link a subscription to use  reddit account on to submit videos to subreddit",
         responses(
             (status = 303, description = "Successfully sLink a subscription to use  reddit account on to submit videos to subreddit."),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Invalid form data.", body = ErrorResponse),
+            (status = 500, description = "Internal server error.", body = ErrorResponse),
         ),
         tag = "forms"
     )]
@@ -319,7 +674,166 @@ async fn link_subscription(
     )
     .await?;
 
+    // Linking an already linked pair again changes its moderation override.
+    update_subscription_link_moderation(
+        &state.db_pool,
+        &form_input.subscription_id,
+        &form_input.reddit_account_id,
+        &form_input.subreddit_id,
+        &form_input.moderate_submissions,
+    )
+    .await?;
+
     println!("Successfully linked subscription to reddit account and subreddit.");
 
     Ok(Redirect::to(&state.base_url))
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+    use super::*;
+    use crate::{
+        infrastructure::Settings,
+        server::{
+            SubCommand,
+            google::verify_subscription,
+            repository::get_subscription_details,
+            shared::{CachedChannel, MOCK_HUB_URL, Verification, VerificationMode},
+            test_support::{CHANNEL_ID, topic_url, verified_subscription},
+        },
+    };
+
+    const NEW_HMAC_SECRET: &str = "a-new-secret-of-enough-length";
+
+    fn resubscribe_form(hmac_secret: &str) -> YouTubeSubscribeForm {
+        YouTubeSubscribeForm {
+            topic_url: topic_url(CHANNEL_ID),
+            hmac_secret: hmac_secret.to_string(),
+            post_shorts: true,
+            min_duration_seconds: None,
+            reddit_account_ids: Vec::new(),
+            subreddit_names: Vec::new(),
+            submission_title_prefix: None,
+            submission_title_suffix: None,
+            submission_flair_id: None,
+            include_title_patterns: Vec::new(),
+            exclude_title_patterns: Vec::new(),
+            posting_cutoff: None,
+            idempotency_key: None,
+        }
+    }
+
+    async fn updating_state(
+        hub_status: u16,
+    ) -> (Arc<AppState>, mpsc::Receiver<SubCommand>, MockServer) {
+        let hub = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(hub_status))
+            .mount(&hub)
+            .await;
+        MOCK_HUB_URL.set(Some(hub.uri()));
+
+        let mut settings = Settings::for_tests();
+        settings.duplicate_subscription_action = DuplicateSubscriptionAction::Update;
+        let (state, receiver) = AppState::for_tests(settings).await;
+        state
+            .channels
+            .insert(CHANNEL_ID, CachedChannel::new(CHANNEL_ID, "Channel"))
+            .await;
+
+        (state, receiver, hub)
+    }
+
+    async fn stored_secrets(
+        state: &AppState,
+        subscription_id: &String,
+    ) -> (String, Option<String>) {
+        sqlx::query_as("SELECT hmac_secret, pending_hmac_secret FROM subscriptions WHERE id = ?")
+            .bind(subscription_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap()
+    }
+
+    fn sent_hmac_secret(body: &[u8]) -> Option<String> {
+        url::form_urlencoded::parse(body)
+            .find(|(key, _)| key == "hub.secret")
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[tokio::test]
+    async fn update_with_a_blank_secret_keeps_the_stored_secret() {
+        let (state, _receiver, hub) = updating_state(202).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+
+        let callback_id = subscribe_from_form(&state, &resubscribe_form(""))
+            .await
+            .unwrap();
+
+        assert_eq!(callback_id, subscription_id);
+        assert_eq!(
+            stored_secrets(&state, &subscription_id).await,
+            ("secret".to_string(), None)
+        );
+        let requests = hub.received_requests().await.unwrap();
+        assert_eq!(
+            sent_hmac_secret(&requests[0].body).as_deref(),
+            Some("secret")
+        );
+        let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(subscription.post_shorts);
+    }
+
+    #[tokio::test]
+    async fn new_secret_replaces_the_stored_one_once_verified() {
+        let (state, _receiver, _hub) = updating_state(202).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+
+        subscribe_from_form(&state, &resubscribe_form(NEW_HMAC_SECRET))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored_secrets(&state, &subscription_id).await,
+            ("secret".to_string(), Some(NEW_HMAC_SECRET.to_string()))
+        );
+
+        let verification = Verification {
+            mode: VerificationMode::Subscribe,
+            topic: topic_url(CHANNEL_ID),
+            challenge: "resubscribe-challenge".to_string(),
+            lease_seconds: Some(432000),
+        };
+        verify_subscription(&state, &subscription_id, verification)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored_secrets(&state, &subscription_id).await,
+            (NEW_HMAC_SECRET.to_string(), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_resubscribe_keeps_the_stored_secret() {
+        let (state, _receiver, _hub) = updating_state(500).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+
+        assert!(
+            subscribe_from_form(&state, &resubscribe_form(NEW_HMAC_SECRET))
+                .await
+                .is_err()
+        );
+
+        assert_eq!(
+            stored_secrets(&state, &subscription_id).await,
+            ("secret".to_string(), None)
+        );
+    }
+}
@@ -1,11 +1,11 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::Html,
 };
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
@@ -13,13 +13,20 @@ use uuid::Uuid;
 use crate::{
     infrastructure::AppState,
     server::{
-        ApiError,
+        ApiError, ErrorResponse,
+        reddit::missing_reddit_scopes,
         repository::{
-            Subscription, fetch_linked_subscriptions, fetch_reddit_accounts, fetch_subreddits,
-            fetch_subscriptions, get_reddit_account_by_id, get_subreddit_by_id,
-            get_subscription_by_id,
+            SubmissionRecord, Subscription, TokenRefresh, count_linked_subscriptions,
+            count_reddit_accounts, count_submissions_for_subscription, count_subscriptions,
+            fetch_linked_subscriptions, fetch_reddit_account_options, fetch_reddit_accounts_page,
+            fetch_submissions_for_subscription, fetch_subreddits, fetch_subscription_options,
+            fetch_subscriptions_page, fetch_token_refreshes, get_reddit_account_by_id,
+            get_subreddit_by_id, get_subscription_by_id,
+        },
+        shared::{
+            DescriptionComment, RedditAccountDTO, RedditOAuthToken, Subreddit, SubscriptionStatus,
+            TitleFilters, reddit_submission_permalink,
         },
-        shared::{RedditAccountDTO, Subreddit},
     },
 };
 
@@ -40,12 +47,6 @@ impl From<handlebars::RenderError> for ApiError {
     }
 }
 
-impl From<handlebars::TemplateError> for ApiError {
-    fn from(error: handlebars::TemplateError) -> Self {
-        ApiError::InternalError(format!("Error on parsing HTML template: {:?}", error))
-    }
-}
-
 const DATE_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S (UTC)";
 
 mod date_format {
@@ -67,7 +68,11 @@ mod date_format {
 struct FrontendRedditAccountData {
     pub id: String,
     pub username: String,
-    pub oauth_token: String,
+    // Only what the token grants is shown, the access and refresh tokens never reach the page.
+    pub scopes: String,
+    /// The scopes the bot needs for the account's role that the token lacks, those actions are skipped.
+    pub missing_scopes: String,
+    pub refreshable: bool,
     pub moderate_submissions: bool,
     #[serde(with = "date_format")]
     pub expires_at: DateTime<Utc>,
@@ -75,10 +80,18 @@ struct FrontendRedditAccountData {
 
 impl FrontendRedditAccountData {
     fn convert(reddit_account: &RedditAccountDTO) -> Result<Self, ApiError> {
+        let oauth_token: RedditOAuthToken = serde_json::from_str(&reddit_account.oauth_token)?;
+
         Ok(FrontendRedditAccountData {
             id: reddit_account.id.clone(),
             username: reddit_account.username.clone(),
-            oauth_token: reddit_account.oauth_token.clone(),
+            missing_scopes: missing_reddit_scopes(
+                &oauth_token.scope,
+                reddit_account.moderate_submissions,
+            )
+            .join(", "),
+            scopes: oauth_token.scope,
+            refreshable: oauth_token.refresh_token.is_some(),
             moderate_submissions: reddit_account.moderate_submissions,
             expires_at: DateTime::from_timestamp_secs(reddit_account.expires_at).ok_or(
                 ApiError::InternalError(format!(
@@ -90,6 +103,27 @@ impl FrontendRedditAccountData {
     }
 }
 
+#[derive(Serialize)]
+struct FrontendTokenRefreshData {
+    #[serde(with = "optional_date_format")]
+    pub refreshed_at: Option<DateTime<Utc>>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl FrontendTokenRefreshData {
+    fn convert(token_refresh: TokenRefresh) -> Self {
+        FrontendTokenRefreshData {
+            refreshed_at: DateTime::from_timestamp_secs(token_refresh.refreshed_at),
+            success: token_refresh.success,
+            error: token_refresh.error,
+        }
+    }
+}
+
+/// The latest token refreshes shown on the account page.
+const ACCOUNT_PAGE_TOKEN_REFRESHES: i64 = 10;
+
 mod optional_date_format {
     use chrono::{DateTime, Utc};
     use serde::{self, Serializer};
@@ -117,10 +151,29 @@ struct FrontendSubscriptionData {
     #[serde(with = "optional_date_format")]
     pub expires_at: Option<DateTime<Utc>>,
     pub post_shorts: bool,
+    pub min_duration_seconds: Option<i64>,
+    pub include_title_patterns: String,
+    pub exclude_title_patterns: String,
+    pub posting_cutoff: String,
+    pub status: SubscriptionStatus,
+    pub min_post_interval_seconds: Option<i64>,
+    pub description_comment: bool,
+    pub description_comment_max_lines: Option<u32>,
 }
 
 impl FrontendSubscriptionData {
     fn convert(subscription: &Subscription) -> Result<Self, ApiError> {
+        let title_filters: TitleFilters = match &subscription.title_filters {
+            Some(title_filters) => serde_json::from_str(title_filters)?,
+            None => TitleFilters::default(),
+        };
+
+        let description_comment: Option<DescriptionComment> = subscription
+            .description_comment
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()?;
+
         Ok(FrontendSubscriptionData {
             id: subscription.id.clone(),
             channel_id: subscription.channel_id.clone(),
@@ -135,18 +188,65 @@ impl FrontendSubscriptionData {
                 )?),
                 None => None,
             },
-            post_shorts: subscription.post_shorts
+            post_shorts: subscription.post_shorts,
+            min_duration_seconds: subscription.min_duration_seconds,
+            include_title_patterns: title_filters.include.join("\n"),
+            exclude_title_patterns: title_filters.exclude.join("\n"),
+            posting_cutoff: match subscription
+                .posting_cutoff
+                .and_then(DateTime::from_timestamp_secs)
+            {
+                Some(cutoff) => cutoff.format(DATE_FORMAT_STR).to_string(),
+                None => String::from("No cutoff, every video is submitted"),
+            },
+            status: subscription.status,
+            min_post_interval_seconds: subscription.min_post_interval_seconds,
+            description_comment: description_comment.is_some(),
+            description_comment_max_lines: description_comment
+                .and_then(|description_comment| description_comment.max_lines),
         })
     }
 }
 
+#[derive(Serialize)]
+struct FrontendSubmissionData {
+    pub video_id: String,
+    pub subreddit_id: i64,
+    pub subreddit_name: String,
+    pub reddit_username: String,
+    #[serde(with = "optional_date_format")]
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub stickied: bool,
+    pub permalink: String,
+}
+
+impl FrontendSubmissionData {
+    fn convert(submission: SubmissionRecord) -> Self {
+        FrontendSubmissionData {
+            permalink: reddit_submission_permalink(&submission.id),
+            video_id: submission.video_id,
+            subreddit_id: submission.subreddit_id,
+            subreddit_name: submission.subreddit_name,
+            reddit_username: submission.reddit_username,
+            submitted_at: DateTime::from_timestamp_secs(submission.created_at),
+            stickied: submission.stickied,
+        }
+    }
+}
+
+/// The older submissions are listed by the `/api/submissions` endpoint.
+const SUBSCRIPTION_PAGE_SUBMISSIONS: i64 = 25;
+
 #[derive(Serialize)]
 struct FrontendSubredditData {
     pub id: i64,
     pub name: String,
     pub title_prefix: Option<String>,
     pub title_suffix: Option<String>,
+    pub title_template: Option<String>,
     pub flair_id: Option<String>,
+    pub nsfw: bool,
+    pub spoiler: bool,
 }
 
 impl FrontendSubredditData {
@@ -156,32 +256,93 @@ impl FrontendSubredditData {
             name: subreddit.name.clone(),
             title_prefix: subreddit.title_prefix.clone(),
             title_suffix: subreddit.title_suffix.clone(),
+            title_template: subreddit.title_template.clone(),
             flair_id: subreddit.flair_id.clone(),
+            nsfw: subreddit.nsfw,
+            spoiler: subreddit.spoiler,
         })
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct LandingPageQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+const LANDING_PAGE_DEFAULT_PER_PAGE: i64 = 25;
+const LANDING_PAGE_MAX_PER_PAGE: i64 = 100;
+
+#[derive(Serialize)]
+struct FrontendPagination {
+    pub page: i64,
+    pub per_page: i64,
+    pub total_pages: i64,
+    pub previous_page: Option<i64>,
+    pub next_page: Option<i64>,
+}
+
+impl FrontendPagination {
+    /// There are as many pages as the longest of the paginated lists needs.
+    fn new(page: i64, per_page: i64, longest_list: i64) -> Self {
+        let total_pages = ((longest_list + per_page - 1) / per_page).max(1);
+
+        FrontendPagination {
+            page,
+            per_page,
+            total_pages,
+            // Past the last page the previous page jumps back to the last one.
+            previous_page: (page > 1).then(|| (page - 1).min(total_pages)),
+            next_page: (page < total_pages).then(|| page + 1),
+        }
+    }
+}
+
 /// Main landing page
 #[utoipa::path(
         get,
         path = "/",
+        params(
+            ("page" = Option<i64>, Query, description = "The page of the Reddit account, subscription and linked subscription tables, starting at 1", example = 1),
+            ("per_page" = Option<i64>, Query, description = "The rows per table, 25 by default and at most 100", example = 25),
+        ),
         description = "Main landing page",
         responses(
-            (status = 200, description = "Main landing page html.", content_type = "text/html; charset=utf-8")
+            (status = 200, description = "Main landing page html.", content_type = "text/html; charset=utf-8"),
+            (status = 400, description = "Invalid page or rows per page.", body = ErrorResponse),
         ),
         tag = "frontend"
     )]
 #[axum::debug_handler]
-async fn main_landing_page(State(state): State<Arc<AppState>>) -> Result<Html<String>, ApiError> {
-    let mut local_hb = state.hb.clone();
+async fn main_landing_page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LandingPageQuery>,
+) -> Result<Html<String>, ApiError> {
+    let page = query.page.unwrap_or(1);
+    if page < 1 {
+        return Err(ApiError::BadRequest(format!(
+            "The page starts at 1, the input was: {}",
+            page
+        )));
+    }
 
-    let subscriptions = fetch_subscriptions(&state.db_pool)
+    let per_page = query.per_page.unwrap_or(LANDING_PAGE_DEFAULT_PER_PAGE);
+    if !(1..=LANDING_PAGE_MAX_PER_PAGE).contains(&per_page) {
+        return Err(ApiError::BadRequest(format!(
+            "The rows per page have to be between 1 and {}, the input was: {}",
+            LANDING_PAGE_MAX_PER_PAGE, per_page
+        )));
+    }
+
+    let offset = (page - 1) * per_page;
+
+    let subscriptions = fetch_subscriptions_page(&state.db_pool, None, per_page, offset)
         .await?
         .iter()
         .map(FrontendSubscriptionData::convert)
         .collect::<Result<Vec<FrontendSubscriptionData>, ApiError>>()?;
 
-    let reddit_accounts = fetch_reddit_accounts(&state.db_pool)
+    let reddit_accounts = fetch_reddit_accounts_page(&state.db_pool, per_page, offset)
         .await?
         .iter()
         .map(FrontendRedditAccountData::convert)
@@ -193,18 +354,28 @@ async fn main_landing_page(State(state): State<Arc<AppState>>) -> Result<Html<St
         .map(FrontendSubredditData::convert)
         .collect::<Result<Vec<FrontendSubredditData>, ApiError>>()?;
 
-    let linked_subscriptions = fetch_linked_subscriptions(&state.db_pool).await?;
+    let linked_subscriptions = fetch_linked_subscriptions(&state.db_pool, per_page, offset).await?;
 
-    local_hb.register_template_file("body_content", "frontend/landing_page.html")?;
+    // The forms' selects still list every subscription and Reddit account, only their ids and names are fetched.
+    let subscription_options = fetch_subscription_options(&state.db_pool).await?;
+    let reddit_account_options = fetch_reddit_account_options(&state.db_pool).await?;
+
+    let longest_list = count_subscriptions(&state.db_pool, None)
+        .await?
+        .max(count_reddit_accounts(&state.db_pool).await?)
+        .max(count_linked_subscriptions(&state.db_pool).await?);
 
     let data = json!({
         "reddit_accounts": reddit_accounts,
         "subscriptions": subscriptions,
         "subreddits": subreddits,
-        "linked_subscriptions": linked_subscriptions
+        "linked_subscriptions": linked_subscriptions,
+        "subscription_options": subscription_options,
+        "reddit_account_options": reddit_account_options,
+        "pagination": FrontendPagination::new(page, per_page, longest_list)
     });
 
-    let whole_document = local_hb.render("whole_document", &data)?;
+    let whole_document = state.render_page("landing_page", &data)?;
 
     Ok(Html(whole_document))
 }
@@ -229,21 +400,28 @@ async fn reddit_account_page(
 ) -> Result<Html<String>, ApiError> {
     Uuid::try_parse(&reddit_account_id).map_err(|_| ApiError::BadRequest("Invalid ID".into()))?;
 
-    let mut local_hb = state.hb.clone();
-
     let reddit_account = get_reddit_account_by_id(&state.db_pool, &reddit_account_id)
         .await
         .map_err(|_| ApiError::NotFound("Account doesn't exist".into()))?;
 
     let reddit_account = FrontendRedditAccountData::convert(&reddit_account)?;
 
-    local_hb.register_template_file("body_content", "frontend/reddit_account.html")?;
+    let token_refreshes: Vec<FrontendTokenRefreshData> = fetch_token_refreshes(
+        &state.db_pool,
+        &reddit_account_id,
+        ACCOUNT_PAGE_TOKEN_REFRESHES,
+    )
+    .await?
+    .into_iter()
+    .map(FrontendTokenRefreshData::convert)
+    .collect();
 
     let data = json!({
         "account": reddit_account,
+        "token_refreshes": token_refreshes,
     });
 
-    let whole_document = local_hb.render("whole_document", &data)?;
+    let whole_document = state.render_page("reddit_account", &data)?;
 
     Ok(Html(whole_document))
 }
@@ -269,21 +447,33 @@ async fn subscription_account_page(
     Uuid::try_parse(&subscription_account_id)
         .map_err(|_| ApiError::BadRequest("Invalid ID".into()))?;
 
-    let mut local_hb = state.hb.clone();
-
     let subscription = get_subscription_by_id(&state.db_pool, &subscription_account_id)
         .await
         .map_err(|_| ApiError::NotFound("Subscription doesn't exist".into()))?;
 
     let subscription = FrontendSubscriptionData::convert(&subscription)?;
 
-    local_hb.register_template_file("body_content", "frontend/subscription.html")?;
+    let submissions_total =
+        count_submissions_for_subscription(&state.db_pool, &subscription_account_id).await?;
+
+    let submissions: Vec<FrontendSubmissionData> = fetch_submissions_for_subscription(
+        &state.db_pool,
+        &subscription_account_id,
+        SUBSCRIPTION_PAGE_SUBMISSIONS,
+        0,
+    )
+    .await?
+    .into_iter()
+    .map(FrontendSubmissionData::convert)
+    .collect();
 
     let data = json!({
         "subscription": subscription,
+        "submissions": submissions,
+        "submissions_total": submissions_total,
     });
 
-    let whole_document = local_hb.render("whole_document", &data)?;
+    let whole_document = state.render_page("subscription", &data)?;
 
     Ok(Html(whole_document))
 }
@@ -306,21 +496,17 @@ async fn subreddit_page(
     State(state): State<Arc<AppState>>,
     Path(subreddit_id): Path<i64>,
 ) -> Result<Html<String>, ApiError> {
-    let mut local_hb = state.hb.clone();
-
     let subreddit = get_subreddit_by_id(&state.db_pool, &subreddit_id)
-        .await
-        .map_err(|_| ApiError::NotFound("Subreddit doesn't exist".into()))?;
+        .await?
+        .ok_or(ApiError::NotFound("Subreddit doesn't exist".into()))?;
 
     let subreddit = FrontendSubredditData::convert(&subreddit)?;
 
-    local_hb.register_template_file("body_content", "frontend/subreddit.html")?;
-
     let data = json!({
         "subreddit": subreddit,
     });
 
-    let whole_document = local_hb.render("whole_document", &data)?;
+    let whole_document = state.render_page("subreddit", &data)?;
 
     Ok(Html(whole_document))
 }
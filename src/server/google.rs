@@ -1,37 +1,60 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use axum::{
-    extract::{Path, Query, State},
-    http::HeaderMap,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, Uri, header},
 };
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use hmac::{Hmac, Mac, digest::crypto_common};
+use metrics::{counter, histogram};
 
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    infrastructure::AppState,
+    infrastructure::{AppState, telemetry},
     server::{
-        ApiError, SubCommand,
+        ApiError, ErrorResponse, RESUBSCRIBE_EARLY_SECS, SubCommand,
+        alerts::send_failure_alert,
         reddit::{
-            get_associated_reddit_accounts_for_subscription, moderate_submission,
-            submit_video_to_subreddit,
+            PERMANENT_SUBMISSION_ERRORS, TRANSIENT_SUBMISSION_ERRORS, comment_on_submission,
+            comment_video_description, get_associated_reddit_accounts_for_subscription,
+            get_reddit_account, handle_previous_reddit_submissions, moderate_submission,
+            reddit_account_meets_subreddit_requirements, submit_video_to_subreddit,
         },
         repository::{
-            fetch_form_data, fetch_subreddits_for_reddit_account, get_subscription_details,
-            handle_youtube_subscription, save_reddit_submission, update_youtube_subscription,
+            FailedSubmission, Subscription, claim_subscription_post, claim_video_submission,
+            delete_failed_submission, disable_subreddit_posting, fetch_failed_submission,
+            fetch_feed_event, fetch_form_data, fetch_last_seen_update, fetch_submission_for_video,
+            fetch_subreddits_for_reddit_account, fetch_subscription_link_moderation,
+            fetch_subscriptions, get_subreddit_by_id, get_subscription_details,
+            handle_youtube_subscription, record_failed_submission, release_video_submission,
+            save_feed_event, save_reddit_submission, update_failed_submission_status,
+            update_last_seen_update, update_subscription_channel_name, update_youtube_subscription,
             video_already_submitted_to_subreddit,
         },
         shared::{
-            Author, Feed, HTTP_CLIENT, SimpleEntry, Verification, VerificationMode,
-            YouTubeSubscription, extract_channel_id_from_topic_url,
+            Author, CachedChannel, CrosspostSettings, DescriptionComment, Entry,
+            FailedSubmissionStatus, Feed, FeedNotification, HTTP_CLIENT, PendingSubmission,
+            PendingVerification, RedditAccount, SimpleEntry, Subreddit, TitleFilters, Verification,
+            VerificationMode, YouTubeSubscription, extract_channel_id_from_topic_url,
+            hub_test_mode, youtube_topic_url,
+        },
+        youtube::{
+            enrich_entry, fetch_channel_title, fetch_channel_uploads_in_range,
+            fetch_video_details_for_entry, skip_region_blocked_video, skip_short_video,
+            video_duration_seconds,
         },
     },
 };
 
+/// A feed notification is a single Atom entry, so anything larger is refused before it's read or its signature checked.
+const FEED_BODY_LIMIT_BYTES: usize = 256 * 1024;
+
 pub fn router() -> OpenApiRouter<Arc<AppState>> {
     OpenApiRouter::new()
         .routes(routes!(new_video_published))
+        // Only applies to the routes above it.
+        .layer(DefaultBodyLimit::max(FEED_BODY_LIMIT_BYTES))
         .routes(routes!(subscription_verification))
 }
 
@@ -58,8 +81,12 @@ impl From<axum::http::header::ToStrError> for ApiError {
 
 type HmacSha1 = Hmac<sha1::Sha1>;
 
-impl Feed {
-    fn validate(hmac_secret: &String, headers: HeaderMap, body: String) -> Result<Feed, ApiError> {
+impl FeedNotification {
+    fn validate(
+        hmac_secret: &String,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Result<FeedNotification, ApiError> {
         match headers.get("X-Hub-Signature") {
             Some(signature) => {
                 let signature = if let Some(("sha1", hash)) = signature.to_str()?.split_once('=') {
@@ -86,19 +113,21 @@ impl Feed {
                 let hash_string = format!("{:x}", hash.into_bytes()); // format the bytes to a lowercase hex string
 
                 if signature.ne(&hash_string) {
+                    counter!(telemetry::SIGNATURE_FAILURES).increment(1);
                     return Err(ApiError::BadRequest(
                         "The signature in the header does not match the calculated signature"
                             .to_string(),
                     ));
                 }
 
-                let feed: Feed = quick_xml::de::from_str(&body)?;
-
-                Ok(feed)
+                Ok(FeedNotification::parse(body)?)
+            }
+            None => {
+                counter!(telemetry::SIGNATURE_FAILURES).increment(1);
+                Err(ApiError::BadRequest(
+                    "The new video request has no X-Hub-Signature header.".to_string(),
+                ))
             }
-            None => Err(ApiError::BadRequest(
-                "The new video request has no X-Hub-Signature header.".to_string(),
-            )),
         }
     }
 }
@@ -108,15 +137,16 @@ impl Feed {
         post,
         path = "/subscription/{id}",
         request_body(content = Feed, description = "Google PubSubHubbub XML request", content_type = "application/atom+xml"),
-        description = "New video published request from Google PubSubHubbub",
+        description = "New video published request from Google PubSubHubbub. Hubs verifying with a POST instead of a GET send the hub.* parameters in the query or as a form body, they're answered like the GET verification",
         params(
             ("X-Hub-Signature" = String, Header, description = "Google PubSubHubbub HMAC signature for the request body in the form of \"sha1=signature\" where the signature is a 40-byte, hexadecimal representation of a SHA1 signature. Source https://pubsubhubbub.github.io/PubSubHubbub/pubsubhubbub-core-0.4.html#rfc.section.8", example = "sha1=e7667dbb6b9dc356ac8dd767560926d5403be497"),
             ("id" = String, Path, description = "Subscription id", example = "019ba504-70f5-7f35-9c2c-2f02b992af7e")
         ),
         responses(
-            (status = 200, description = "Successful request."),
-            (status = 400, description = "Bad request, possible malformed XML or X-Hub-Signature header."),
-            (status = 404, description = "Subscription doesn't exists."),
+            (status = 200, description = "Successful request, the challenge string for a verification.", body = String),
+            (status = 400, description = "Bad request, possible malformed XML or X-Hub-Signature header.", body = ErrorResponse),
+            (status = 404, description = "Subscription doesn't exists.", body = ErrorResponse),
+            (status = 413, description = "The body is larger than 256 KB."),
         ),
         tag = "google"
     )]
@@ -124,28 +154,130 @@ impl Feed {
 async fn new_video_published(
     State(state): State<Arc<AppState>>,
     Path(subscription_id): Path<String>,
+    uri: Uri,
     headers: HeaderMap,
     body: String,
-) -> Result<(), ApiError> {
+) -> Result<String, ApiError> {
+    // Checked before the subscription, a first subscribe is verified before the subscription is stored.
+    if let Some(verification) = post_verification(&uri, &headers, &body)? {
+        return verify_subscription(&state, &subscription_id, verification).await;
+    }
+
+    // Subscriptions are only deleted once the hub verified the unsubscribe, so the 404 only answers notifications still in flight.
     let subscription = get_subscription_details(&state.db_pool, &subscription_id)
         .await?
-        .ok_or(ApiError::BadRequest(format!(
+        .ok_or(ApiError::NotFound(format!(
             "No subscription found for subscription id: {}",
             subscription_id
         )))?;
 
-    let feed = Feed::validate(&subscription.hmac_secret, headers, body)?;
+    counter!(telemetry::FEEDS_RECEIVED).increment(1);
 
-    let simple_entry = match Into::<Option<SimpleEntry>>::into(&feed.entry) {
-        Some(entry) => entry,
-        None => {
-            return Err(ApiError::InternalError(format!(
-                "Couldn't create SimpleEntry from following Feed: {:?}",
-                &feed
-            )));
+    let feed = match FeedNotification::validate(&subscription.hmac_secret, &headers, &body)? {
+        FeedNotification::NewEntry(feed) => feed,
+        FeedNotification::DeletedEntry(deleted_feed) => {
+            // Answered with 200 so the hub doesn't keep retrying, the Reddit submissions are kept.
+            let deleted_entry = deleted_feed.deleted_entry;
+            println!(
+                "The video https://www.youtube.com/watch?v={} of the subscription {} was deleted or made private at {}{}, ignoring it.",
+                deleted_entry.yt_video_id(),
+                subscription.id,
+                deleted_entry.when,
+                deleted_entry
+                    .by
+                    .as_ref()
+                    .map(|author| format!(" by '{}'", author.name))
+                    .unwrap_or_default()
+            );
+            return Ok(String::new());
+        }
+    };
+
+    let signature = headers
+        .get("X-Hub-Signature")
+        .map(|signature| signature.to_str())
+        .transpose()?
+        .map(|signature| signature.to_string());
+
+    let event_id = save_feed_event(
+        &state.db_pool,
+        &subscription.id,
+        &Utc::now().timestamp(),
+        &signature,
+        &body,
+    )
+    .await?;
+
+    println!("Stored the video request as feed event: {}", event_id);
+
+    let mut simple_entries = to_simple_entries(&feed)?;
+
+    // Oldest first, so a newer entry in the same batch doesn't get the older ones rejected as replays.
+    simple_entries.sort_by_key(|simple_entry| simple_entry.updated);
+
+    // Every entry is handled even if one fails, the first error is returned afterwards.
+    let mut first_error = None;
+    for simple_entry in &simple_entries {
+        if let Err(e) = handle_feed_entry(&state, &subscription, simple_entry).await {
+            eprintln!(
+                "Handling the video request (title: '{}' link: {}) failed: {}",
+                simple_entry.title, simple_entry.link.href, e
+            );
+            first_error.get_or_insert(e);
         }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(String::new()),
+    }
+}
+
+/// The hub.* parameters of a POST verification, from the query or a form body. Feed notifications
+/// are Atom XML and never carry a `hub.challenge`, so they're left to the feed handling.
+fn post_verification(
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<Option<Verification>, ApiError> {
+    let has_challenge = |query: &str| {
+        query
+            .split('&')
+            .any(|pair| pair.starts_with("hub.challenge="))
     };
 
+    if uri.query().is_some_and(has_challenge) {
+        return Query::<Verification>::try_from_uri(uri)
+            .map(|Query(verification)| Some(verification))
+            .map_err(|e| ApiError::BadRequest(format!("Invalid hub verification query: {}", e)));
+    }
+
+    let is_form_body = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"));
+
+    if is_form_body && has_challenge(body.trim()) {
+        // A form body is encoded like a query string, so it's parsed as one.
+        let body_uri: Uri = format!("/?{}", body.trim()).parse().map_err(|e| {
+            ApiError::BadRequest(format!("Invalid hub verification form body: {}", e))
+        })?;
+
+        return Query::<Verification>::try_from_uri(&body_uri)
+            .map(|Query(verification)| Some(verification))
+            .map_err(|e| {
+                ApiError::BadRequest(format!("Invalid hub verification form body: {}", e))
+            });
+    }
+
+    Ok(None)
+}
+
+async fn handle_feed_entry(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    simple_entry: &SimpleEntry,
+) -> Result<(), ApiError> {
     println!(
         "Received video request (title: '{}' link: {}) published from '{}' (link: {})",
         simple_entry.title,
@@ -154,6 +286,19 @@ async fn new_video_published(
         simple_entry.author.uri
     );
 
+    // A validly signed payload can be replayed, so entries older than the newest processed one are rejected.
+    let entry_updated = simple_entry.updated.timestamp();
+    if let Some(last_seen_update) = fetch_last_seen_update(&state.db_pool, &subscription.id).await?
+        && entry_updated < last_seen_update - state.feed_replay_window_secs
+    {
+        return Err(ApiError::BadRequest(format!(
+            "The video request (title: '{}' link: {}) was updated at {}, which is older than the newest processed update at {}, possible replay.",
+            simple_entry.title, simple_entry.link.href, simple_entry.updated, last_seen_update
+        )));
+    }
+
+    update_last_seen_update(&state.db_pool, &subscription.id, &entry_updated).await?;
+
     let published_diff = (simple_entry.updated - simple_entry.published).num_seconds();
     if published_diff > 60 {
         println!(
@@ -163,20 +308,249 @@ async fn new_video_published(
         return Ok(());
     }
 
-    // Shorts are only posted when the user has explicitly set post_shorts to true.
-    if simple_entry.link.href.contains("shorts") && !subscription.post_shorts {
+    if defer_rapid_post(state, subscription, simple_entry).await? {
+        return Ok(());
+    }
+
+    submit_entry_for_subscription(state, subscription, simple_entry).await?;
+
+    Ok(())
+}
+
+/// Hands the entry to the scheduler when the subscription posted less than its minimum interval ago,
+/// so a channel uploading several videos at once doesn't flood the subreddits. Returns true if it was deferred.
+async fn defer_rapid_post(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    simple_entry: &SimpleEntry,
+) -> Result<bool, ApiError> {
+    let min_post_interval_seconds = subscription
+        .min_post_interval_seconds
+        .unwrap_or(state.min_post_interval_secs);
+
+    if min_post_interval_seconds <= 0 {
+        return Ok(false);
+    }
+
+    let Some(wait_secs) = claim_subscription_post(
+        &state.db_pool,
+        &subscription.id,
+        &Utc::now().timestamp(),
+        &min_post_interval_seconds,
+    )
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    println!(
+        "The subscription {} posted less than {} seconds ago, deferring the video (title: '{}' link: {}) by {} seconds.",
+        subscription.id,
+        min_post_interval_seconds,
+        simple_entry.title,
+        simple_entry.link.href,
+        wait_secs
+    );
+
+    let _ = state
+        .scheduler_sender
+        .send(SubCommand::DeferEntry {
+            subscription_id: subscription.id.clone(),
+            entry: Box::new(simple_entry.clone()),
+            wait_secs,
+        })
+        .await;
+
+    Ok(true)
+}
+
+/// Submits an entry deferred by the minimum post interval, called by the scheduler once the wait is over.
+pub async fn submit_deferred_entry(
+    state: &Arc<AppState>,
+    subscription_id: &String,
+    simple_entry: &SimpleEntry,
+) -> Result<(), ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subscription found for id: {}",
+            subscription_id
+        )))?;
+
+    // Another deferred entry can have taken the slot, then this one waits for the next.
+    if defer_rapid_post(state, &subscription, simple_entry).await? {
         return Ok(());
     }
 
+    submit_entry_for_subscription(state, &subscription, simple_entry).await?;
+
+    Ok(())
+}
+
+fn to_simple_entries(feed: &Feed) -> Result<Vec<SimpleEntry>, ApiError> {
+    if feed.entries.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "The following Feed has no entries: {:?}",
+            feed
+        )));
+    }
+
+    feed.entries
+        .iter()
+        .map(|entry| match Into::<Option<SimpleEntry>>::into(entry) {
+            Some(entry) => Ok(entry),
+            None => Err(ApiError::InternalError(format!(
+                "Couldn't create SimpleEntry from following Feed: {:?}",
+                feed
+            ))),
+        })
+        .collect()
+}
+
+/// Runs the submission logic again for a stored feed event. The signature was checked
+/// when the event was received, and the replay and update checks are skipped on purpose.
+pub async fn replay_feed_event(state: &Arc<AppState>, event_id: i64) -> Result<(), ApiError> {
+    let feed_event =
+        fetch_feed_event(&state.db_pool, &event_id)
+            .await?
+            .ok_or(ApiError::NotFound(format!(
+                "No feed event found for id: {}",
+                event_id
+            )))?;
+
+    let subscription = get_subscription_details(&state.db_pool, &feed_event.subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subscription found for subscription id: {}",
+            feed_event.subscription_id
+        )))?;
+
+    let feed: Feed = quick_xml::de::from_str(&feed_event.body)?;
+
+    for simple_entry in to_simple_entries(&feed)? {
+        println!(
+            "Replaying the feed event {} received at {} (signature: {}): video (title: '{}' link: {}) published from '{}'",
+            feed_event.id,
+            feed_event.received_at,
+            feed_event.signature.as_deref().unwrap_or("none"),
+            simple_entry.title,
+            simple_entry.link.href,
+            simple_entry.author.name
+        );
+
+        submit_entry_for_subscription(state, &subscription, &simple_entry).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns true if the subscription's title filters rule out the video.
+fn skip_filtered_title(subscription: &Subscription, simple_entry: &SimpleEntry) -> bool {
+    let Some(title_filters) = &subscription.title_filters else {
+        return false;
+    };
+
+    let title_filters: TitleFilters = match serde_json::from_str(title_filters) {
+        Ok(title_filters) => title_filters,
+        Err(e) => {
+            eprintln!(
+                "Could not parse the title filters of the subscription {}, submitting the video anyway: {}",
+                subscription.id, e
+            );
+            return false;
+        }
+    };
+
+    match title_filters.skip_reason(&simple_entry.title) {
+        Some(reason) => {
+            println!(
+                "Skipping the video (title: '{}' link: {}) as {}.",
+                simple_entry.title, simple_entry.link.href, reason
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+/// Submits the entry to the subreddits linked to the subscription, subreddits the video
+/// has already been submitted to are skipped. Each subreddit is submitted to on its own, a failed
+/// one doesn't stop the others. Returns the number of subreddits it was submitted to, or the first
+/// error when every subreddit failed.
+pub async fn submit_entry_for_subscription(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    simple_entry: &SimpleEntry,
+) -> Result<usize, ApiError> {
+    // Shorts are only posted when the user has explicitly set post_shorts to true. Without a YouTube
+    // API key only the link tells them apart, with one the video's duration is checked below too.
+    if simple_entry.link.href.contains("shorts") && !subscription.post_shorts {
+        return Ok(0);
+    }
+
+    if skip_filtered_title(subscription, simple_entry) {
+        return Ok(0);
+    }
+
+    // Keeps a new subscription from flooding the subreddits with the channel's older videos.
+    if let Some(posting_cutoff) = subscription.posting_cutoff
+        && simple_entry.published.timestamp() < posting_cutoff
+    {
+        println!(
+            "Skipping the video (title: '{}' link: {}) as it was published at {}, before the subscription's posting cutoff at {}.",
+            simple_entry.title, simple_entry.link.href, simple_entry.published, posting_cutoff
+        );
+        return Ok(0);
+    }
+
+    // The YouTube enrichment and checks are best-effort, a YouTube API failure shouldn't stop the submission.
+    let details = fetch_video_details_for_entry(state, &simple_entry.yt_video_id)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "Could not fetch the YouTube details for the video (title: '{}' link: {}): {}",
+                simple_entry.title, simple_entry.link.href, e
+            );
+            None
+        });
+
+    let simple_entry = &enrich_entry(simple_entry, details.as_ref());
+
+    let duration_seconds =
+        video_duration_seconds(state, &simple_entry.yt_video_id, details.as_ref())
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Could not get the duration of the video (title: '{}' link: {}): {}",
+                    simple_entry.title, simple_entry.link.href, e
+                );
+                None
+            });
+
+    if skip_short_video(subscription, &simple_entry.yt_video_id, duration_seconds) {
+        return Ok(0);
+    }
+
+    if let Some(details) = &details {
+        match skip_region_blocked_video(state, &simple_entry.yt_video_id, details).await {
+            Ok(true) => return Ok(0),
+            Ok(false) => {}
+            Err(e) => eprintln!(
+                "Could not check the region restriction for the video (title: '{}' link: {}): {}",
+                simple_entry.title, simple_entry.link.href, e
+            ),
+        }
+    }
+
     let subscription_reddit_accounts =
-        get_associated_reddit_accounts_for_subscription(&state, &subscription.id).await?;
+        get_associated_reddit_accounts_for_subscription(state, &subscription.id).await?;
 
     if subscription_reddit_accounts.is_empty() {
         println!(
             "The subscription: {} has no associated Reddit accounts to use for submit the video (title: '{}' link: {})",
-            subscription_id, simple_entry.title, simple_entry.link.href
+            subscription.id, simple_entry.title, simple_entry.link.href
         );
-        return Ok(());
+        return Ok(0);
     }
 
     println!(
@@ -185,9 +559,28 @@ async fn new_video_published(
         subscription.id
     );
 
+    let mut submitted = 0;
+    let mut failures: Vec<(String, ApiError)> = Vec::new();
     for reddit_account in subscription_reddit_accounts {
-        let reddit_account_subreddits =
-            fetch_subreddits_for_reddit_account(&state.db_pool, &reddit_account.id).await?;
+        let reddit_account_subreddits = match fetch_subreddits_for_reddit_account(
+            &state.db_pool,
+            &subscription.id,
+            &reddit_account.id,
+        )
+        .await
+        {
+            Ok(reddit_account_subreddits) => reddit_account_subreddits,
+            Err(e) => {
+                failures.push((
+                    format!(
+                        "the subreddits of https://www.reddit.com/user/{}",
+                        reddit_account.username
+                    ),
+                    e,
+                ));
+                continue;
+            }
+        };
 
         if reddit_account_subreddits.is_empty() {
             println!(
@@ -203,52 +596,681 @@ async fn new_video_published(
             reddit_account.username
         );
 
+        // The primary subreddit goes first so its submission exists before it's crossposted.
+        let mut reddit_account_subreddits = reddit_account_subreddits;
+        if let Some(crossposting) = crosspost_settings(subscription) {
+            reddit_account_subreddits
+                .sort_by_key(|subreddit| Some(subreddit.id) != crossposting.primary_subreddit_id);
+        }
+
         for subreddit in reddit_account_subreddits {
-            if video_already_submitted_to_subreddit(
-                &state.db_pool,
-                &subreddit.id,
-                &simple_entry.yt_video_id,
+            match submit_entry_to_subreddit(
+                state,
+                subscription,
+                &reddit_account,
+                &subreddit,
+                simple_entry,
+                0,
             )
-            .await?
+            .await
             {
-                println!(
-                    "The video (title: '{}' link: {}) has been already submitted to the https://reddit.com/r/{} subreddit.",
-                    simple_entry.title, simple_entry.link.href, subreddit.name,
-                );
-                continue;
+                Ok(true) => submitted += 1,
+                Ok(false) => {}
+                Err(e) => failures.push((
+                    format!(
+                        "https://reddit.com/r/{} with https://www.reddit.com/user/{}",
+                        subreddit.name, reddit_account.username
+                    ),
+                    e,
+                )),
             }
+        }
+    }
+
+    for (target, e) in &failures {
+        eprintln!(
+            "Submitting the video (title: '{}' link: {}) to {} failed: {}",
+            simple_entry.title, simple_entry.link.href, target, e
+        );
+    }
+
+    // Only a video that went nowhere is reported as failed, a partial submission isn't redone.
+    if submitted == 0
+        && let Some((_, e)) = failures.into_iter().next()
+    {
+        return Err(e);
+    }
+
+    Ok(submitted)
+}
+
+/// The subscription's crosspost settings, `None` when every subreddit gets a link post.
+fn crosspost_settings(subscription: &Subscription) -> Option<CrosspostSettings> {
+    let crossposting = subscription.crossposting.as_ref()?;
+
+    serde_json::from_str(crossposting)
+        .inspect_err(|e| {
+            eprintln!(
+                "Could not parse the crosspost settings of the subscription {}, submitting links instead: {}",
+                subscription.id, e
+            )
+        })
+        .ok()
+}
+
+/// The subscription's description comment settings, `None` when the description isn't commented.
+fn description_comment_settings(subscription: &Subscription) -> Option<DescriptionComment> {
+    let description_comment = subscription.description_comment.as_ref()?;
 
+    serde_json::from_str(description_comment)
+        .inspect_err(|e| {
+            eprintln!(
+                "Could not parse the description comment settings of the subscription {}, not commenting the description: {}",
+                subscription.id, e
+            )
+        })
+        .ok()
+}
+
+/// The fullname of the primary subreddit's submission when the subreddit crossposts it.
+async fn crosspost_fullname(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    subreddit: &Subreddit,
+    simple_entry: &SimpleEntry,
+) -> Result<Option<String>, ApiError> {
+    let Some(primary_subreddit_id) = crosspost_settings(subscription)
+        .and_then(|crossposting| crossposting.crosspost_source(subreddit.id))
+    else {
+        return Ok(None);
+    };
+
+    let submission = fetch_submission_for_video(
+        &state.db_pool,
+        &primary_subreddit_id,
+        &simple_entry.yt_video_id,
+    )
+    .await?;
+
+    if submission.is_none() {
+        println!(
+            "The video (title: '{}' link: {}) has no submission in the primary subreddit to crosspost, submitting it as a link to the https://reddit.com/r/{} subreddit.",
+            simple_entry.title, simple_entry.link.href, subreddit.name
+        );
+    }
+
+    Ok(submission)
+}
+
+const MAX_SUBMISSION_RETRIES: u32 = 3;
+/// Attempts of a failed submission, including the first one, before it's marked as failed.
+const MAX_FAILED_SUBMISSION_ATTEMPTS: i64 = 5;
+/// The wait before the first retry of a failed submission, doubled for each retry after it.
+const FAILED_SUBMISSION_BACKOFF_SECS: i64 = 5 * 60;
+/// A submission takes seconds, an older claim is from a submission that never finished.
+const SUBMISSION_CLAIM_STALE_SECS: i64 = 10 * 60;
+
+/// Submits the entry to one subreddit, a rate limited submission is handed to the scheduler
+/// to retry later, `attempt` counts the retries already made. Other submissions that fail for a
+/// reason that can pass are stored as failed submissions to retry. Returns true if it was submitted.
+async fn submit_entry_to_subreddit(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+    simple_entry: &SimpleEntry,
+    attempt: u32,
+) -> Result<bool, ApiError> {
+    if let Some(reason) = &subreddit.posting_disabled_reason {
+        println!(
+            "Skipping the video (title: '{}' link: {}), posting to the https://reddit.com/r/{} subreddit is disabled: {}",
+            simple_entry.title, simple_entry.link.href, subreddit.name, reason,
+        );
+        return Ok(false);
+    }
+
+    if video_already_submitted_to_subreddit(
+        &state.db_pool,
+        &subreddit.id,
+        &simple_entry.yt_video_id,
+    )
+    .await?
+    {
+        println!(
+            "The video (title: '{}' link: {}) has been already submitted to the https://reddit.com/r/{} subreddit.",
+            simple_entry.title, simple_entry.link.href, subreddit.name,
+        );
+        return Ok(false);
+    }
+
+    // Like the region check this is best-effort, Reddit itself is the final judge.
+    match reddit_account_meets_subreddit_requirements(state, reddit_account, subreddit).await {
+        Ok(true) => {}
+        Ok(false) => {
             println!(
-                "Now submitting the new video (title: '{}' link: {}) to the following subreddit: {}",
-                simple_entry.title, simple_entry.link.href, subreddit.name
+                "Skipping the video (title: '{}' link: {}) for the https://reddit.com/r/{} subreddit.",
+                simple_entry.title, simple_entry.link.href, subreddit.name,
             );
+            return Ok(false);
+        }
+        Err(e) => eprintln!(
+            "Could not check the account requirements of the https://reddit.com/r/{} subreddit for https://www.reddit.com/user/{}: {}",
+            subreddit.name, reddit_account.username, e
+        ),
+    }
+
+    println!(
+        "Now submitting the new video (title: '{}' link: {}) to the following subreddit: {}",
+        simple_entry.title, simple_entry.link.href, subreddit.name
+    );
+
+    let crosspost_fullname =
+        crosspost_fullname(state, subscription, subreddit, simple_entry).await?;
+
+    // When several accounts are linked to the subreddit the first one to claim it submits the video.
+    let now = Utc::now().timestamp();
+    if !claim_video_submission(
+        &state.db_pool,
+        &simple_entry.yt_video_id,
+        &subreddit.id,
+        &now,
+        &(now - SUBMISSION_CLAIM_STALE_SECS),
+    )
+    .await?
+    {
+        println!(
+            "The video (title: '{}' link: {}) is already being submitted to the https://reddit.com/r/{} subreddit.",
+            simple_entry.title, simple_entry.link.href, subreddit.name,
+        );
+        return Ok(false);
+    }
 
-            let reddit_submission =
-                submit_video_to_subreddit(&reddit_account, &subreddit, &simple_entry).await?;
+    let submit_started = Instant::now();
+    let reddit_submission = submit_video_to_subreddit(
+        state,
+        reddit_account,
+        subreddit,
+        simple_entry,
+        crosspost_fullname.as_ref(),
+    )
+    .await
+    .inspect(|_| {
+        histogram!(telemetry::REDDIT_SUBMIT_DURATION).record(submit_started.elapsed().as_secs_f64())
+    })
+    .inspect_err(|_| {
+        counter!(telemetry::SUBMISSION_FAILURES, "subreddit" => subreddit.name.clone()).increment(1)
+    });
 
+    if reddit_submission.is_err()
+        && let Err(e) =
+            release_video_submission(&state.db_pool, &simple_entry.yt_video_id, &subreddit.id).await
+    {
+        eprintln!(
+            "Could not release the submission claim of the video {} on the https://reddit.com/r/{} subreddit: {}",
+            simple_entry.yt_video_id, subreddit.name, e
+        );
+    }
+
+    let reddit_submission = match reddit_submission {
+        Ok(reddit_submission) => reddit_submission,
+        Err(ApiError::RateLimited(message, wait_secs)) if attempt < MAX_SUBMISSION_RETRIES => {
             println!(
-                "Reddit submission successful. URL: {}",
-                reddit_submission.url
+                "{}, retrying in {} seconds (retry {} of {}).",
+                message,
+                wait_secs,
+                attempt + 1,
+                MAX_SUBMISSION_RETRIES
             );
 
-            save_reddit_submission(
-                &state.db_pool,
-                &reddit_submission.id,
-                &simple_entry.yt_video_id,
-                &reddit_account.id,
-                &subreddit.id,
-                &Utc::now().timestamp(),
-                &false,
-                Some(&subscription_id),
+            let _ = state
+                .scheduler_sender
+                .send(SubCommand::RetrySubmission {
+                    submission: Box::new(PendingSubmission {
+                        subscription_id: subscription.id.clone(),
+                        reddit_account_id: reddit_account.id.clone(),
+                        subreddit_id: subreddit.id,
+                        entry: simple_entry.clone(),
+                        attempt: attempt + 1,
+                    }),
+                    wait_secs,
+                })
+                .await;
+
+            return Ok(false);
+        }
+        Err(e) if is_transient_submission_error(&e) => {
+            queue_failed_submission(
+                state,
+                subscription,
+                reddit_account,
+                subreddit,
+                simple_entry,
+                &e,
             )
             .await?;
 
-            if reddit_account.moderate_submissions {
-                moderate_submission(&state, &reddit_account, &subreddit).await?;
-            }
+            return Ok(false);
         }
+        Err(e) if is_permanent_submission_error(&e) => {
+            disable_posting_to_subreddit(
+                state,
+                subscription,
+                reddit_account,
+                subreddit,
+                simple_entry,
+                &e,
+            )
+            .await?;
+
+            return Err(e);
+        }
+        Err(e) => {
+            send_failure_alert(
+                state,
+                &format!(
+                    "Submitting the video '{}' ({}) from {} to https://reddit.com/r/{} with https://www.reddit.com/user/{} failed: {}",
+                    simple_entry.title,
+                    simple_entry.link.href,
+                    subscription.channel_name,
+                    subreddit.name,
+                    reddit_account.username,
+                    e
+                ),
+            )
+            .await;
+
+            return Err(e);
+        }
+    };
+
+    counter!(telemetry::SUBMISSIONS_POSTED, "subreddit" => subreddit.name.clone()).increment(1);
+
+    println!(
+        "Reddit submission successful. URL: {}",
+        reddit_submission.url
+    );
+
+    save_reddit_submission(
+        &state.db_pool,
+        &reddit_submission.id,
+        &simple_entry.yt_video_id,
+        &reddit_account.id,
+        &subreddit.id,
+        &Utc::now().timestamp(),
+        &false,
+        Some(&subscription.id),
+    )
+    .await?;
+
+    // The video is already submitted, so a failed comment is only logged.
+    if let Err(e) =
+        comment_on_submission(reddit_account, subreddit, &reddit_submission, simple_entry).await
+    {
+        eprintln!(
+            "Could not comment on the submission {}: {}",
+            reddit_submission.url, e
+        );
+    }
+
+    if let Some(description_comment) = description_comment_settings(subscription)
+        && let Err(e) = comment_video_description(
+            state,
+            reddit_account,
+            &reddit_submission,
+            simple_entry,
+            &description_comment,
+        )
+        .await
+    {
+        eprintln!(
+            "Could not comment the video description on the submission {}: {}",
+            reddit_submission.url, e
+        );
+    }
+
+    // The link's override lets an account moderate only some of the subreddits it submits to.
+    let moderate_submissions = fetch_subscription_link_moderation(
+        &state.db_pool,
+        &subscription.id,
+        &reddit_account.id,
+        &subreddit.id,
+    )
+    .await?
+    .unwrap_or(reddit_account.moderate_submissions);
+
+    // Like the comments, the video is already submitted, so a failed moderation is only logged.
+    if moderate_submissions
+        && let Err(e) = moderate_submission(state, reddit_account, subreddit).await
+    {
+        eprintln!(
+            "Could not moderate the submission {}: {}",
+            reddit_submission.url, e
+        );
+    }
+
+    Ok(true)
+}
+
+/// Reddit being down, timeouts and rate limits can pass, a rejected submission usually won't.
+fn is_transient_submission_error(error: &ApiError) -> bool {
+    match error {
+        ApiError::InternalError(_) | ApiError::DatabaseBusy(_) | ApiError::RateLimited(..) => true,
+        ApiError::RedditRejected(code, _) => TRANSIENT_SUBMISSION_ERRORS.contains(&code.as_str()),
+        _ => false,
+    }
+}
+
+/// The account not being allowed to submit to the subreddit, retrying only repeats the failure.
+fn is_permanent_submission_error(error: &ApiError) -> bool {
+    error
+        .reddit_error_code()
+        .is_some_and(|code| PERMANENT_SUBMISSION_ERRORS.contains(&code.as_str()))
+}
+
+/// Stops the submissions to the subreddit until the reason is cleared, records the failed
+/// submission with Reddit's error code and sends an alert.
+async fn disable_posting_to_subreddit(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+    simple_entry: &SimpleEntry,
+    error: &ApiError,
+) -> Result<(), ApiError> {
+    disable_subreddit_posting(&state.db_pool, &subreddit.id, &error.to_string()).await?;
+
+    let (failed_submission_id, _) = record_failed_submission(
+        &state.db_pool,
+        &subscription.id,
+        &reddit_account.id,
+        &subreddit.id,
+        &simple_entry.yt_video_id,
+        &serde_json::to_string(simple_entry)?,
+        error,
+    )
+    .await?;
+
+    update_failed_submission_status(
+        &state.db_pool,
+        &failed_submission_id,
+        FailedSubmissionStatus::Failed,
+        None,
+    )
+    .await?;
+
+    eprintln!(
+        "Disabled posting to the https://reddit.com/r/{} subreddit: {}",
+        subreddit.name, error
+    );
+
+    send_failure_alert(
+        state,
+        &format!(
+            "Submitting the video '{}' ({}) from {} to https://reddit.com/r/{} with https://www.reddit.com/user/{} failed, posting to the subreddit is disabled until the reason is cleared with PATCH /api/subreddits/{}: {}",
+            simple_entry.title,
+            simple_entry.link.href,
+            subscription.channel_name,
+            subreddit.name,
+            reddit_account.username,
+            subreddit.id,
+            error
+        ),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Stores the failed submission and queues its retry, the wait doubles with each attempt.
+/// Once the attempts run out it's marked as failed and an alert is sent.
+async fn queue_failed_submission(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+    simple_entry: &SimpleEntry,
+    error: &ApiError,
+) -> Result<(), ApiError> {
+    let (failed_submission_id, attempts) = record_failed_submission(
+        &state.db_pool,
+        &subscription.id,
+        &reddit_account.id,
+        &subreddit.id,
+        &simple_entry.yt_video_id,
+        &serde_json::to_string(simple_entry)?,
+        error,
+    )
+    .await?;
+
+    if attempts >= MAX_FAILED_SUBMISSION_ATTEMPTS {
+        update_failed_submission_status(
+            &state.db_pool,
+            &failed_submission_id,
+            FailedSubmissionStatus::Failed,
+            None,
+        )
+        .await?;
+
+        send_failure_alert(
+            state,
+            &format!(
+                "Submitting the video '{}' ({}) from {} to https://reddit.com/r/{} with https://www.reddit.com/user/{} failed {} times, giving up: {}",
+                simple_entry.title,
+                simple_entry.link.href,
+                subscription.channel_name,
+                subreddit.name,
+                reddit_account.username,
+                attempts,
+                error
+            ),
+        )
+        .await;
+
+        return Ok(());
     }
 
+    let wait_secs = FAILED_SUBMISSION_BACKOFF_SECS << (attempts - 1);
+    update_failed_submission_status(
+        &state.db_pool,
+        &failed_submission_id,
+        FailedSubmissionStatus::Pending,
+        Some(Utc::now().timestamp() + wait_secs),
+    )
+    .await?;
+
+    eprintln!(
+        "Submitting the video {} to the https://reddit.com/r/{} subreddit failed, retrying in {} seconds (attempt {} of {}): {}",
+        simple_entry.yt_video_id,
+        subreddit.name,
+        wait_secs,
+        attempts + 1,
+        MAX_FAILED_SUBMISSION_ATTEMPTS,
+        error
+    );
+
+    let _ = state
+        .scheduler_sender
+        .send(SubCommand::RetryFailedSubmission {
+            failed_submission_id,
+            wait_secs,
+        })
+        .await;
+
+    Ok(())
+}
+
+/// Retries a stored failed submission, called by the scheduler once the wait is over.
+pub async fn retry_failed_submission(
+    state: &Arc<AppState>,
+    failed_submission_id: i64,
+) -> Result<(), ApiError> {
+    // The row is gone when its subscription, account or subreddit was deleted.
+    let Some(failed_submission) =
+        fetch_failed_submission(&state.db_pool, &failed_submission_id).await?
+    else {
+        return Ok(());
+    };
+
+    if !matches!(failed_submission.status, FailedSubmissionStatus::Pending) {
+        return Ok(());
+    }
+
+    // Only a transient error queues another retry, anything else would leave the row pending for good.
+    if let Err(e) = resubmit_failed_submission(state, &failed_submission).await {
+        update_failed_submission_status(
+            &state.db_pool,
+            &failed_submission_id,
+            FailedSubmissionStatus::Failed,
+            None,
+        )
+        .await?;
+        eprintln!(
+            "The retry of the failed submission {} failed, it won't be retried again: {}",
+            failed_submission_id, e
+        );
+        return Err(e);
+    }
+
+    // Submitted or skipped, unless the retry failed again and counted another attempt.
+    delete_failed_submission(
+        &state.db_pool,
+        &failed_submission_id,
+        &failed_submission.attempts,
+    )
+    .await
+}
+
+async fn resubmit_failed_submission(
+    state: &Arc<AppState>,
+    failed_submission: &FailedSubmission,
+) -> Result<(), ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &failed_submission.subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subscription found for id: {}",
+            failed_submission.subscription_id
+        )))?;
+
+    let reddit_account = get_reddit_account(state, &failed_submission.reddit_account_id).await?;
+    let subreddit = get_subreddit_by_id(&state.db_pool, &failed_submission.subreddit_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subreddit found for id: {}",
+            failed_submission.subreddit_id
+        )))?;
+    let entry: SimpleEntry = serde_json::from_str(&failed_submission.entry)?;
+
+    println!(
+        "Retrying the failed submission of the video {} to the https://reddit.com/r/{} subreddit.",
+        entry.yt_video_id, subreddit.name
+    );
+
+    // Passing the last rate limit retry makes another failure come back to the failed submissions.
+    submit_entry_to_subreddit(
+        state,
+        &subscription,
+        &reddit_account,
+        &subreddit,
+        &entry,
+        MAX_SUBMISSION_RETRIES,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Queues a check that the hub verified the subscribe request, the verification callback
+/// can silently never arrive, e.g. when the callback URL isn't reachable.
+pub async fn schedule_verification_check(
+    state: &Arc<AppState>,
+    subscription_id: &str,
+    channel_id: &str,
+    hmac_secret: &str,
+    attempt: u32,
+) {
+    if hub_test_mode() {
+        verify_subscription_locally(state, subscription_id, channel_id).await;
+        return;
+    }
+
+    let _ = state
+        .scheduler_sender
+        .send(SubCommand::CheckVerification {
+            verification: Box::new(PendingVerification {
+                subscription_id: subscription_id.to_string(),
+                channel_id: channel_id.to_string(),
+                hmac_secret: hmac_secret.to_string(),
+                attempt,
+            }),
+            wait_secs: state.verification_timeout_secs,
+        })
+        .await;
+}
+
+/// The lease the real hub grants by default, used for the subscriptions verified in the hub test mode.
+const HUB_TEST_MODE_LEASE_SECS: i64 = 432000;
+
+/// Runs the hub's verification request against the subscription, so it becomes active without a hub.
+async fn verify_subscription_locally(
+    state: &Arc<AppState>,
+    subscription_id: &str,
+    channel_id: &str,
+) {
+    let verification = Verification {
+        mode: VerificationMode::Subscribe,
+        topic: youtube_topic_url(channel_id),
+        challenge: "hub-test-mode".to_string(),
+        lease_seconds: Some(HUB_TEST_MODE_LEASE_SECS),
+    };
+
+    match subscription_verification(
+        State(state.clone()),
+        Path(subscription_id.to_string()),
+        Query(verification),
+    )
+    .await
+    {
+        Ok(_) => println!(
+            "Hub test mode: verified the subscription {} locally.",
+            subscription_id
+        ),
+        Err(e) => eprintln!(
+            "Hub test mode: could not verify the subscription {} locally: {}",
+            subscription_id, e
+        ),
+    }
+}
+
+/// Retries a rate limited submission, called by the scheduler once the wait is over.
+pub async fn retry_submission(
+    state: &Arc<AppState>,
+    submission: Box<PendingSubmission>,
+) -> Result<(), ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, &submission.subscription_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subscription found for id: {}",
+            submission.subscription_id
+        )))?;
+
+    let reddit_account = get_reddit_account(state, &submission.reddit_account_id).await?;
+    let subreddit = get_subreddit_by_id(&state.db_pool, &submission.subreddit_id)
+        .await?
+        .ok_or(ApiError::NotFound(format!(
+            "No subreddit found for id: {}",
+            submission.subreddit_id
+        )))?;
+
+    submit_entry_to_subreddit(
+        state,
+        &subscription,
+        &reddit_account,
+        &subreddit,
+        &submission.entry,
+        submission.attempt,
+    )
+    .await?;
+
     Ok(())
 }
 
@@ -266,7 +1288,8 @@ async fn new_video_published(
         ),
         responses(
             (status = 200, description = "The challenge string.", body = String),
-            (status = 400, description = "Missing required query arguments."),            
+            (status = 400, description = "Missing required query arguments.", body = ErrorResponse),            
+            (status = 404, description = "The subscription or unsubscribe wasn't requested.", body = ErrorResponse),
         ),
         tag = "google"
     )]
@@ -276,36 +1299,67 @@ async fn subscription_verification(
     Path(subscription_id): Path<String>,
     Query(verification): Query<Verification>,
 ) -> Result<String, ApiError> {
-    let subscription = get_subscription_details(&state.db_pool, &subscription_id).await?;
-    let expires_at = match verification.lease_seconds {
-        Some(wait_secs) => {
-            let buffer = 3600; // 1 hour in seconds to resubscribe early
+    verify_subscription(&state, &subscription_id, verification).await
+}
 
-            // schedule the resubscription
-            let _ = state
-                .scheduler_sender
-                .send(SubCommand::Schedule {
-                    subscription_id: subscription_id.clone(),
-                    wait_secs: (wait_secs - buffer).max(5),
-                })
-                .await;
+/// Handles a hub verification, from the GET handler or a hub verifying with a POST, returns the challenge to echo.
+pub async fn verify_subscription(
+    state: &Arc<AppState>,
+    subscription_id: &String,
+    verification: Verification,
+) -> Result<String, ApiError> {
+    let subscription = get_subscription_details(&state.db_pool, subscription_id).await?;
 
-            Some(Utc::now().timestamp() + wait_secs)
-        }
-        None => None,
-    };
+    // After a remap the old channel's unsubscribe is verified on the same callback,
+    // it only needs to be confirmed, the subscription now belongs to the new channel.
+    if let Some(existing_sub) = &subscription
+        && let VerificationMode::Unsubscribe = verification.mode
+        && extract_channel_id_from_topic_url(&verification.topic)? != existing_sub.channel_id
+    {
+        println!(
+            "Confirmed the Google PubSubHubbub unsubscribe for the old topic of the remapped subscription: {}",
+            verification.topic
+        );
+        return Ok(verification.challenge);
+    }
+
+    // The bot only unsubscribes the old topic of a remap, so any other unsubscribe wasn't asked for
+    // and isn't confirmed, hub.lease_seconds MUST be ignored for unsubscribes either way.
+    if let VerificationMode::Unsubscribe = verification.mode {
+        return Err(ApiError::NotFound(format!(
+            "No unsubscribe was requested for the topic: {}",
+            verification.topic
+        )));
+    }
+
+    let topic_channel_id = extract_channel_id_from_topic_url(&verification.topic)?.to_string();
+
+    let expires_at = verification
+        .lease_seconds
+        .map(|wait_secs| Utc::now().timestamp() + wait_secs);
 
     match subscription {
         Some(existing_sub) => {
+            verify_topic_channel(&topic_channel_id, &existing_sub.channel_id)?;
+
             println!(
                 "Received Google PubSubHubbub resubscription request for YouTube channel: https://www.youtube.com/channel/{}",
                 &existing_sub.channel_id
             );
 
-            update_youtube_subscription(&state.db_pool, &subscription_id, &expires_at).await?;
+            update_youtube_subscription(&state.db_pool, subscription_id, &expires_at).await?;
+
+            // A name that couldn't be fetched before is retried on every renewal.
+            if existing_sub.channel_name.is_empty()
+                || existing_sub.channel_name == existing_sub.channel_id
+            {
+                let channel_name = fetch_channel_name_or_id(state, &existing_sub.channel_id).await;
+                update_subscription_channel_name(&state.db_pool, subscription_id, &channel_name)
+                    .await?;
+            }
         }
         None => {
-            let channel_id = extract_channel_id_from_topic_url(&verification.topic)?.to_string();
+            let channel_id = topic_channel_id;
 
             println!(
                 "Received Google PubSubHubbub subscription verification request for YouTube channel: https://www.youtube.com/channel/{}",
@@ -313,16 +1367,21 @@ async fn subscription_verification(
             );
 
             let subscription_form: YouTubeSubscription =
-                fetch_form_data(&state.db_pool, &subscription_id).await?;
+                fetch_form_data(&state.db_pool, subscription_id).await?;
 
-            let subscription_data = fetch_subscription_data(&channel_id).await?;
+            verify_topic_channel(&channel_id, &subscription_form.channel_id)?;
+
+            let channel_name = match &subscription_form.channel_name {
+                Some(channel_name) => channel_name.clone(),
+                None => fetch_channel_name_or_id(state, &channel_id).await,
+            };
 
             handle_youtube_subscription(
                 &state.db_pool,
-                &subscription_id,
+                subscription_id,
                 &expires_at,
                 &channel_id,
-                &subscription_data.author.name,
+                &channel_name,
                 &verification,
                 &subscription_form,
             )
@@ -332,22 +1391,119 @@ async fn subscription_verification(
         }
     }
 
+    if let Some(wait_secs) = verification.lease_seconds {
+        // schedule the resubscription
+        let _ = state
+            .scheduler_sender
+            .send(SubCommand::Schedule {
+                subscription_id: subscription_id.clone(),
+                wait_secs: (wait_secs - RESUBSCRIBE_EARLY_SECS).max(5),
+            })
+            .await;
+    }
+
     Ok(verification.challenge)
 }
 
+/// The hub has to verify the topic the callback was subscribed to, anything else is refused.
+fn verify_topic_channel(topic_channel_id: &str, expected_channel_id: &str) -> Result<(), ApiError> {
+    if topic_channel_id != expected_channel_id {
+        return Err(ApiError::BadRequest(format!(
+            "The verification topic is for the YouTube channel {}, but the subscription is for the YouTube channel {}",
+            topic_channel_id, expected_channel_id
+        )));
+    }
+
+    Ok(())
+}
+
 #[derive(serde::Deserialize)]
 struct SubscriptionData {
     author: Author,
+    #[serde(rename = "entry", default)]
+    entries: Vec<Entry>,
+}
+
+/// Fetches the channel from the YouTube Data API when a YouTube API key is configured, otherwise from
+/// the channel's public feed. Either also confirms the channel exists.
+pub async fn fetch_channel(
+    state: &AppState,
+    channel_id: &String,
+) -> Result<CachedChannel, ApiError> {
+    if let Some(channel) = state.channels.get(channel_id).await {
+        return Ok(channel);
+    }
+
+    if let Some(api_key) = &state.youtube_api_key {
+        match fetch_channel_title(api_key, channel_id).await {
+            Ok(Some(channel_name)) => {
+                let channel = CachedChannel::new(channel_id, &channel_name);
+                state.channels.insert(channel_id, channel.clone()).await;
+                return Ok(channel);
+            }
+            Ok(None) => {
+                return Err(ApiError::NotFound(format!(
+                    "The YouTube channel https://www.youtube.com/channel/{} doesn't exist",
+                    channel_id
+                )));
+            }
+            Err(e) => eprintln!(
+                "Could not fetch the YouTube channel https://www.youtube.com/channel/{} from the YouTube API, using its feed instead: {}",
+                channel_id, e
+            ),
+        }
+    }
+
+    let subscription_data = fetch_subscription_data(channel_id).await?;
+
+    let channel = CachedChannel::new(channel_id, &subscription_data.author.name);
+    state.channels.insert(channel_id, channel.clone()).await;
+
+    Ok(channel)
 }
 
-async fn fetch_subscription_data(channel_id: &String) -> Result<SubscriptionData, ApiError> {
+/// The channel name, see `fetch_channel`.
+pub async fn fetch_channel_name(state: &AppState, channel_id: &String) -> Result<String, ApiError> {
+    Ok(fetch_channel(state, channel_id).await?.channel_name)
+}
+
+/// The channel name, or the channel id when the feed can't be fetched so the subscription isn't held up.
+pub async fn fetch_channel_name_or_id(state: &AppState, channel_id: &String) -> String {
+    match fetch_channel_name(state, channel_id).await {
+        Ok(channel_name) => channel_name,
+        Err(e) => {
+            eprintln!(
+                "Could not fetch the name of the YouTube channel https://www.youtube.com/channel/{}, using the channel id instead: {}",
+                channel_id, e
+            );
+            channel_id.clone()
+        }
+    }
+}
+
+/// The channel's public feed, which has its latest 15 videos.
+fn channel_feed_url(channel_id: &str) -> String {
+    #[cfg(test)]
+    if let Some(mock_youtube_url) =
+        crate::server::shared::MOCK_YOUTUBE_URL.with_borrow(|url| url.clone())
+    {
+        return format!(
+            "{}/feeds/videos.xml?channel_id={}",
+            mock_youtube_url, channel_id
+        );
+    }
+
+    format!(
+        "https://www.youtube.com/feeds/videos.xml?channel_id={}",
+        channel_id
+    )
+}
+
+async fn fetch_subscription_data(channel_id: &str) -> Result<SubscriptionData, ApiError> {
     let client = &HTTP_CLIENT;
 
     let subscription_data = client
-        .get(format!(
-            "https://www.youtube.com/feeds/videos.xml?channel_id={}",
-            channel_id
-        ))
+        .get(channel_feed_url(channel_id))
         .send()
         .await?
         .text()
@@ -357,3 +1513,706 @@ async fn fetch_subscription_data(channel_id: &String) -> Result<SubscriptionData
 
     Ok(data)
 }
+
+/// Goes through the latest videos in each subscribed channel's feed and submits the ones
+/// published within the catch-up window that weren't submitted, e.g. because of downtime.
+pub async fn catch_up_on_missed_videos(state: Arc<AppState>) -> Result<(), ApiError> {
+    let subscriptions = fetch_subscriptions(&state.db_pool).await?;
+    let oldest_published = Utc::now() - Duration::hours(state.catch_up_max_age_hours);
+
+    println!(
+        "Catching up on videos published since {} for {} subscriptions.",
+        oldest_published,
+        subscriptions.len()
+    );
+
+    for subscription in subscriptions {
+        let channel_feed = match fetch_subscription_data(&subscription.channel_id).await {
+            Ok(channel_feed) => channel_feed,
+            Err(e) => {
+                eprintln!(
+                    "Could not fetch the feed for YouTube channel https://www.youtube.com/channel/{}: {}",
+                    subscription.channel_id, e
+                );
+                continue;
+            }
+        };
+
+        let entries = feed_entries_in_range(
+            &state,
+            &subscription.channel_id,
+            &channel_feed,
+            Some(oldest_published),
+            None,
+        )
+        .await;
+
+        submit_entries_for_subscription(&state, &subscription, &entries).await;
+    }
+
+    println!("Catch-up on missed videos done.");
+
+    Ok(())
+}
+
+/// Submits the channel's videos published within the range. The range replaces the subscription's
+/// posting cutoff. With a YouTube API key the videos come from the channel's uploads playlist,
+/// otherwise from its feed, which only has the latest 15 videos.
+pub async fn backfill_channel(
+    state: &Arc<AppState>,
+    channel_id: &String,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<(), ApiError> {
+    if let (Some(since), Some(until)) = (since, until)
+        && since > until
+    {
+        return Err(ApiError::BadRequest(format!(
+            "The backfill start {} is after its end {}",
+            since, until
+        )));
+    }
+
+    let subscriptions: Vec<Subscription> = fetch_subscriptions(&state.db_pool)
+        .await?
+        .into_iter()
+        .filter(|subscription| &subscription.channel_id == channel_id)
+        .collect();
+
+    if subscriptions.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No subscription found for the YouTube channel: {}",
+            channel_id
+        )));
+    }
+
+    let entries = match &state.youtube_api_key {
+        Some(api_key) => fetch_channel_uploads_in_range(api_key, channel_id, since, until).await?,
+        None => {
+            let channel_feed = fetch_subscription_data(channel_id).await?;
+            check_feed_covers_backfill(&channel_feed, since)?;
+            feed_entries_in_range(state, channel_id, &channel_feed, since, until).await
+        }
+    };
+
+    for subscription in subscriptions {
+        let subscription = Subscription {
+            posting_cutoff: None,
+            ..subscription
+        };
+
+        // The videos already posted within the range, e.g. by hand, are imported so they aren't posted again.
+        for reddit_account in
+            get_associated_reddit_accounts_for_subscription(state, &subscription.id).await?
+        {
+            handle_previous_reddit_submissions(
+                state,
+                &reddit_account.id,
+                &reddit_account.username,
+                &reddit_account.oauth_token,
+                since,
+            )
+            .await?;
+        }
+
+        let submitted = submit_entries_for_subscription(state, &subscription, &entries).await;
+
+        println!(
+            "Backfilled the subscription {} for {}: {} videos were published within the range, {} Reddit submissions were made.",
+            subscription.id,
+            subscription.channel_name,
+            entries.len(),
+            submitted
+        );
+    }
+
+    Ok(())
+}
+
+/// The most entries a channel's feed has.
+const FEED_MAX_ENTRIES: usize = 15;
+
+/// A full feed may be missing videos published at the start of the range, those can only be
+/// backfilled from the uploads playlist.
+fn check_feed_covers_backfill(
+    channel_feed: &SubscriptionData,
+    since: Option<DateTime<Utc>>,
+) -> Result<(), ApiError> {
+    if channel_feed.entries.len() < FEED_MAX_ENTRIES {
+        return Ok(());
+    }
+
+    let Some(oldest_published) = channel_feed
+        .entries
+        .iter()
+        .map(|entry| entry.published)
+        .min()
+    else {
+        return Ok(());
+    };
+
+    if since.is_some_and(|since| since >= oldest_published) {
+        return Ok(());
+    }
+
+    Err(ApiError::BadRequest(format!(
+        "The channel's feed only goes back to {}, set YOUTUBE_API_KEY to backfill videos published before it or start the range after it",
+        oldest_published
+    )))
+}
+
+/// The entries of the channel's feed published within the range.
+async fn feed_entries_in_range(
+    state: &Arc<AppState>,
+    channel_id: &str,
+    channel_feed: &SubscriptionData,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Vec<SimpleEntry> {
+    state
+        .channels
+        .insert(
+            channel_id,
+            CachedChannel::new(channel_id, &channel_feed.author.name),
+        )
+        .await;
+
+    channel_feed
+        .entries
+        .iter()
+        .filter(|entry| since.is_none_or(|since| entry.published >= since))
+        .filter(|entry| until.is_none_or(|until| entry.published <= until))
+        .filter_map(|entry| entry.into())
+        .collect()
+}
+
+/// Submits the entries for the subscription, a failed submission is logged and the next entry
+/// is tried. Returns how many submissions were made.
+async fn submit_entries_for_subscription(
+    state: &Arc<AppState>,
+    subscription: &Subscription,
+    entries: &[SimpleEntry],
+) -> usize {
+    let mut submitted = 0;
+    for simple_entry in entries {
+        match submit_entry_for_subscription(state, subscription, simple_entry).await {
+            Ok(submissions) => submitted += submissions,
+            Err(e) => eprintln!(
+                "Submission failed for the video (title: '{}' link: {}): {}",
+                simple_entry.title, simple_entry.link.href, e
+            ),
+        }
+    }
+
+    submitted
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Uri;
+    use serde_json::json;
+    use uuid::Uuid;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, method, path, query_param},
+    };
+
+    use super::*;
+    use crate::{
+        infrastructure::Settings,
+        server::{
+            repository::{
+                save_form_data, update_subscription_channel, update_subscription_posting_cutoff,
+            },
+            shared::{MOCK_REDDIT_URL, MOCK_YOUTUBE_URL},
+            test_support::{
+                CHANNEL_ID, OLD_CHANNEL_ID, feed_entry, feed_xml, linked_subscription,
+                reddit_account, simple_entry, subreddit, subscription_form, topic_url,
+                verified_subscription,
+            },
+        },
+    };
+
+    fn unsubscribe_query(channel_id: &str) -> Query<Verification> {
+        let uri: Uri = format!(
+            "/?hub.mode=unsubscribe&hub.topic={}&hub.challenge=unsubscribe-challenge",
+            url::form_urlencoded::byte_serialize(topic_url(channel_id).as_bytes())
+                .collect::<String>()
+        )
+        .parse()
+        .unwrap();
+
+        Query::try_from_uri(&uri).unwrap()
+    }
+
+    /// The headers the hub sends with a notification, signed with the test subscriptions' secret.
+    fn signed_headers(body: &str) -> HeaderMap {
+        let mut hasher = HmacSha1::new_from_slice(b"secret").unwrap();
+        hasher.update(body.as_bytes());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Hub-Signature",
+            format!("sha1={:x}", hasher.finalize().into_bytes())
+                .parse()
+                .unwrap(),
+        );
+
+        headers
+    }
+
+    async fn post_feed(
+        state: &Arc<AppState>,
+        subscription_id: &str,
+        body: String,
+    ) -> Result<String, ApiError> {
+        new_video_published(
+            State(state.clone()),
+            Path(subscription_id.to_string()),
+            Uri::from_static("/"),
+            signed_headers(&body),
+            body,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn deleted_entry_notification_is_answered_with_200() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns:at="http://purl.org/atompub/tombstones/1.0" xmlns="http://www.w3.org/2005/Atom"><at:deleted-entry ref="yt:video:video1" when="2026-01-03T10:00:00.552394234+00:00"><link href="https://www.youtube.com/watch?v=video1"/><at:by><name>Channel</name><uri>https://www.youtube.com/channel/{CHANNEL_ID}</uri></at:by></at:deleted-entry></feed>"#
+        );
+
+        let response = post_feed(&state, &subscription_id, body).await;
+
+        // An Ok is answered with 200, so the hub stops retrying.
+        assert_eq!(response.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn notification_for_an_unknown_subscription_is_not_found() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let body = feed_xml(&[feed_entry("video1", "A video", Utc::now())]);
+
+        let error = post_feed(&state, &Uuid::now_v7().to_string(), body)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, ApiError::NotFound(_)), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn submission_sends_its_metrics_to_statsd() {
+        let statsd = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        statsd
+            .set_read_timeout(Some(std::time::Duration::from_secs(2)))
+            .unwrap();
+        // Without buffering every metric is sent as soon as it's recorded.
+        let recorder = telemetry::statsd_builder(&statsd.local_addr().unwrap().to_string())
+            .unwrap()
+            .with_buffer_size(1)
+            .build(Some(telemetry::METRICS_PREFIX))
+            .unwrap();
+        let _recorder_guard = metrics::set_default_local_recorder(&recorder);
+
+        let now = Utc::now();
+        let reddit = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [],
+                    "data": {
+                        "url": "https://www.reddit.com/r/videos/comments/abc/a_video/",
+                        "name": "t3_abc"
+                    }
+                }
+            })))
+            .mount(&reddit)
+            .await;
+        MOCK_REDDIT_URL.set(Some(reddit.uri()));
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, CHANNEL_ID, &reddit_account_id, &subreddit).await;
+        update_subscription_posting_cutoff(
+            &state.db_pool,
+            &subscription_id,
+            &Some((now - Duration::days(1)).timestamp()),
+        )
+        .await
+        .unwrap();
+
+        let body = feed_xml(&[feed_entry("video1", "A video", now - Duration::hours(1))]);
+        post_feed(&state, &subscription_id, body).await.unwrap();
+
+        let mut packets = Vec::new();
+        let mut buffer = [0; 512];
+        while let Ok(size) = statsd.recv(&mut buffer) {
+            let packet = String::from_utf8_lossy(&buffer[..size]).into_owned();
+            if packet.starts_with("reddit_youtube_bot.submissions_posted_total:1|c") {
+                assert!(packet.contains("subreddit:videos"), "{}", packet);
+                return;
+            }
+            packets.push(packet);
+        }
+
+        panic!("no submission metric was sent, only: {:?}", packets);
+    }
+
+    #[tokio::test]
+    async fn retry_ending_in_a_non_transient_error_marks_the_submission_failed() {
+        let reddit = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "json": { "errors": [["TOO_LONG", "this is too long (max: 300)", "title"]] }
+            })))
+            .expect(1)
+            .mount(&reddit)
+            .await;
+        MOCK_REDDIT_URL.set(Some(reddit.uri()));
+
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, CHANNEL_ID, &reddit_account_id, &subreddit).await;
+        let entry = simple_entry("video1", "A video");
+        update_subscription_posting_cutoff(
+            &state.db_pool,
+            &subscription_id,
+            &Some((entry.published - Duration::days(1)).timestamp()),
+        )
+        .await
+        .unwrap();
+        let (failed_submission_id, _) = record_failed_submission(
+            &state.db_pool,
+            &subscription_id,
+            &reddit_account_id,
+            &subreddit.id,
+            &entry.yt_video_id,
+            &serde_json::to_string(&entry).unwrap(),
+            &ApiError::InternalError("Web request timed out".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let result = retry_failed_submission(&state, failed_submission_id).await;
+
+        assert!(matches!(result, Err(ApiError::RedditRejected(..))));
+        let failed_submission = fetch_failed_submission(&state.db_pool, &failed_submission_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            failed_submission.status,
+            FailedSubmissionStatus::Failed
+        ));
+    }
+
+    #[tokio::test]
+    async fn every_entry_of_a_batched_notification_is_submitted() {
+        let now = Utc::now();
+        let reddit = MockServer::start().await;
+        for video_id in ["video1", "video2"] {
+            Mock::given(method("POST"))
+                .and(path("/api/submit"))
+                .and(body_string_contains(video_id))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "json": {
+                        "errors": [],
+                        "data": {
+                            "url": format!("https://www.reddit.com/r/videos/comments/{video_id}/"),
+                            "name": format!("t3_{video_id}")
+                        }
+                    }
+                })))
+                .expect(1)
+                .mount(&reddit)
+                .await;
+        }
+        MOCK_REDDIT_URL.set(Some(reddit.uri()));
+
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, CHANNEL_ID, &reddit_account_id, &subreddit).await;
+        update_subscription_posting_cutoff(
+            &state.db_pool,
+            &subscription_id,
+            &Some((now - Duration::days(1)).timestamp()),
+        )
+        .await
+        .unwrap();
+        let body = feed_xml(&[
+            feed_entry("video1", "First video", now - Duration::hours(2)),
+            feed_entry("video2", "Second video", now - Duration::hours(1)),
+        ]);
+
+        post_feed(&state, &subscription_id, body).await.unwrap();
+
+        for video_id in ["video1", "video2"] {
+            assert!(
+                video_already_submitted_to_subreddit(
+                    &state.db_pool,
+                    &subreddit.id,
+                    &video_id.to_string()
+                )
+                .await
+                .unwrap(),
+                "{}",
+                video_id
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_without_lease_seconds_returns_the_challenge() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subscription_id = verified_subscription(&state, OLD_CHANNEL_ID).await;
+        update_subscription_channel(
+            &state.db_pool,
+            &subscription_id,
+            &CHANNEL_ID.to_string(),
+            &"Channel".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let challenge = subscription_verification(
+            State(state.clone()),
+            Path(subscription_id),
+            unsubscribe_query(OLD_CHANNEL_ID),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(challenge, "unsubscribe-challenge");
+    }
+
+    #[tokio::test]
+    async fn unrequested_unsubscribe_is_refused_and_keeps_the_subscription() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+
+        let result = subscription_verification(
+            State(state.clone()),
+            Path(subscription_id.clone()),
+            unsubscribe_query(CHANNEL_ID),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::NotFound(_))));
+        assert!(
+            get_subscription_details(&state.db_pool, &subscription_id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    fn channel_feed(entry_count: i64) -> SubscriptionData {
+        let author = Author {
+            name: "Channel".to_string(),
+            uri: format!("https://www.youtube.com/channel/{}", CHANNEL_ID),
+        };
+        let newest_published = Utc::now();
+
+        SubscriptionData {
+            author: author.clone(),
+            entries: (0..entry_count)
+                .map(|i| Entry {
+                    id: format!("yt:video:video{}", i),
+                    yt_video_id: format!("video{}", i),
+                    yt_channel_id: CHANNEL_ID.to_string(),
+                    title: format!("Video {}", i),
+                    links: Vec::new(),
+                    author: author.clone(),
+                    published: newest_published - Duration::days(i),
+                    updated: newest_published - Duration::days(i),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn full_feed_refuses_a_backfill_starting_before_its_oldest_entry() {
+        let channel_feed = channel_feed(FEED_MAX_ENTRIES as i64);
+        let oldest_published = channel_feed.entries.last().unwrap().published;
+
+        assert!(matches!(
+            check_feed_covers_backfill(&channel_feed, Some(oldest_published - Duration::days(1))),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            check_feed_covers_backfill(&channel_feed, None),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(check_feed_covers_backfill(&channel_feed, Some(oldest_published)).is_ok());
+    }
+
+    #[test]
+    fn partial_feed_covers_any_backfill() {
+        let channel_feed = channel_feed(3);
+
+        assert!(check_feed_covers_backfill(&channel_feed, None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn catch_up_submits_the_videos_published_while_the_bot_was_down() {
+        let now = Utc::now();
+        let youtube = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(query_param("channel_id", CHANNEL_ID))
+            .respond_with(ResponseTemplate::new(200).set_body_string(feed_xml(&[
+                feed_entry("missed1", "Missed video", now - Duration::hours(1)),
+                feed_entry("old1", "Old video", now - Duration::days(30)),
+            ])))
+            .mount(&youtube)
+            .await;
+        MOCK_YOUTUBE_URL.set(Some(youtube.uri()));
+        let reddit = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [],
+                    "data": {
+                        "url": "https://www.reddit.com/r/videos/comments/abc/missed_video/",
+                        "name": "t3_abc"
+                    }
+                }
+            })))
+            .expect(1)
+            .mount(&reddit)
+            .await;
+        MOCK_REDDIT_URL.set(Some(reddit.uri()));
+
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let reddit_account_id = reddit_account(&state, "bot").await;
+        let subreddit = subreddit(&state, "videos").await;
+        let subscription_id =
+            linked_subscription(&state, CHANNEL_ID, &reddit_account_id, &subreddit).await;
+        // The subscription was made before the bot went down.
+        update_subscription_posting_cutoff(
+            &state.db_pool,
+            &subscription_id,
+            &Some((now - Duration::days(2)).timestamp()),
+        )
+        .await
+        .unwrap();
+
+        catch_up_on_missed_videos(state.clone()).await.unwrap();
+
+        for (video_id, submitted) in [("missed1", true), ("old1", false)] {
+            assert_eq!(
+                video_already_submitted_to_subreddit(
+                    &state.db_pool,
+                    &subreddit.id,
+                    &video_id.to_string()
+                )
+                .await
+                .unwrap(),
+                submitted,
+                "{}",
+                video_id
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fetched_channel_is_cached_with_its_topic_url() {
+        let youtube = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(query_param("channel_id", CHANNEL_ID))
+            .respond_with(ResponseTemplate::new(200).set_body_string(feed_xml(&[])))
+            .expect(1)
+            .mount(&youtube)
+            .await;
+        MOCK_YOUTUBE_URL.set(Some(youtube.uri()));
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let channel = fetch_channel(&state, &CHANNEL_ID.to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(channel.channel_name, "Channel");
+        assert_eq!(channel.topic_url, topic_url(CHANNEL_ID));
+        assert_eq!(state.channels.get(CHANNEL_ID).await, Some(channel));
+        // The second lookup is served from the cache, the mock expects a single fetch.
+        assert_eq!(
+            fetch_channel_name(&state, &CHANNEL_ID.to_string())
+                .await
+                .unwrap(),
+            "Channel"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_verifications_all_echo_and_persist_their_leases() {
+        let database_directory = std::env::temp_dir().join(Uuid::now_v7().to_string());
+        let mut settings = Settings::for_tests();
+        settings.database_url = format!("sqlite://{}/bot.db", database_directory.display());
+        settings.database_max_connections = 10;
+        settings.database_wal_mode = true;
+        let (state, _receiver) = AppState::for_tests(settings).await;
+
+        let mut subscription_ids = Vec::new();
+        for i in 0..50 {
+            let subscription_id = Uuid::now_v7().to_string();
+            let subscription_form = subscription_form(&format!("UC{:0>22}", i));
+            save_form_data(
+                &state.db_pool,
+                &subscription_id,
+                &serde_json::to_string(&subscription_form).unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
+            subscription_ids.push((subscription_id, subscription_form.topic_url));
+        }
+
+        let mut verifications = tokio::task::JoinSet::new();
+        for (subscription_id, topic_url) in subscription_ids.clone() {
+            let state = state.clone();
+            verifications.spawn(async move {
+                let challenge = format!("challenge-{}", subscription_id);
+                let echoed = subscription_verification(
+                    State(state),
+                    Path(subscription_id),
+                    Query(Verification {
+                        mode: VerificationMode::Subscribe,
+                        topic: topic_url,
+                        challenge: challenge.clone(),
+                        lease_seconds: Some(432000),
+                    }),
+                )
+                .await;
+
+                (challenge, echoed)
+            });
+        }
+
+        while let Some(verification) = verifications.join_next().await {
+            let (challenge, echoed) = verification.unwrap();
+            assert_eq!(echoed.unwrap(), challenge);
+        }
+
+        for (subscription_id, _) in &subscription_ids {
+            let subscription = get_subscription_details(&state.db_pool, subscription_id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(subscription.expires.is_some(), "{}", subscription_id);
+        }
+
+        state.db_pool.close().await;
+        let _ = std::fs::remove_dir_all(database_directory);
+    }
+}
@@ -1,10 +1,34 @@
+mod admin_auth;
+mod alerts;
+mod api;
+mod bulk_subscribe;
+mod doctor;
 mod forms;
 mod frontend;
 mod google;
+mod monitoring;
 mod reddit;
 mod repository;
+mod request_log;
 mod server;
 mod shared;
+#[cfg(test)]
+pub mod test_support;
+mod youtube;
 
-pub use server::{ApiError, serve};
-pub use shared::{RedditCredentials, SubCommand, subscribe_to_channel};
+pub use alerts::send_failure_alert;
+pub use bulk_subscribe::bulk_subscribe;
+pub use doctor::doctor;
+pub use google::{
+    retry_failed_submission, retry_submission, schedule_verification_check, submit_deferred_entry,
+};
+pub use reddit::prewarm_reddit_oauth_token;
+pub use repository::{delete_expired_form_data, expire_subscription, mark_subscription_pending};
+pub use server::{ApiError, ErrorResponse, backfill, openapi_spec, replay, serve};
+#[cfg(test)]
+pub use shared::MOCK_HUB_URL;
+pub use shared::{
+    AdminCredentials, ChannelCache, HttpTimeouts, PendingSubmission, PendingVerification,
+    RESUBSCRIBE_EARLY_SECS, RedditCredentials, RedditUrls, SimpleEntry, SubCommand,
+    SubscriptionStatus, subscribe_to_channel,
+};
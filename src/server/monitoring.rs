@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    infrastructure::AppState,
+    server::{ApiError, ErrorResponse},
+};
+
+pub fn router() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::new()
+        .routes(routes!(prometheus_metrics))
+        .routes(routes!(version))
+}
+
+/// Prometheus metrics
+#[utoipa::path(
+        get,
+        path = "/metrics",
+        description = "Counters for received feeds, signature failures, submissions, token refreshes and resubscriptions, plus the Reddit submit latency, in the Prometheus text format.",
+        responses(
+            (status = 200, description = "The metrics in the Prometheus text format.", body = String, content_type = "text/plain"),
+            (status = 404, description = "The Prometheus exporter isn't enabled.", body = ErrorResponse),
+        ),
+        tag = "monitoring"
+    )]
+#[axum::debug_handler]
+async fn prometheus_metrics(State(state): State<Arc<AppState>>) -> Result<String, ApiError> {
+    match &state.metrics_handle {
+        Some(handle) => Ok(handle.render()),
+        None => Err(ApiError::NotFound(
+            "The Prometheus exporter isn't enabled, set PROMETHEUS_METRICS=true to enable it."
+                .into(),
+        )),
+    }
+}
+
+/// The running build, set by the build script.
+#[derive(Serialize, ToSchema)]
+struct VersionInfo {
+    #[schema(example = "0.1.0")]
+    version: &'static str,
+    /// The short git commit hash, `unknown` when built outside of the git repository.
+    #[schema(example = "a6aeac2")]
+    git_commit: &'static str,
+    built_at: Option<DateTime<Utc>>,
+}
+
+/// Build info
+#[utoipa::path(
+        get,
+        path = "/version",
+        description = "The version, git commit and build time of the running bot.",
+        responses(
+            (status = 200, description = "The build info.", body = VersionInfo),
+        ),
+        tag = "monitoring"
+    )]
+#[axum::debug_handler]
+async fn version() -> Json<VersionInfo> {
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("GIT_COMMIT"),
+        built_at: env!("BUILD_TIMESTAMP")
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_secs),
+    })
+}
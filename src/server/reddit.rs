@@ -3,31 +3,39 @@ use std::{collections::HashMap, sync::Arc};
 use axum::{
     Form,
     extract::{Query, State},
-    response::Redirect,
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use metrics::counter;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use serde_textual::DisplaySerde;
-use sqlx::{Pool, Sqlite};
 use url::Url;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
 use crate::{
-    infrastructure::AppState,
+    infrastructure::{AppState, TitleOverflow, telemetry},
     server::{
-        ApiError, RedditCredentials,
+        ApiError, ErrorResponse, RedditCredentials, SubCommand,
+        alerts::send_failure_alert,
         repository::{
-            fetch_form_data, fetch_reddit_accounts, fetch_reddit_accounts_for_subscription,
-            fetch_submissions_on_subreddit, fetch_subreddits, get_or_create_subreddit,
-            save_reddit_account, save_reddit_submission, update_reddit_oauth_token,
-            update_reddit_submission_sticky_state,
+            RedditSubmission, delete_form_data, fetch_form_data,
+            fetch_reddit_account_id_by_username, fetch_reddit_account_stats, fetch_reddit_accounts,
+            fetch_reddit_accounts_for_subscription, fetch_submissions_on_subreddit,
+            fetch_subreddits, get_or_create_subreddit, get_reddit_account_by_id,
+            record_token_refresh, reddit_submission_saved, save_reddit_account,
+            save_reddit_account_stats, save_reddit_submission, update_reddit_account,
+            update_reddit_oauth_token, update_reddit_submission_sticky_state,
         },
         shared::{
-            self, HTTP_CLIENT, RedditAccount, RedditAccountDTO, RedditAuthorization,
-            RedditOAuthToken, RedditSubmissionData, Subreddit,
+            self, DescriptionComment, HTTP_CLIENT, RedditAccount, RedditAccountDTO,
+            RedditAccountStats, RedditAuthorization, RedditOAuthToken, RedditSubmissionData,
+            Subreddit, reddit_oauth_url, reddit_url, render_title_template, youtube_thumbnail_url,
         },
+        youtube::fetch_video_description,
     },
 };
 
@@ -50,6 +58,10 @@ impl From<uuid::Error> for ApiError {
 
 impl From<reqwest::Error> for ApiError {
     fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return ApiError::InternalError(format!("Web request timed out: {}", error));
+        }
+
         ApiError::InternalError(format!("Web request failed: {}", error))
     }
 }
@@ -108,26 +120,74 @@ pub enum RedditCallbackErrors {
         description = "Reddit authorize URL redirect used to retrieve the Reddit OAuth token for a given Reddit account.",
         responses(
             (status = 303, description = "Reddit OAuth token successfully received and previous Reddit submissions handled"),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Error page explaining the invalid callback, e.g. a denied authorization or missing scopes.", content_type = "text/html; charset=utf-8"),
+            (status = 500, description = "Error page explaining the internal server error.", content_type = "text/html; charset=utf-8"),
         ),
         tag = "reddit"
     )]
 #[axum::debug_handler]
 async fn reddit_callback(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Query(callback): Query<RedditCallback>,
+) -> Response {
+    let base_url = state.external_base_url(&headers);
+
+    // The user lands here from Reddit in the browser, so errors get a page instead of JSON.
+    match handle_reddit_callback(&state, callback, &base_url).await {
+        Ok(redirect) => redirect.into_response(),
+        Err(error) => render_callback_error_page(&state, error),
+    }
+}
+
+fn render_callback_error_page(state: &Arc<AppState>, error: ApiError) -> Response {
+    let message = error.to_string();
+    let status = error.into_response().status();
+
+    let whole_document = state
+        .render_page(
+            "callback_error",
+            &json!({
+                "message": message,
+                "base_url": state.base_url,
+            }),
+        )
+        .map_err(|e| e.to_string());
+
+    match whole_document {
+        Ok(whole_document) => (status, Html(whole_document)).into_response(),
+        Err(e) => {
+            eprintln!("Could not render the Reddit callback error page: {}", e);
+            (status, message).into_response()
+        }
+    }
+}
+
+async fn handle_reddit_callback(
+    state: &Arc<AppState>,
+    callback: RedditCallback,
+    base_url: &String,
 ) -> Result<Redirect, ApiError> {
     let state_uuid = RedditCallback::validate(&callback.state, &callback.error)?;
     println!("Now handling a Reddit OAuth callback");
 
-    let reddit_auth_form_data: RedditAuthorization =
-        fetch_form_data(&state.db_pool, &state_uuid.to_string()).await?;
+    let state_key = state_uuid.to_string();
+
+    // A missing row means the state was never issued by this bot or was already used.
+    let reddit_auth_form_data: RedditAuthorization = fetch_form_data(&state.db_pool, &state_key)
+        .await
+        .map_err(|e| match e {
+            ApiError::NotFound(_) => ApiError::BadRequest(format!(
+                "The Reddit OAuth state {} is unknown or expired, restart the authorization from the Reddit account form.",
+                state_key
+            )),
+            e => e,
+        })?;
 
     let client = &HTTP_CLIENT;
 
     let oauth_token = client
-        .post("https://www.reddit.com/api/v1/access_token")
+        .post(reddit_url("/api/v1/access_token"))
         .basic_auth(
             &state.reddit_credentials.client_id,
             Some(&state.reddit_credentials.client_secret),
@@ -135,17 +195,14 @@ async fn reddit_callback(
         .form(&[
             ("grant_type", "authorization_code"),
             ("code", &callback.code),
-            (
-                "redirect_uri",
-                &format!("{}/reddit/callback", &state.base_url),
-            ),
+            ("redirect_uri", &format!("{}/reddit/callback", base_url)),
         ])
         .send()
         .await?
         .text()
         .await?;
 
-    let oauth_token: RedditOAuthToken = serde_json::from_str(&oauth_token).map_err(|e| {
+    let mut oauth_token: RedditOAuthToken = serde_json::from_str(&oauth_token).map_err(|e| {
         ApiError::BadRequest(format!(
             "Error parsing Reddit OAuth token response body: {}. Response body was: {}. The form data was: {:?}",
             e, oauth_token, reddit_auth_form_data
@@ -154,56 +211,267 @@ async fn reddit_callback(
 
     println!("Successfully created Reddit OAuth token, now verifying its scopes.");
 
-    if !oauth_token.scope.contains("identity") {
-        return Err(ApiError::BadRequest(
-            "'identity' Reddit API scope needed for to get username".into(),
-        ));
+    let missing_scopes = missing_reddit_scopes(
+        &oauth_token.scope,
+        reddit_auth_form_data.moderate_submissions,
+    );
+
+    if !missing_scopes.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "The Reddit authorization is missing the following scopes: {}. The granted scopes were: {}",
+            missing_scopes.join(", "),
+            oauth_token.scope
+        )));
+    }
+
+    let reddit_user_name = fetch_reddit_username(state, &mut oauth_token).await?;
+
+    let reddit_account_id = save_reddit_account(
+        &state.db_pool,
+        &reddit_user_name,
+        &oauth_token,
+        &reddit_auth_form_data.moderate_submissions,
+    )
+    .await?;
+
+    if let Err(e) = delete_form_data(&state.db_pool, &state_key).await {
+        eprintln!(
+            "Could not delete the consumed Reddit OAuth form data {}: {}",
+            state_key, e
+        );
     }
 
+    if oauth_token.refresh_token.is_some() {
+        let _ = state
+            .scheduler_sender
+            .send(SubCommand::RefreshToken {
+                reddit_account_id: reddit_account_id.clone(),
+                wait_secs: (oauth_token.expires_in - state.token_refresh_window_secs).max(0),
+            })
+            .await;
+    }
+
+    println!("Reddit account data saved to db, now handling previous Reddit submissions.");
+
+    handle_previous_reddit_submissions(
+        state,
+        &reddit_account_id,
+        &reddit_user_name,
+        &oauth_token,
+        None,
+    )
+    .await?;
+
+    Ok(Redirect::to(base_url))
+}
+
+/// Fetches the username of the token's account, which also confirms Reddit accepts the token.
+/// A rejected token is refreshed when it has a refresh token.
+async fn fetch_reddit_username(
+    state: &Arc<AppState>,
+    oauth_token: &mut RedditOAuthToken,
+) -> Result<String, ApiError> {
     println!("Fetching Reddit username using the OAuth token.");
 
+    let response = send_with_token_refresh(state, None, oauth_token, |access_token| {
+        HTTP_CLIENT
+            .get(reddit_oauth_url("/api/v1/me"))
+            .bearer_auth(access_token)
+    })
+    .await?;
+
+    if matches!(
+        response.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(ApiError::BadRequest(format!(
+            "Reddit rejected the OAuth token ({}).",
+            response.status()
+        )));
+    }
+
     // uses serde_json::Value since the 'name' property is the only value wanted
-    let reddit_user_name = client
-        .get("https://oauth.reddit.com/api/v1/me")
-        .bearer_auth(&oauth_token.access_token)
-        .send()
-        .await?
-        .json::<serde_json::Value>()
-        .await?["name"]
+    response.json::<serde_json::Value>().await?["name"]
         .as_str()
         .map(|s| s.to_string())
         .ok_or({
             ApiError::InternalError(
                 "'name' property missing from https://oauth.reddit.com/api/v1/me response.".into(),
             )
-        })?;
+        })
+}
 
-    let reddit_account_id = save_reddit_account(
-        &state.db_pool,
-        &reddit_user_name,
-        &oauth_token,
-        &reddit_auth_form_data.moderate_submissions,
-    )
-    .await?;
+/// Stores a Reddit account from a token obtained outside the OAuth callback, e.g. when restoring
+/// the accounts after losing the database. The token is checked with `/api/v1/me`, an account
+/// with the same username gets the new token instead of a second row.
+/// Returns the account id, its username and whether it was created.
+pub async fn import_reddit_account(
+    state: &Arc<AppState>,
+    mut oauth_token: RedditOAuthToken,
+    moderate_submissions: bool,
+) -> Result<(String, String, bool), ApiError> {
+    let missing_scopes = missing_reddit_scopes(&oauth_token.scope, moderate_submissions);
 
-    println!("Reddit account data saved to db, now handling previous Reddit submissions.");
+    if !missing_scopes.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "The OAuth token is missing the following scopes: {}. The token's scopes were: {}",
+            missing_scopes.join(", "),
+            oauth_token.scope
+        )));
+    }
 
-    handle_previous_reddit_submissions(&state, &reddit_account_id, &reddit_user_name).await?;
+    let reddit_user_name = fetch_reddit_username(state, &mut oauth_token).await?;
 
-    Ok(Redirect::to(&state.base_url))
+    let existing_account_id =
+        fetch_reddit_account_id_by_username(&state.db_pool, &reddit_user_name).await?;
+
+    let reddit_account_id = match &existing_account_id {
+        Some(reddit_account_id) => {
+            update_reddit_account(
+                &state.db_pool,
+                reddit_account_id,
+                &oauth_token,
+                &moderate_submissions,
+            )
+            .await?;
+            reddit_account_id.clone()
+        }
+        None => {
+            save_reddit_account(
+                &state.db_pool,
+                &reddit_user_name,
+                &oauth_token,
+                &moderate_submissions,
+            )
+            .await?
+        }
+    };
+
+    println!(
+        "Imported the OAuth token for https://www.reddit.com/user/{}.",
+        reddit_user_name
+    );
+
+    if oauth_token.refresh_token.is_some() {
+        let _ = state
+            .scheduler_sender
+            .send(SubCommand::RefreshToken {
+                reddit_account_id: reddit_account_id.clone(),
+                wait_secs: (oauth_token.expires_in - state.token_refresh_window_secs).max(0),
+            })
+            .await;
+    }
+
+    // A restored account has no submissions in the new database yet.
+    if existing_account_id.is_none() {
+        handle_previous_reddit_submissions(
+            state,
+            &reddit_account_id,
+            &reddit_user_name,
+            &oauth_token,
+            None,
+        )
+        .await?;
+    }
+
+    Ok((
+        reddit_account_id,
+        reddit_user_name,
+        existing_account_id.is_none(),
+    ))
+}
+
+// 'identity' is needed to get the username, 'submit' to submit videos and comment on them.
+const REQUIRED_REDDIT_SCOPES: [&str; 2] = ["identity", "submit"];
+// Stickying submissions and comments needs 'modposts', flairing needs 'flair'.
+const MODERATION_REDDIT_SCOPES: [&str; 2] = ["modposts", "flair"];
+
+fn split_reddit_scopes(granted_scopes: &str) -> Vec<&str> {
+    granted_scopes
+        .split([' ', ','])
+        .map(|scope| scope.trim())
+        .collect()
 }
 
-async fn handle_previous_reddit_submissions(
+/// Whether the account's token was granted the scope, Reddit grants every scope as `*`.
+fn has_reddit_scope(reddit_account: &RedditAccount, scope: &str) -> bool {
+    split_reddit_scopes(&reddit_account.oauth_token.scope)
+        .iter()
+        .any(|granted_scope| *granted_scope == scope || *granted_scope == "*")
+}
+
+/// Checked before a Reddit request, so a missing scope is reported here instead of as a 403 from Reddit.
+fn require_reddit_scope(
+    reddit_account: &RedditAccount,
+    scope: &str,
+    action: &str,
+) -> Result<(), ApiError> {
+    if has_reddit_scope(reddit_account, scope) {
+        return Ok(());
+    }
+
+    Err(ApiError::BadRequest(format!(
+        "The OAuth token for https://www.reddit.com/user/{} wasn't granted the '{}' scope needed to {}, authorize the account again to grant it. Granted scopes: {}",
+        reddit_account.username, scope, action, reddit_account.oauth_token.scope
+    )))
+}
+
+/// Returns the scopes the account's role needs that weren't granted.
+pub fn missing_reddit_scopes(
+    granted_scopes: &str,
+    moderate_submissions: bool,
+) -> Vec<&'static str> {
+    let granted_scopes = split_reddit_scopes(granted_scopes);
+
+    let moderation_scopes: &[&'static str] = if moderate_submissions {
+        &MODERATION_REDDIT_SCOPES
+    } else {
+        &[]
+    };
+
+    REQUIRED_REDDIT_SCOPES
+        .iter()
+        .chain(moderation_scopes)
+        .filter(|scope| !granted_scopes.contains(scope) && !granted_scopes.contains(&"*"))
+        .copied()
+        .collect()
+}
+
+/// Saves the account's YouTube video submissions made since `since`, or all of them, so the bot
+/// doesn't submit those videos again. Submissions that are already saved are skipped.
+pub async fn handle_previous_reddit_submissions(
     state: &Arc<AppState>,
     reddit_account_id: &String,
     reddit_user_name: &String,
+    oauth_token: &RedditOAuthToken,
+    since: Option<DateTime<Utc>>,
 ) -> Result<(), ApiError> {
+    // The account's own listing also has its hidden and removed submissions, it needs the 'history' scope.
+    let (submissions_url, access_token) = if split_reddit_scopes(&oauth_token.scope)
+        .contains(&"history")
+    {
+        (
+            reddit_oauth_url(&format!("/user/{}/submitted?limit=100", reddit_user_name)),
+            Some(&oauth_token.access_token),
+        )
+    } else {
+        println!(
+            "The OAuth token for https://www.reddit.com/user/{} doesn't have the 'history' scope, only the public submissions are imported.",
+            reddit_user_name
+        );
+        (
+            reddit_url(&format!(
+                "/user/{}/submitted.json?limit=100",
+                reddit_user_name
+            )),
+            None,
+        )
+    };
+
     let reddit_account_submissions = fetch_reddit_account_submissions(
         &state.reddit_credentials,
-        format!(
-            "https://www.reddit.com/user/{}/submitted.json",
-            reddit_user_name
-        ),
+        access_token,
+        submissions_url.clone(),
     )
     .await?;
 
@@ -212,14 +480,22 @@ async fn handle_previous_reddit_submissions(
     println!("Fetched {} Reddit submissions.", submission_data.len());
 
     let mut next_page_token = reddit_account_submissions.next_page_token;
+    // The listing is newest first, the pages after one reaching past `since` are all older.
+    let reaches_since = |submission_data: &[SubmissionJsonData]| {
+        since.is_some_and(|since| {
+            submission_data
+                .last()
+                .is_some_and(|data| (data.created_utc as i64) < since.timestamp())
+        })
+    };
 
-    while let Some(token) = next_page_token {
+    while let Some(token) = next_page_token
+        && !reaches_since(&submission_data)
+    {
         let new_submission_data = fetch_reddit_account_submissions(
             &state.reddit_credentials,
-            format!(
-                "https://www.reddit.com/user/{}/submitted.json?after={}",
-                reddit_user_name, token
-            ),
+            access_token,
+            format!("{}&after={}", submissions_url, token),
         )
         .await?;
 
@@ -231,6 +507,7 @@ async fn handle_previous_reddit_submissions(
     let filtered_submissions: Vec<SubmissionData> = submission_data
         .into_iter()
         .filter_map(|data| to_submission_data(&data))
+        .filter(|submission| since.is_none_or(|since| submission.timestamp >= since.timestamp()))
         .collect();
 
     println!(
@@ -240,6 +517,10 @@ async fn handle_previous_reddit_submissions(
     );
 
     for submission in filtered_submissions {
+        if reddit_submission_saved(&state.db_pool, &submission.id).await? {
+            continue;
+        }
+
         let subreddit = get_or_create_subreddit(
             &state.db_pool,
             &submission.subreddit_name,
@@ -275,6 +556,10 @@ struct SubmissionData {
 }
 
 fn to_submission_data(data: &SubmissionJsonData) -> Option<SubmissionData> {
+    if data.is_self {
+        return None;
+    }
+
     let video_id = youtube_url_to_video_id(&data.url)?;
     let timestamp = data.created_utc.round() as i64;
 
@@ -291,9 +576,12 @@ fn to_submission_data(data: &SubmissionJsonData) -> Option<SubmissionData> {
 fn youtube_url_to_video_id(url: &Url) -> Option<String> {
     let domain = url.domain()?;
 
-    // Handle https://youtu.be/ID
+    // Handle https://youtu.be/ID, the query (e.g. ?t=30) and fragment aren't part of the path segments
     if domain.ends_with("youtu.be") {
-        return url.path_segments()?.next().map(|id| id.to_string());
+        return url
+            .path_segments()?
+            .find(|&s| !s.is_empty())
+            .map(|id| id.to_string());
     }
 
     if domain.ends_with("youtube.com") {
@@ -332,22 +620,31 @@ pub struct SubmissionJsonData {
     pub flair_id: Option<String>,
     pub created_utc: f64,
     pub stickied: bool,
+    /// Text posts can link a video in their body, only link posts are the bot's video submissions.
+    #[serde(default)]
+    pub is_self: bool,
 }
 
+/// Uses the account's access token when given, the app's client credentials otherwise.
 async fn fetch_reddit_account_submissions(
     reddit_credentials: &RedditCredentials,
+    access_token: Option<&String>,
     url: String,
 ) -> Result<RedditSubmissionJson, ApiError> {
     let client = &HTTP_CLIENT;
 
-    let reddit_submissions = client
-        .get(url)
-        .basic_auth(
+    let request = match access_token {
+        Some(access_token) => client.get(url).bearer_auth(access_token),
+        None => client.get(url).basic_auth(
             &reddit_credentials.client_id,
             Some(&reddit_credentials.client_secret),
-        )
+        ),
+    };
+
+    let reddit_submissions = request
         .send()
         .await?
+        .error_for_status()?
         .json::<serde_json::Value>()
         .await?;
 
@@ -384,11 +681,22 @@ pub async fn get_associated_reddit_accounts_for_subscription(
     Ok(reddit_accounts)
 }
 
+pub async fn get_reddit_account(
+    state: &Arc<AppState>,
+    reddit_account_id: &String,
+) -> Result<RedditAccount, ApiError> {
+    let reddit_account = get_reddit_account_by_id(&state.db_pool, reddit_account_id).await?;
+
+    to_reddit_account(state, &reddit_account).await
+}
+
 async fn to_reddit_account(
     state: &Arc<AppState>,
     reddit_account: &RedditAccountDTO,
 ) -> Result<RedditAccount, ApiError> {
     let mut oauth_token: RedditOAuthToken = serde_json::from_str(&reddit_account.oauth_token)?;
+
+    // The scheduler normally refreshes the token before it expires, this covers a missed refresh.
     if let Some(refresh_token) = &oauth_token.refresh_token
         && Utc::now().timestamp() >= reddit_account.expires_at
     {
@@ -397,9 +705,13 @@ async fn to_reddit_account(
             reddit_account.username
         );
 
-        oauth_token = refresh_reddit_oauth_token(&state, refresh_token).await?;
-
-        update_reddit_oauth_token(&state.db_pool, &reddit_account.id, &oauth_token).await?;
+        oauth_token = refresh_and_save_oauth_token(
+            state,
+            &reddit_account.id,
+            &reddit_account.username,
+            refresh_token,
+        )
+        .await?;
     }
 
     Ok(RedditAccount {
@@ -410,6 +722,176 @@ async fn to_reddit_account(
     })
 }
 
+/// Refreshes the account's token if it expires within the refresh window, and returns the seconds
+/// until the next refresh is due. `None` when the token has no refresh token and can't be refreshed.
+pub async fn prewarm_reddit_oauth_token(
+    state: &Arc<AppState>,
+    reddit_account_id: &String,
+) -> Result<Option<i64>, ApiError> {
+    let reddit_account = get_reddit_account_by_id(&state.db_pool, reddit_account_id).await?;
+    let oauth_token: RedditOAuthToken = serde_json::from_str(&reddit_account.oauth_token)?;
+
+    let Some(refresh_token) = &oauth_token.refresh_token else {
+        return Ok(None);
+    };
+
+    let mut expires_at = reddit_account.expires_at;
+
+    // A submission may have refreshed the token in the meantime, then only the next refresh is scheduled.
+    if Utc::now().timestamp() >= expires_at - state.token_refresh_window_secs {
+        println!(
+            "The OAuth token for https://www.reddit.com/user/{} expires soon, refreshing token.",
+            reddit_account.username
+        );
+
+        let oauth_token = refresh_and_save_oauth_token(
+            state,
+            &reddit_account.id,
+            &reddit_account.username,
+            refresh_token,
+        )
+        .await?;
+        expires_at = Utc::now().timestamp() + oauth_token.expires_in;
+    }
+
+    Ok(Some(
+        (expires_at - state.token_refresh_window_secs - Utc::now().timestamp()).max(0),
+    ))
+}
+
+/// Refreshes the account's token regardless of its expiry, e.g. when Reddit keeps rejecting it.
+/// Returns when the new token expires.
+pub async fn force_refresh_reddit_oauth_token(
+    state: &Arc<AppState>,
+    reddit_account_id: &String,
+) -> Result<i64, ApiError> {
+    let reddit_account = get_reddit_account_by_id(&state.db_pool, reddit_account_id).await?;
+    let oauth_token: RedditOAuthToken = serde_json::from_str(&reddit_account.oauth_token)?;
+
+    let Some(refresh_token) = &oauth_token.refresh_token else {
+        return Err(ApiError::BadRequest(format!(
+            "The OAuth token for https://www.reddit.com/user/{} has no refresh token, it was authorized temporarily, authorize the account again instead",
+            reddit_account.username
+        )));
+    };
+
+    println!(
+        "Refreshing the OAuth token for https://www.reddit.com/user/{} on request.",
+        reddit_account.username
+    );
+
+    let oauth_token = refresh_and_save_oauth_token(
+        state,
+        &reddit_account.id,
+        &reddit_account.username,
+        refresh_token,
+    )
+    .await?;
+
+    Ok(Utc::now().timestamp() + oauth_token.expires_in)
+}
+
+async fn refresh_and_save_oauth_token(
+    state: &Arc<AppState>,
+    reddit_account_id: &String,
+    username: &String,
+    refresh_token: &String,
+) -> Result<RedditOAuthToken, ApiError> {
+    let oauth_token = match refresh_reddit_oauth_token(state, refresh_token).await {
+        Ok(oauth_token) => oauth_token,
+        Err(e) => {
+            log_token_refresh(state, reddit_account_id, Some(&e.to_string())).await;
+
+            send_failure_alert(
+                state,
+                &format!(
+                    "Refreshing the OAuth token for https://www.reddit.com/user/{} failed: {}",
+                    username, e
+                ),
+            )
+            .await;
+
+            return Err(e);
+        }
+    };
+    counter!(telemetry::TOKEN_REFRESHES).increment(1);
+
+    let saved = update_reddit_oauth_token(&state.db_pool, reddit_account_id, &oauth_token).await;
+    log_token_refresh(
+        state,
+        reddit_account_id,
+        saved.as_ref().err().map(|e| e.to_string()).as_ref(),
+    )
+    .await;
+    saved?;
+
+    Ok(oauth_token)
+}
+
+/// Token refresh attempts kept per account, enough to spot a pattern of failures.
+const TOKEN_REFRESH_HISTORY: i64 = 50;
+
+/// The history is only for diagnosing, so failing to write it doesn't fail the refresh.
+async fn log_token_refresh(
+    state: &Arc<AppState>,
+    reddit_account_id: &String,
+    error: Option<&String>,
+) {
+    if let Err(e) = record_token_refresh(
+        &state.db_pool,
+        reddit_account_id,
+        &Utc::now().timestamp(),
+        error,
+        &TOKEN_REFRESH_HISTORY,
+    )
+    .await
+    {
+        eprintln!(
+            "Could not record the token refresh of the Reddit account {}: {}",
+            reddit_account_id, e
+        );
+    }
+}
+
+/// Sends an authenticated Reddit request. A 401 means the token expired between the expiry check and
+/// the request, so the token is refreshed, saved when the account is stored, and the request is sent once more.
+async fn send_with_token_refresh<F>(
+    state: &Arc<AppState>,
+    reddit_account: Option<&RedditAccount>,
+    oauth_token: &mut RedditOAuthToken,
+    request: F,
+) -> Result<reqwest::Response, ApiError>
+where
+    F: Fn(&str) -> reqwest::RequestBuilder,
+{
+    let response = request(&oauth_token.access_token).send().await?;
+
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+
+    let Some(refresh_token) = oauth_token.refresh_token.clone() else {
+        return Ok(response);
+    };
+
+    println!("Reddit rejected the OAuth token, refreshing token and retrying the request.");
+
+    *oauth_token = match reddit_account {
+        Some(reddit_account) => {
+            refresh_and_save_oauth_token(
+                state,
+                &reddit_account.id,
+                &reddit_account.username,
+                &refresh_token,
+            )
+            .await?
+        }
+        None => refresh_reddit_oauth_token(state, &refresh_token).await?,
+    };
+
+    Ok(request(&oauth_token.access_token).send().await?)
+}
+
 pub async fn refresh_reddit_oauth_token(
     state: &Arc<AppState>,
     refresh_token: &String,
@@ -417,7 +899,7 @@ pub async fn refresh_reddit_oauth_token(
     let client = &HTTP_CLIENT;
 
     let oauth_token: RedditOAuthToken = client
-        .post("https://www.reddit.com/api/v1/access_token")
+        .post(reddit_url("/api/v1/access_token"))
         .basic_auth(
             &state.reddit_credentials.client_id,
             Some(&state.reddit_credentials.client_secret),
@@ -434,79 +916,651 @@ pub async fn refresh_reddit_oauth_token(
     Ok(oauth_token)
 }
 
-pub async fn submit_video_to_subreddit(
-    reddit_account: &RedditAccount,
-    subreddit: &Subreddit,
-    entry: &shared::SimpleEntry,
-) -> Result<RedditSubmissionData, ApiError> {
-    let title = format!(
-        "{prefix}{title}{suffix}",
-        prefix = &subreddit.title_prefix.clone().unwrap_or("".to_string()),
-        title = entry.title,
-        suffix = &subreddit.title_suffix.clone().unwrap_or("".to_string())
-    );
-
-    let mut submission_form = HashMap::from([
-        ("api_type", "json"),
-        ("extension", "json"),
-        ("kind", "link"),
-        ("resubmit", "true"),
-        ("sendreplies", "false"),
-        ("sr", &subreddit.name),
-        ("title", &title),
-        ("url", &entry.link.href),
-    ]);
+/// How long the fetched account age/karma is reused before asking Reddit again.
+const REDDIT_ACCOUNT_STATS_MAX_AGE_SECS: i64 = 6 * 60 * 60;
 
-    if let Some(flair_id) = &subreddit.flair_id {
-        submission_form.insert("flair_id", &flair_id);
+async fn get_reddit_account_stats(
+    state: &Arc<AppState>,
+    reddit_account: &RedditAccount,
+) -> Result<RedditAccountStats, ApiError> {
+    if let Some(account_stats) =
+        fetch_reddit_account_stats(&state.db_pool, &reddit_account.id).await?
+        && Utc::now().timestamp() - account_stats.fetched_at < REDDIT_ACCOUNT_STATS_MAX_AGE_SECS
+    {
+        return Ok(account_stats);
     }
 
     let client = &HTTP_CLIENT;
 
-    let submission_response = client
-        .post("https://oauth.reddit.com/api/submit")
+    let me_response = client
+        .get(reddit_oauth_url("/api/v1/me"))
         .bearer_auth(&reddit_account.oauth_token.access_token)
-        .form(&submission_form)
         .send()
         .await?
-        .text()
-        .await
-        .map_err(|e| {
-            ApiError::InternalError(format!(
-                "Error accessing submission_response response text: {:?}",
-                e
-            ))
-        })?;
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
 
-    let submission_response: serde_json::Value = serde_json::from_str(&submission_response).map_err(|e| {
-            ApiError::InternalError(format!(
-                "Error json deserializing submission_response response: {:?} | original response body: {}",
-                e, submission_response
-            ))
-        })?;
+    let created_utc = me_response["created_utc"]
+        .as_f64()
+        .ok_or(ApiError::InternalError(
+            "'created_utc' property missing from https://oauth.reddit.com/api/v1/me response."
+                .into(),
+        ))?;
+    let total_karma = me_response["total_karma"]
+        .as_i64()
+        .ok_or(ApiError::InternalError(
+            "'total_karma' property missing from https://oauth.reddit.com/api/v1/me response."
+                .into(),
+        ))?;
+
+    let account_stats = RedditAccountStats {
+        created_utc: created_utc.round() as i64,
+        total_karma,
+        fetched_at: Utc::now().timestamp(),
+    };
 
-    let submission_errors = submission_response["json"]["errors"].as_array();
+    save_reddit_account_stats(&state.db_pool, &reddit_account.id, &account_stats).await?;
 
-    if let Some(errors) = submission_errors
-        && !errors.is_empty()
+    Ok(account_stats)
+}
+
+/// Checks the subreddit's minimum account age and karma, if any are set, against the Reddit account.
+pub async fn reddit_account_meets_subreddit_requirements(
+    state: &Arc<AppState>,
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+) -> Result<bool, ApiError> {
+    if subreddit.min_account_age_days.is_none() && subreddit.min_karma.is_none() {
+        return Ok(true);
+    }
+
+    let account_stats = get_reddit_account_stats(state, reddit_account).await?;
+
+    if let Some(min_account_age_days) = subreddit.min_account_age_days {
+        let account_age_days =
+            (Utc::now().timestamp() - account_stats.created_utc) / (24 * 60 * 60);
+
+        if account_age_days < min_account_age_days {
+            println!(
+                "The Reddit account https://www.reddit.com/user/{} is {} days old, the https://www.reddit.com/r/{} subreddit requires at least {} days.",
+                reddit_account.username, account_age_days, subreddit.name, min_account_age_days
+            );
+            return Ok(false);
+        }
+    }
+
+    if let Some(min_karma) = subreddit.min_karma
+        && account_stats.total_karma < min_karma
+    {
+        println!(
+            "The Reddit account https://www.reddit.com/user/{} has {} karma, the https://www.reddit.com/r/{} subreddit requires at least {} karma.",
+            reddit_account.username, account_stats.total_karma, subreddit.name, min_karma
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// Reddit rejects submission titles longer than this with a `TOO_LONG` error.
+const REDDIT_TITLE_MAX_LENGTH: usize = 300;
+
+/// Joins the prefix, title and suffix, when that's longer than `max_len` characters only the title
+/// is shortened and ends with an ellipsis, so tags in the prefix or suffix (e.g. `" [Official]"`)
+/// are always kept. The prefix and suffix have to leave room for at least the ellipsis.
+pub fn build_title(prefix: &str, title: &str, suffix: &str, max_len: usize) -> String {
+    let surrounding_length = prefix.chars().count() + suffix.chars().count();
+
+    if surrounding_length + title.chars().count() <= max_len {
+        return format!("{}{}{}", prefix, title, suffix);
+    }
+
+    let available_length = max_len.saturating_sub(surrounding_length + 1);
+    let shortened_title: String = title.chars().take(available_length).collect();
+
+    format!("{}{}…{}", prefix, shortened_title.trim_end(), suffix)
+}
+
+fn build_submission_title(
+    subreddit: &Subreddit,
+    entry: &shared::SimpleEntry,
+    title_overflow: TitleOverflow,
+) -> Result<String, ApiError> {
+    let prefix = subreddit.title_prefix.as_deref().unwrap_or("");
+    let suffix = subreddit.title_suffix.as_deref().unwrap_or("");
+
+    let render = |video_title: &str| -> Result<String, ApiError> {
+        match &subreddit.title_template {
+            Some(template) => render_title_template(template, video_title, entry),
+            None => Ok(format!("{}{}{}", prefix, video_title, suffix)),
+        }
+    };
+
+    let title = render(&entry.title)?;
+    let title_length = title.chars().count();
+
+    if title_length <= REDDIT_TITLE_MAX_LENGTH {
+        return Ok(title);
+    }
+
+    if let TitleOverflow::Reject = title_overflow {
+        return Err(ApiError::BadRequest(format!(
+            "The submission title for the {} subreddit is {} characters long, Reddit allows at most {}: {}",
+            subreddit.name, title_length, REDDIT_TITLE_MAX_LENGTH, title
+        )));
+    }
+
+    // Only the video title is shortened, the prefix/suffix (or the rest of the template) is kept as is.
+    let surrounding_length = render("")?.chars().count();
+
+    if surrounding_length + 1 > REDDIT_TITLE_MAX_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "The title prefix/suffix or template for the {} subreddit alone is {} characters long, Reddit allows at most {}",
+            subreddit.name, surrounding_length, REDDIT_TITLE_MAX_LENGTH
+        )));
+    }
+
+    println!(
+        "The submission title was {} characters long, shortened the video title to fit Reddit's {} character limit.",
+        title_length, REDDIT_TITLE_MAX_LENGTH
+    );
+
+    match &subreddit.title_template {
+        // The rest of the template takes the place of the prefix and suffix.
+        Some(_) => render(&build_title(
+            "",
+            &entry.title,
+            "",
+            REDDIT_TITLE_MAX_LENGTH - surrounding_length,
+        )),
+        None => Ok(build_title(
+            prefix,
+            &entry.title,
+            suffix,
+            REDDIT_TITLE_MAX_LENGTH,
+        )),
+    }
+}
+
+pub async fn submit_video_to_subreddit(
+    state: &Arc<AppState>,
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+    entry: &shared::SimpleEntry,
+    crosspost_fullname: Option<&String>,
+) -> Result<RedditSubmissionData, ApiError> {
+    require_reddit_scope(reddit_account, "submit", "submit videos")?;
+
+    let title = build_submission_title(subreddit, entry, state.title_overflow)?;
+
+    let thumbnail_url = if subreddit.thumbnail_post && crosspost_fullname.is_none() {
+        available_thumbnail_url(entry).await
+    } else {
+        None
+    };
+
+    let mut submission_form = HashMap::from([
+        ("api_type", "json"),
+        ("extension", "json"),
+        ("kind", "link"),
+        ("resubmit", "true"),
+        ("sendreplies", "false"),
+        ("sr", &subreddit.name),
+        ("title", &title),
+        ("url", thumbnail_url.as_ref().unwrap_or(&entry.link.href)),
+    ]);
+
+    if let Some(crosspost_fullname) = crosspost_fullname {
+        submission_form.insert("kind", "crosspost");
+        submission_form.insert("crosspost_fullname", crosspost_fullname);
+        submission_form.remove("url");
+    }
+
+    if let Some(flair_id) = &subreddit.flair_id {
+        submission_form.insert("flair_id", &flair_id);
+    }
+
+    if let Some(flair_text) = &subreddit.flair_text {
+        submission_form.insert("flair_text", flair_text);
+    }
+
+    if subreddit.flair_id.is_none()
+        && subreddit.flair_text.is_none()
+        && flair_required(reddit_account, subreddit).await
     {
         return Err(ApiError::BadRequest(format!(
-            "The video (title: '{}' link: {}) from '{}' (link: {}) could not be submitted, got following errors: {:#?}",
-            title, entry.link.href, entry.author.name, entry.author.uri, errors
+            "The https://reddit.com/r/{} subreddit requires a flair, set the subreddit's flair_id or flair_text",
+            subreddit.name
         )));
     }
 
+    if subreddit.nsfw {
+        submission_form.insert("nsfw", "true");
+    }
+
+    if subreddit.spoiler {
+        submission_form.insert("spoiler", "true");
+    }
+
+    let client = &HTTP_CLIENT;
+    let mut oauth_token = reddit_account.oauth_token.clone();
+
+    let submission_response = send_with_token_refresh(
+        state,
+        Some(reddit_account),
+        &mut oauth_token,
+        |access_token| {
+            client
+                .post(reddit_oauth_url("/api/submit"))
+                .bearer_auth(access_token)
+                .form(&submission_form)
+        },
+    )
+    .await?;
+
+    // Going over the API's request limit gets a 429 instead of a RATELIMIT error.
+    if submission_response.status() == StatusCode::TOO_MANY_REQUESTS {
+        let wait_secs = submission_response
+            .headers()
+            .get("x-ratelimit-reset")
+            .and_then(|reset| reset.to_str().ok())
+            .and_then(|reset| reset.parse::<f64>().ok())
+            .map(|reset| reset.ceil() as i64)
+            .unwrap_or(DEFAULT_RATE_LIMIT_WAIT_SECS);
+
+        return Err(ApiError::RateLimited(
+            format!(
+                "Reddit rate limited the requests of https://www.reddit.com/user/{} while submitting the video (title: '{}' link: {}) to the https://reddit.com/r/{} subreddit",
+                reddit_account.username, entry.title, entry.link.href, subreddit.name
+            ),
+            wait_secs + RATE_LIMIT_MARGIN_SECS,
+        ));
+    }
+
+    let submission_response = submission_response.text().await.map_err(|e| {
+        ApiError::InternalError(format!(
+            "Error accessing submission_response response text: {:?}",
+            e
+        ))
+    })?;
+
+    let submission_response: serde_json::Value = serde_json::from_str(&submission_response).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Error json deserializing submission_response response: {:?} | original response body: {}",
+                e, submission_response
+            ))
+        })?;
+
+    let submission_errors = submission_response["json"]["errors"].as_array();
+
+    if let Some(errors) = submission_errors
+        && !errors.is_empty()
+    {
+        if let Some(wait_secs) = rate_limit_wait_secs(&submission_response, errors) {
+            return Err(ApiError::RateLimited(
+                format!(
+                    "Reddit rate limited the submission of the video (title: '{}' link: {}) to the https://reddit.com/r/{} subreddit",
+                    entry.title, entry.link.href, subreddit.name
+                ),
+                wait_secs,
+            ));
+        }
+
+        // Subreddits can disallow crossposts, the video is then submitted as a link like any other subreddit.
+        if let Some(crosspost_fullname) = crosspost_fullname {
+            println!(
+                "Could not crosspost {} to the https://reddit.com/r/{} subreddit, submitting the video as a link instead. Errors: {:?}",
+                crosspost_fullname, subreddit.name, errors
+            );
+            return Box::pin(submit_video_to_subreddit(
+                state,
+                reddit_account,
+                subreddit,
+                entry,
+                None,
+            ))
+            .await;
+        }
+
+        eprintln!(
+            "The video (title: '{}' link: {}) from '{}' (link: {}) could not be submitted to the https://reddit.com/r/{} subreddit, got following errors: {:?}",
+            title, entry.link.href, entry.author.name, entry.author.uri, subreddit.name, errors
+        );
+
+        // Each error is a [code, message, field] array, the first one decides how the failure is handled.
+        return Err(ApiError::RedditRejected(
+            errors[0][0].as_str().unwrap_or("UNKNOWN").to_string(),
+            errors[0][1].as_str().unwrap_or_default().to_string(),
+        ));
+    }
+
     let submission_data: RedditSubmissionData =
         serde_json::from_value(submission_response["json"]["data"].clone())?;
 
+    // The thumbnail is already submitted, so a failed comment is only logged.
+    if thumbnail_url.is_some()
+        && let Err(e) = post_comment(
+            &oauth_token.access_token,
+            &submission_data,
+            &entry.link.href,
+        )
+        .await
+    {
+        eprintln!(
+            "Could not comment the video link on the thumbnail submission {}: {}",
+            submission_data.url, e
+        );
+    }
+
     Ok(submission_data)
 }
 
+/// Whether the subreddit rejects posts without a flair. When the requirements can't be fetched the
+/// flair is assumed to be optional, Reddit rejects the submission itself if it isn't.
+async fn flair_required(reddit_account: &RedditAccount, subreddit: &Subreddit) -> bool {
+    let post_requirements = HTTP_CLIENT
+        .get(reddit_oauth_url(&format!(
+            "/api/v1/{}/post_requirements",
+            subreddit.name
+        )))
+        .bearer_auth(&reddit_account.oauth_token.access_token)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    match post_requirements {
+        Ok(response) => response
+            .json::<serde_json::Value>()
+            .await
+            .map(|requirements| requirements["is_flair_required"].as_bool() == Some(true))
+            .unwrap_or(false),
+        Err(e) => {
+            eprintln!(
+                "Could not fetch the post requirements of the https://reddit.com/r/{} subreddit: {}",
+                subreddit.name, e
+            );
+            false
+        }
+    }
+}
+
+/// The video's thumbnail URL if YouTube has it, a HEAD request is used to not download the image.
+/// The YouTube Data API only lists existing thumbnails, so its thumbnail isn't checked.
+async fn available_thumbnail_url(entry: &shared::SimpleEntry) -> Option<String> {
+    if let Some(thumbnail_url) = &entry.thumbnail_url {
+        return Some(thumbnail_url.clone());
+    }
+
+    let thumbnail_url = youtube_thumbnail_url(&entry.yt_video_id);
+
+    match HTTP_CLIENT.head(&thumbnail_url).send().await {
+        Ok(response) if response.status().is_success() => Some(thumbnail_url),
+        Ok(response) => {
+            println!(
+                "The thumbnail {} isn't available ({}), submitting the video link instead.",
+                thumbnail_url,
+                response.status()
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!(
+                "Could not check the thumbnail {}, submitting the video link instead: {}",
+                thumbnail_url, e
+            );
+            None
+        }
+    }
+}
+
+/// Reddit error codes of submissions that fail until the subreddit or account is changed,
+/// e.g. the account being banned from the subreddit.
+pub const PERMANENT_SUBMISSION_ERRORS: [&str; 4] = [
+    "SUBREDDIT_NOTALLOWED",
+    "SUBREDDIT_NOEXIST",
+    "SUBREDDIT_NO_ACCESS",
+    "BANNED_FROM_SUBREDDIT",
+];
+/// Reddit error codes of submissions that can pass, e.g. the token not being accepted during an outage.
+pub const TRANSIENT_SUBMISSION_ERRORS: [&str; 2] = ["USER_REQUIRED", "HEAVY_LOAD"];
+
+const DEFAULT_RATE_LIMIT_WAIT_SECS: i64 = 10 * 60;
+// Reddit rounds the wait down to whole minutes, so a bit extra avoids hitting the limit again.
+const RATE_LIMIT_MARGIN_SECS: i64 = 30;
+
+/// Returns how many seconds to wait if the errors contain a `RATELIMIT` error.
+/// The wait comes from the `ratelimit` field when Reddit sends it, otherwise from
+/// the error message, e.g. "you are doing that too much. try again in 9 minutes."
+fn rate_limit_wait_secs(
+    submission_response: &serde_json::Value,
+    errors: &[serde_json::Value],
+) -> Option<i64> {
+    let rate_limit_error = errors
+        .iter()
+        .find(|error| error[0].as_str() == Some("RATELIMIT"))?;
+
+    if let Some(wait_secs) = submission_response["json"]["ratelimit"].as_f64() {
+        return Some(wait_secs.ceil() as i64 + RATE_LIMIT_MARGIN_SECS);
+    }
+
+    let message = rate_limit_error[1]
+        .as_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    let words: Vec<&str> = message.split_whitespace().collect();
+
+    let wait_secs = words.windows(2).find_map(|pair| {
+        let amount = pair[0].parse::<i64>().ok()?;
+
+        if pair[1].starts_with("second") {
+            Some(amount)
+        } else if pair[1].starts_with("minute") {
+            Some(amount * 60)
+        } else if pair[1].starts_with("hour") {
+            Some(amount * 60 * 60)
+        } else {
+            None
+        }
+    });
+
+    Some(wait_secs.unwrap_or(DEFAULT_RATE_LIMIT_WAIT_SECS) + RATE_LIMIT_MARGIN_SECS)
+}
+
+/// Comments on the submission with the subreddit's comment template, if it has one,
+/// and stickies the comment when the subreddit has `sticky_comment` set.
+pub async fn comment_on_submission(
+    reddit_account: &RedditAccount,
+    subreddit: &Subreddit,
+    submission: &RedditSubmissionData,
+    entry: &shared::SimpleEntry,
+) -> Result<(), ApiError> {
+    let comment_template = if let Some(template) = &subreddit.comment_template {
+        template
+    } else {
+        return Ok(());
+    };
+
+    require_reddit_scope(reddit_account, "submit", "comment")?;
+
+    let comment_text = render_title_template(comment_template, &entry.title, entry)?;
+
+    let comment_id = post_comment(
+        &reddit_account.oauth_token.access_token,
+        submission,
+        &comment_text,
+    )
+    .await?;
+
+    if !subreddit.sticky_comment {
+        return Ok(());
+    }
+
+    require_reddit_scope(reddit_account, "modposts", "sticky the comment")?;
+
+    let client = &HTTP_CLIENT;
+
+    let distinguish_response = client
+        .post(reddit_oauth_url("/api/distinguish"))
+        .bearer_auth(&reddit_account.oauth_token.access_token)
+        .form(&[
+            ("api_type", "json"),
+            ("id", &comment_id),
+            ("how", "yes"),
+            ("sticky", "true"),
+        ])
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    if let Some(errors) = distinguish_response["json"]["errors"].as_array()
+        && !errors.is_empty()
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Got following errors while trying to sticky the comment {} on the submission {}, the Reddit account needs to be a moderator of the https://www.reddit.com/r/{} subreddit: {:#?}",
+            comment_id, submission.url, subreddit.name, errors
+        )));
+    }
+
+    println!(
+        "Stickied the comment {} on the submission {}",
+        comment_id, submission.url
+    );
+
+    Ok(())
+}
+
+/// Reddit's limit on the length of a comment.
+const REDDIT_COMMENT_MAX_CHARS: usize = 10000;
+
+/// The description's non-empty lines as paragraphs, at most `max_lines` of them. A shortened
+/// description ends with a link to the video for the rest.
+fn description_comment_text(description: &str, video_url: &str, max_lines: Option<u32>) -> String {
+    let lines: Vec<&str> = description
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+
+    let kept_lines = max_lines.map_or(lines.len(), |max_lines| lines.len().min(max_lines as usize));
+    let mut text = lines[..kept_lines].join("\n\n");
+    let read_more = format!("\n\n[Read more on YouTube]({})", video_url);
+
+    let max_chars = REDDIT_COMMENT_MAX_CHARS - read_more.chars().count();
+    let shortened = kept_lines < lines.len() || text.chars().count() > max_chars;
+
+    if text.chars().count() > max_chars {
+        text = text.chars().take(max_chars - 1).collect();
+        text.push('…');
+    }
+
+    if shortened {
+        text.push_str(&read_more);
+    }
+
+    text
+}
+
+/// Comments the video's description on the submission, needs the YouTube API key to fetch it.
+pub async fn comment_video_description(
+    state: &Arc<AppState>,
+    reddit_account: &RedditAccount,
+    submission: &RedditSubmissionData,
+    entry: &shared::SimpleEntry,
+    description_comment: &DescriptionComment,
+) -> Result<(), ApiError> {
+    let Some(api_key) = &state.youtube_api_key else {
+        return Err(ApiError::BadRequest(
+            "The video description can only be fetched with a YOUTUBE_API_KEY".into(),
+        ));
+    };
+
+    require_reddit_scope(reddit_account, "submit", "comment")?;
+
+    let description = fetch_video_description(api_key, &entry.yt_video_id)
+        .await?
+        .unwrap_or_default();
+
+    if description.trim().is_empty() {
+        println!(
+            "The video {} has no description to comment on the submission {}.",
+            entry.yt_video_id, submission.url
+        );
+        return Ok(());
+    }
+
+    let comment_text = description_comment_text(
+        &description,
+        &entry.link.href,
+        description_comment.max_lines,
+    );
+
+    post_comment(
+        &reddit_account.oauth_token.access_token,
+        submission,
+        &comment_text,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Comments the text on the submission, returns the comment's fullname.
+async fn post_comment(
+    access_token: &str,
+    submission: &RedditSubmissionData,
+    text: &str,
+) -> Result<String, ApiError> {
+    let comment_response = HTTP_CLIENT
+        .post(reddit_oauth_url("/api/comment"))
+        .bearer_auth(access_token)
+        .form(&[
+            ("api_type", "json"),
+            ("thing_id", &submission.id),
+            ("text", text),
+        ])
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    if let Some(errors) = comment_response["json"]["errors"].as_array()
+        && !errors.is_empty()
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Got following errors while trying to comment on the submission {}: {:#?}",
+            submission.url, errors
+        )));
+    }
+
+    let comment_id = comment_response["json"]["data"]["things"][0]["data"]["name"]
+        .as_str()
+        .ok_or(ApiError::InternalError(format!(
+            "The comment id is missing from the https://oauth.reddit.com/api/comment response: {}",
+            comment_response
+        )))?
+        .to_string();
+
+    println!(
+        "Commented on the submission {} with the comment: {}",
+        submission.url, comment_id
+    );
+
+    Ok(comment_id)
+}
+
 pub async fn moderate_submission(
     state: &Arc<AppState>,
     reddit_account: &RedditAccount,
     subreddit: &Subreddit,
 ) -> Result<(), ApiError> {
+    // Skipped instead of failing, the submission itself already went through.
+    if let Err(e) = require_reddit_scope(reddit_account, "modposts", "sticky submissions") {
+        println!("Skipping the moderation: {}", e);
+        return Ok(());
+    }
+
     let subreddit_submissions =
         fetch_submissions_on_subreddit(&state.db_pool, subreddit.id).await?;
 
@@ -523,82 +1577,126 @@ pub async fn moderate_submission(
         reddit_account.username, subreddit.name
     );
 
-    // subreddit_submissions is ordered by timestamp ascending
-    let oldest_stickied_submission = subreddit_submissions.iter().find(|s| s.stickied);
+    let sticky_changes = plan_sticky_changes(&subreddit_submissions);
 
-    let previous_submission = subreddit_submissions
-        .iter()
-        .filter(|s| !s.stickied)
-        .rev() // Start from the end (the newest)
-        .nth(1); // Skip index 0 (the last), take index 1 (the previous submission)
+    if sticky_changes.unsticky.is_empty() && sticky_changes.sticky.is_empty() {
+        println!("The stickied submissions are already up to date.");
+        return Ok(());
+    }
 
-    let (oldest_stickied_submission, previous_submission) = if let Some(old) =
-        oldest_stickied_submission
-        && let Some(prev) = previous_submission
-    {
-        (old, prev)
-    } else {
+    // A token refreshed by one of the requests is reused by the following ones.
+    let mut oauth_token = reddit_account.oauth_token.clone();
+
+    // Unsticky first so the slots are free when stickying.
+    for submission in sticky_changes.unsticky {
+        println!("Now unstickying the submission: {:?}", submission);
+        set_reddit_submission_sticky_state(
+            state,
+            reddit_account,
+            &mut oauth_token,
+            &submission.id,
+            &false,
+            None,
+        )
+        .await?;
+        println!("Successfully unstickied the submission");
+    }
+
+    for (submission, slot) in sticky_changes.sticky {
         println!(
-            "The Reddit account https://www.reddit.com/u/{} has no submission on the https://www.reddit.com/r/{} subreddit.",
-            reddit_account.username, subreddit.name
+            "Now stickying the submission: {:?} in sticky slot {}",
+            submission, slot
         );
-        return Ok(());
-    };
+        set_reddit_submission_sticky_state(
+            state,
+            reddit_account,
+            &mut oauth_token,
+            &submission.id,
+            &true,
+            Some(slot),
+        )
+        .await?;
+        println!("Successfully stickied the submission");
+    }
 
-    println!(
-        "Current oldest stickied submission: {:?} | previous submission: {:?}",
-        oldest_stickied_submission, previous_submission
-    );
+    Ok(())
+}
 
-    println!("Now unstickying the oldest stickied submission");
-    set_reddit_submission_sticky_state(
-        &state.db_pool,
-        &reddit_account.oauth_token,
-        &oldest_stickied_submission.id,
-        &false,
-    )
-    .await?;
-    println!("Successfully unstickied the oldest stickied submission");
+/// Reddit allows at most two stickied posts per subreddit.
+const REDDIT_STICKY_SLOTS: usize = 2;
 
-    println!("Now stickying the previous submission");
-    set_reddit_submission_sticky_state(
-        &state.db_pool,
-        &reddit_account.oauth_token,
-        &previous_submission.id,
-        &true,
-    )
-    .await?;
-    println!("Successfully stickyied the previous submission");
+struct StickyChanges<'a> {
+    unsticky: Vec<&'a RedditSubmission>,
+    /// The submissions to sticky with their sticky slot, 1 is the top slot.
+    sticky: Vec<(&'a RedditSubmission, usize)>,
+}
 
-    Ok(())
+/// The newest submission is already at the top of the subreddit, so the sticky slots are
+/// used for the submissions before it, the most recent one in the top slot.
+/// `submissions` has to be ordered by timestamp ascending.
+fn plan_sticky_changes(submissions: &[RedditSubmission]) -> StickyChanges<'_> {
+    let wanted: Vec<&RedditSubmission> = submissions
+        .iter()
+        .rev()
+        .skip(1)
+        .take(REDDIT_STICKY_SLOTS)
+        .collect();
+
+    let unsticky = submissions
+        .iter()
+        .filter(|s| s.stickied && !wanted.iter().any(|w| w.id == s.id))
+        .collect();
+
+    let sticky = wanted
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !s.stickied)
+        .map(|(index, s)| (*s, index + 1))
+        .collect();
+
+    StickyChanges { unsticky, sticky }
 }
 
 async fn set_reddit_submission_sticky_state(
-    pool: &Pool<Sqlite>,
-    oauth_token: &RedditOAuthToken,
+    app_state: &Arc<AppState>,
+    reddit_account: &RedditAccount,
+    oauth_token: &mut RedditOAuthToken,
     submission_id: &String,
     state: &bool,
+    slot: Option<usize>,
 ) -> Result<(), ApiError> {
     let client = &HTTP_CLIENT;
 
-    let sticky_response = client
-        .post("https://oauth.reddit.com/api/set_subreddit_sticky")
-        .bearer_auth(&oauth_token.access_token)
-        .form(&[
-            ("api_type", "json"),
-            ("id", submission_id),
-            ("state", &state.to_string()),
-        ])
-        .send()
-        .await?
-        .text()
-        .await
-        .map_err(|e| {
-            ApiError::InternalError(format!(
-                "Error accessing sticky_response response text: {:?}",
-                e
-            ))
-        })?;
+    let mut sticky_form = vec![
+        ("api_type", "json".to_string()),
+        ("id", submission_id.clone()),
+        ("state", state.to_string()),
+    ];
+
+    if let Some(slot) = slot {
+        sticky_form.push(("num", slot.to_string()));
+    }
+
+    let sticky_response = send_with_token_refresh(
+        app_state,
+        Some(reddit_account),
+        oauth_token,
+        |access_token| {
+            client
+                .post(reddit_oauth_url("/api/set_subreddit_sticky"))
+                .bearer_auth(access_token)
+                .form(&sticky_form)
+        },
+    )
+    .await?
+    .text()
+    .await
+    .map_err(|e| {
+        ApiError::InternalError(format!(
+            "Error accessing sticky_response response text: {:?}",
+            e
+        ))
+    })?;
 
     let sticky_response: serde_json::Value = serde_json::from_str(&sticky_response).map_err(|e| {
             ApiError::InternalError(format!(
@@ -620,7 +1718,7 @@ async fn set_reddit_submission_sticky_state(
         )));
     }
 
-    update_reddit_submission_sticky_state(&pool, &submission_id, &state).await?;
+    update_reddit_submission_sticky_state(&app_state.db_pool, &submission_id, &state).await?;
 
     Ok(())
 }
@@ -639,8 +1737,8 @@ struct ModerateSubmissionsForm {
         description = "Reddit moderate submissions for an Reddit account and subreddit",
         responses(
             (status = 303, description = "Reddit moderate submissions successfully handled"),
-            (status = 400, description = "Invalid form data."),
-            (status = 500, description = "Internal server error."),
+            (status = 400, description = "Invalid form data.", body = ErrorResponse),
+            (status = 500, description = "Internal server error.", body = ErrorResponse),
         ),
         tag = "reddit"
     )]
@@ -699,3 +1797,791 @@ async fn moderate_submissions_for_reddit_account_and_subreddit(
 
     Ok(Redirect::to(&state.base_url))
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path},
+    };
+
+    use super::*;
+    use crate::{
+        infrastructure::Settings,
+        server::{
+            repository::{reddit_submission_saved, save_form_data},
+            shared::{FormType, MOCK_REDDIT_URL, RedditAuthorizeDuration},
+            test_support::{self, simple_entry, subreddit},
+        },
+    };
+
+    fn oauth_token(scope: &str) -> RedditOAuthToken {
+        RedditOAuthToken {
+            access_token: "access_token".to_string(),
+            token_type: "bearer".to_string(),
+            expires_in: 86400,
+            scope: scope.to_string(),
+            refresh_token: Some("refresh_token".to_string()),
+        }
+    }
+
+    fn submitting_account() -> RedditAccount {
+        RedditAccount {
+            id: Uuid::now_v7().to_string(),
+            username: "bot".to_string(),
+            oauth_token: oauth_token("identity submit"),
+            moderate_submissions: false,
+        }
+    }
+
+    fn subreddit_settings() -> Subreddit {
+        Subreddit {
+            id: 1,
+            name: "videos".to_string(),
+            title_prefix: None,
+            title_suffix: None,
+            title_template: None,
+            flair_id: None,
+            flair_text: None,
+            nsfw: false,
+            spoiler: false,
+            min_account_age_days: None,
+            min_karma: None,
+            comment_template: None,
+            sticky_comment: false,
+            thumbnail_post: false,
+            posting_disabled_reason: None,
+        }
+    }
+
+    async fn mock_reddit() -> MockServer {
+        let reddit = MockServer::start().await;
+        MOCK_REDDIT_URL.set(Some(reddit.uri()));
+        reddit
+    }
+
+    async fn mock_submit_response(reddit: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .and(header("authorization", "Bearer access_token"))
+            .respond_with(response)
+            .mount(reddit)
+            .await;
+    }
+
+    async fn submit_to_videos(state: &Arc<AppState>) -> Result<RedditSubmissionData, ApiError> {
+        let subreddit = subreddit(state, "videos").await;
+
+        submit_video_to_subreddit(
+            state,
+            &submitting_account(),
+            &subreddit,
+            &simple_entry("video1", "A video"),
+            None,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn submitted_video_returns_the_submission() {
+        let reddit = mock_reddit().await;
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .and(body_string_contains("sr=videos"))
+            .and(body_string_contains("kind=link"))
+            .and(body_string_contains("title=A+video"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [],
+                    "data": {
+                        "url": "https://www.reddit.com/r/videos/comments/abc/a_video/",
+                        "id": "abc",
+                        "name": "t3_abc"
+                    }
+                }
+            })))
+            .expect(1)
+            .mount(&reddit)
+            .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let submission = submit_to_videos(&state).await.unwrap();
+
+        assert_eq!(submission.id, "t3_abc");
+        assert_eq!(
+            submission.url,
+            "https://www.reddit.com/r/videos/comments/abc/a_video/"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_submission_returns_reddits_error() {
+        let reddit = mock_reddit().await;
+        mock_submit_response(
+            &reddit,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [["SUBREDDIT_NOTALLOWED", "you aren't allowed to post there.", "sr"]]
+                }
+            })),
+        )
+        .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let result = submit_to_videos(&state).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::RedditRejected(code, message))
+                if code == "SUBREDDIT_NOTALLOWED" && message == "you aren't allowed to post there."
+        ));
+    }
+
+    #[tokio::test]
+    async fn ratelimit_error_returns_the_wait() {
+        let reddit = mock_reddit().await;
+        mock_submit_response(
+            &reddit,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [["RATELIMIT", "you are doing that too much. try again in 9 minutes.", "ratelimit"]],
+                    "ratelimit": 521.5
+                }
+            })),
+        )
+        .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let result = submit_to_videos(&state).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::RateLimited(_, wait_secs)) if wait_secs == 522 + RATE_LIMIT_MARGIN_SECS
+        ));
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_returns_the_wait_until_the_reset() {
+        let reddit = mock_reddit().await;
+        mock_submit_response(
+            &reddit,
+            ResponseTemplate::new(429)
+                .insert_header("x-ratelimit-reset", "120")
+                .set_body_json(json!({ "message": "Too Many Requests", "error": 429 })),
+        )
+        .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let result = submit_to_videos(&state).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::RateLimited(_, wait_secs)) if wait_secs == 120 + RATE_LIMIT_MARGIN_SECS
+        ));
+    }
+
+    /// The flair fields of the submission form sent for a subreddit with the flair id and text.
+    async fn submitted_flair(
+        flair_id: Option<&str>,
+        flair_text: Option<&str>,
+    ) -> (Option<String>, Option<String>) {
+        let reddit = mock_reddit().await;
+        mock_submit_response(
+            &reddit,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "json": {
+                    "errors": [],
+                    "data": {
+                        "url": "https://www.reddit.com/r/videos/comments/abc/a_video/",
+                        "name": "t3_abc"
+                    }
+                }
+            })),
+        )
+        .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let mut subreddit = subreddit(&state, "videos").await;
+        subreddit.flair_id = flair_id.map(str::to_string);
+        subreddit.flair_text = flair_text.map(str::to_string);
+
+        submit_video_to_subreddit(
+            &state,
+            &submitting_account(),
+            &subreddit,
+            &simple_entry("video1", "A video"),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let requests = reddit.received_requests().await.unwrap();
+        let submission = requests
+            .iter()
+            .find(|request| request.url.path() == "/api/submit")
+            .unwrap();
+        let submission_form: HashMap<String, String> =
+            url::form_urlencoded::parse(&submission.body)
+                .into_owned()
+                .collect();
+
+        (
+            submission_form.get("flair_id").cloned(),
+            submission_form.get("flair_text").cloned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn flair_id_and_text_are_sent_when_set() {
+        assert_eq!(
+            submitted_flair(Some("flair-template"), None).await,
+            (Some("flair-template".to_string()), None)
+        );
+        assert_eq!(
+            submitted_flair(None, Some("Music video")).await,
+            (None, Some("Music video".to_string()))
+        );
+        assert_eq!(
+            submitted_flair(Some("flair-template"), Some("Music video")).await,
+            (
+                Some("flair-template".to_string()),
+                Some("Music video".to_string())
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn flairless_submission_to_a_subreddit_requiring_a_flair_is_refused() {
+        let reddit = mock_reddit().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/videos/post_requirements"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "is_flair_required": true })),
+            )
+            .mount(&reddit)
+            .await;
+        mock_submit_response(&reddit, ResponseTemplate::new(500)).await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let result = submit_to_videos(&state).await;
+
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+        assert_eq!(
+            reddit
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .filter(|request| request.url.path() == "/api/submit")
+                .count(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn account_below_the_karma_minimum_is_skipped() {
+        let reddit = mock_reddit().await;
+        let created_utc = (Utc::now() - chrono::Duration::days(400)).timestamp();
+        for (access_token, total_karma) in [("low_karma", 50), ("high_karma", 500)] {
+            Mock::given(method("GET"))
+                .and(path("/api/v1/me"))
+                .and(header("authorization", format!("Bearer {}", access_token)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "name": access_token,
+                    "created_utc": created_utc,
+                    "total_karma": total_karma
+                })))
+                // The stats are cached, the second check doesn't ask Reddit again.
+                .expect(1)
+                .mount(&reddit)
+                .await;
+        }
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let mut subreddit = subreddit(&state, "videos").await;
+        subreddit.min_account_age_days = Some(30);
+        subreddit.min_karma = Some(100);
+
+        for (username, meets_requirements) in [("low_karma", false), ("high_karma", true)] {
+            let mut oauth_token = oauth_token("identity submit");
+            oauth_token.access_token = username.to_string();
+            let reddit_account = RedditAccount {
+                id: test_support::reddit_account(&state, username).await,
+                username: username.to_string(),
+                oauth_token,
+                moderate_submissions: false,
+            };
+
+            for _ in 0..2 {
+                assert_eq!(
+                    reddit_account_meets_subreddit_requirements(
+                        &state,
+                        &reddit_account,
+                        &subreddit
+                    )
+                    .await
+                    .unwrap(),
+                    meets_requirements,
+                    "{}",
+                    username
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshed_token_comes_from_the_refresh_token_grant() {
+        let reddit = mock_reddit().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/access_token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=refresh_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "new_access_token",
+                "token_type": "bearer",
+                "expires_in": 86400,
+                "scope": "identity submit"
+            })))
+            .expect(1)
+            .mount(&reddit)
+            .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let oauth_token = refresh_reddit_oauth_token(&state, &"refresh_token".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(oauth_token.access_token, "new_access_token");
+        assert!(oauth_token.refresh_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn callback_saves_the_account_and_imports_its_submissions() {
+        let reddit = mock_reddit().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/access_token"))
+            .and(body_string_contains("grant_type=authorization_code"))
+            .and(body_string_contains("code=callback_code"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::to_value(oauth_token("identity submit history")).unwrap(),
+            ))
+            .mount(&reddit)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "name": "bot" })))
+            .mount(&reddit)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user/bot/submitted"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": {
+                    "after": null,
+                    "children": [{
+                        "data": {
+                            "name": "t3_old",
+                            "url": "https://www.youtube.com/watch?v=video0",
+                            "subreddit": "videos",
+                            "link_flair_template_id": null,
+                            "created_utc": 1767000000.0,
+                            "stickied": false,
+                            "is_self": false
+                        }
+                    }]
+                }
+            })))
+            .mount(&reddit)
+            .await;
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let state_key = Uuid::now_v7().to_string();
+        let reddit_authorization = RedditAuthorization {
+            r#type: FormType::Reddit,
+            moderate_submissions: false,
+            duration: RedditAuthorizeDuration::Permanent,
+            scopes: "identity submit history".to_string(),
+        };
+        save_form_data(
+            &state.db_pool,
+            &state_key,
+            &serde_json::to_string(&reddit_authorization).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let response = reddit_callback(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(RedditCallback {
+                code: "callback_code".to_string(),
+                state: state_key,
+                error: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(
+            fetch_reddit_account_id_by_username(&state.db_pool, &"bot".to_string())
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            reddit_submission_saved(&state.db_pool, &"t3_old".to_string())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn denied_callback_gets_an_error_page() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+
+        let response = reddit_callback(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(RedditCallback {
+                code: String::new(),
+                state: Uuid::now_v7().to_string(),
+                error: Some(RedditCallbackErrors::AccessDenied),
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn build_title_only_shortens_the_title() {
+        assert_eq!(
+            build_title("[Tag] ", "A video", " (live)", 300),
+            "[Tag] A video (live)"
+        );
+        assert_eq!(
+            build_title("[Tag] ", "A long video", " (live)", 20),
+            "[Tag] A long… (live)"
+        );
+        assert_eq!(build_title("", "A video title", "", 8), "A video…");
+        assert_eq!(build_title("ab", "title", "cd", 4), "ab…cd");
+    }
+
+    #[test]
+    fn build_title_keeps_a_title_that_fits_exactly() {
+        assert_eq!(
+            build_title("[Tag] ", "A video", " (live)", 20),
+            "[Tag] A video (live)"
+        );
+        assert_eq!(
+            build_title("[Tag] ", "A video", " (live)", 19),
+            "[Tag] A vid… (live)"
+        );
+        assert_eq!(build_title("", "", "", 0), "");
+    }
+
+    #[test]
+    fn build_title_counts_characters_not_bytes() {
+        assert_eq!(build_title("", "Blåbærsyltetøy", "", 14), "Blåbærsyltetøy");
+        assert_eq!(build_title("", "Blåbærsyltetøy", "", 7), "Blåbær…");
+        assert_eq!(build_title("🎵 ", "Song title", "", 8), "🎵 Song…");
+    }
+
+    #[test]
+    fn build_title_trims_the_cut_before_the_ellipsis() {
+        assert_eq!(build_title("", "A video title", "", 9), "A video…");
+        assert_eq!(
+            build_title("[Tag] ", "One two three", " [Official]", 26),
+            "[Tag] One two… [Official]"
+        );
+    }
+
+    #[test]
+    fn build_title_keeps_the_suffix_of_a_title_over_reddits_limit() {
+        let title = build_title(
+            "[Video] ",
+            &"x".repeat(400),
+            " [Official]",
+            REDDIT_TITLE_MAX_LENGTH,
+        );
+
+        assert_eq!(title.chars().count(), REDDIT_TITLE_MAX_LENGTH);
+        assert!(title.starts_with("[Video] xxx"));
+        assert!(title.ends_with("x… [Official]"));
+    }
+
+    #[test]
+    fn rate_limit_wait_comes_from_the_field_or_the_message() {
+        let error = |message: &str| json!([["RATELIMIT", message, "ratelimit"]]);
+        let wait = |response: serde_json::Value, errors: serde_json::Value| {
+            rate_limit_wait_secs(&response, errors.as_array().unwrap())
+        };
+
+        assert_eq!(
+            wait(json!({ "json": { "ratelimit": 59.2 } }), error("")),
+            Some(60 + RATE_LIMIT_MARGIN_SECS)
+        );
+        assert_eq!(
+            wait(
+                json!({}),
+                error("you are doing that too much. try again in 9 minutes.")
+            ),
+            Some(9 * 60 + RATE_LIMIT_MARGIN_SECS)
+        );
+        assert_eq!(
+            wait(json!({}), error("Try again in 45 seconds.")),
+            Some(45 + RATE_LIMIT_MARGIN_SECS)
+        );
+        assert_eq!(
+            wait(json!({}), error("try again in 1 hour.")),
+            Some(60 * 60 + RATE_LIMIT_MARGIN_SECS)
+        );
+        assert_eq!(
+            wait(json!({}), error("try again later")),
+            Some(DEFAULT_RATE_LIMIT_WAIT_SECS + RATE_LIMIT_MARGIN_SECS)
+        );
+        assert_eq!(
+            wait(
+                json!({}),
+                json!([["TOO_LONG", "this is too long", "title"]])
+            ),
+            None
+        );
+    }
+
+    /// The ids to unsticky and the `(id, slot)` pairs to sticky for the submissions, oldest first.
+    fn sticky_changes(submissions: &[(&str, bool)]) -> (Vec<String>, Vec<(String, usize)>) {
+        let submissions: Vec<RedditSubmission> = submissions
+            .iter()
+            .map(|(id, stickied)| RedditSubmission {
+                id: id.to_string(),
+                stickied: *stickied,
+            })
+            .collect();
+
+        let changes = plan_sticky_changes(&submissions);
+
+        (
+            changes.unsticky.iter().map(|s| s.id.clone()).collect(),
+            changes
+                .sticky
+                .iter()
+                .map(|(s, slot)| (s.id.clone(), *slot))
+                .collect(),
+        )
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn slots(slots: &[(&str, usize)]) -> Vec<(String, usize)> {
+        slots
+            .iter()
+            .map(|(id, slot)| (id.to_string(), *slot))
+            .collect()
+    }
+
+    #[test]
+    fn no_existing_stickies_fill_both_slots() {
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", false),
+                ("t3_2", false),
+                ("t3_3", false),
+                ("t3_4", false)
+            ]),
+            (ids(&[]), slots(&[("t3_3", 1), ("t3_2", 2)]))
+        );
+        assert_eq!(sticky_changes(&[("t3_1", false)]), (ids(&[]), slots(&[])));
+        assert_eq!(
+            sticky_changes(&[("t3_1", false), ("t3_2", false)]),
+            (ids(&[]), slots(&[("t3_1", 1)]))
+        );
+    }
+
+    #[test]
+    fn one_existing_sticky_is_kept_or_replaced() {
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", false),
+                ("t3_2", false),
+                ("t3_3", true),
+                ("t3_4", false)
+            ]),
+            (ids(&[]), slots(&[("t3_2", 2)]))
+        );
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", true),
+                ("t3_2", false),
+                ("t3_3", false),
+                ("t3_4", false)
+            ]),
+            (ids(&["t3_1"]), slots(&[("t3_3", 1), ("t3_2", 2)]))
+        );
+    }
+
+    #[test]
+    fn two_existing_stickies_only_change_the_outdated_ones() {
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", false),
+                ("t3_2", true),
+                ("t3_3", true),
+                ("t3_4", false)
+            ]),
+            (ids(&[]), slots(&[]))
+        );
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", true),
+                ("t3_2", false),
+                ("t3_3", true),
+                ("t3_4", false)
+            ]),
+            (ids(&["t3_1"]), slots(&[("t3_2", 2)]))
+        );
+        assert_eq!(
+            sticky_changes(&[
+                ("t3_1", true),
+                ("t3_2", true),
+                ("t3_3", false),
+                ("t3_4", false),
+                ("t3_5", false),
+            ]),
+            (ids(&["t3_1", "t3_2"]), slots(&[("t3_4", 1), ("t3_3", 2)]))
+        );
+    }
+
+    #[test]
+    fn video_ids_are_read_from_every_youtube_url_form() {
+        let video_id = |url: &str| youtube_url_to_video_id(&Url::parse(url).unwrap());
+
+        for url in [
+            "https://www.youtube.com/watch?v=video1",
+            "https://youtube.com/watch?feature=share&v=video1",
+            "https://m.youtube.com/watch?v=video1&t=30",
+            "https://youtu.be/video1",
+            "https://youtu.be/video1?t=30",
+            "https://www.youtube.com/shorts/video1",
+            "https://www.youtube.com/shorts/video1/",
+        ] {
+            assert_eq!(video_id(url).as_deref(), Some("video1"), "{}", url);
+        }
+
+        assert_eq!(video_id("https://www.youtube.com/channel/UCBR8-60"), None);
+        assert_eq!(video_id("https://vimeo.com/12345"), None);
+        assert_eq!(video_id("https://notyoutube.example/watch?v=video1"), None);
+    }
+
+    #[test]
+    fn only_youtube_link_posts_are_imported_from_a_mixed_listing() {
+        let submission = |id: &str, url: &str, is_self: bool| {
+            serde_json::from_value::<SubmissionJsonData>(json!({
+                "name": id,
+                "url": url,
+                "subreddit": "videos",
+                "link_flair_template_id": null,
+                "created_utc": 1767000000.4,
+                "stickied": false,
+                "is_self": is_self
+            }))
+            .unwrap()
+        };
+        let submissions = [
+            submission("t3_link", "https://www.youtube.com/watch?v=video1", false),
+            submission(
+                "t3_self",
+                "https://www.reddit.com/r/videos/comments/self/",
+                true,
+            ),
+            submission("t3_self_video", "https://youtu.be/video2", true),
+            submission("t3_other", "https://vimeo.com/12345", false),
+            submission("t3_short", "https://youtu.be/video3", false),
+        ];
+
+        let imported: Vec<(String, String, i64)> = submissions
+            .iter()
+            .filter_map(to_submission_data)
+            .map(|submission| (submission.id, submission.video_id, submission.timestamp))
+            .collect();
+
+        assert_eq!(
+            imported,
+            vec![
+                ("t3_link".to_string(), "video1".to_string(), 1767000000),
+                ("t3_short".to_string(), "video3".to_string(), 1767000000),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_links_give_the_id_without_the_slash_query_or_fragment() {
+        for url in [
+            "https://youtu.be/video1/",
+            "https://youtu.be/video1?t=30",
+            "https://youtu.be/video1#t=30",
+            "https://youtu.be/video1/?si=share&t=30#comments",
+            "https://youtu.be//video1",
+        ] {
+            assert_eq!(
+                youtube_url_to_video_id(&Url::parse(url).unwrap()).as_deref(),
+                Some("video1"),
+                "{}",
+                url
+            );
+        }
+
+        assert_eq!(
+            youtube_url_to_video_id(&Url::parse("https://youtu.be/").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn submission_title_uses_the_template_over_the_prefix_and_suffix() {
+        let entry = simple_entry("video1", "A video");
+        let mut subreddit = subreddit_settings();
+        subreddit.title_prefix = Some("[Video] ".to_string());
+        subreddit.title_suffix = Some(" (new)".to_string());
+
+        assert_eq!(
+            build_submission_title(&subreddit, &entry, TitleOverflow::Truncate).unwrap(),
+            "[Video] A video (new)"
+        );
+
+        subreddit.title_template = Some("[{channel_name}] {title} ({published})".to_string());
+
+        assert_eq!(
+            build_submission_title(&subreddit, &entry, TitleOverflow::Truncate).unwrap(),
+            "[Channel] A video (2026-01-02)"
+        );
+    }
+
+    #[test]
+    fn overlong_title_is_shortened_to_reddits_limit_or_rejected() {
+        let entry = simple_entry("video1", &"A very long video title ".repeat(20));
+        let mut subreddit = subreddit_settings();
+        subreddit.title_prefix = Some("[Video] ".to_string());
+        subreddit.title_suffix = Some(" [Official]".to_string());
+
+        let title = build_submission_title(&subreddit, &entry, TitleOverflow::Truncate).unwrap();
+
+        assert_eq!(title.chars().count(), REDDIT_TITLE_MAX_LENGTH);
+        assert!(title.starts_with("[Video] A very long video title"));
+        assert!(title.ends_with("… [Official]"));
+
+        subreddit.title_prefix = None;
+        subreddit.title_suffix = None;
+        subreddit.title_template = Some("{title} ({published})".to_string());
+
+        let title = build_submission_title(&subreddit, &entry, TitleOverflow::Truncate).unwrap();
+
+        assert_eq!(title.chars().count(), REDDIT_TITLE_MAX_LENGTH);
+        assert!(title.ends_with("… (2026-01-02)"));
+
+        assert!(matches!(
+            build_submission_title(&subreddit, &entry, TitleOverflow::Reject),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
@@ -5,17 +5,58 @@ use uuid::Uuid;
 use crate::server::{
     ApiError,
     shared::{
-        LinkedSubscription, RedditAccountDTO, RedditOAuthToken, Subreddit, Verification,
+        FailedSubmissionStatus, LinkedSubscription, RedditAccountDTO, RedditAccountStats,
+        RedditOAuthToken, SelectOption, Subreddit, SubscriptionStatus, Verification,
         VerificationMode, YouTubeSubscription,
     },
 };
 
+// SQLite primary result codes, see https://www.sqlite.org/rescode.html
+const SQLITE_BUSY: i64 = 5;
+const SQLITE_LOCKED: i64 = 6;
+const SQLITE_IOERR: i64 = 10;
+const SQLITE_FULL: i64 = 13;
+const SQLITE_CANTOPEN: i64 = 14;
+
 impl From<sqlx::Error> for ApiError {
     fn from(error: sqlx::Error) -> Self {
-        ApiError::InternalError(format!("SQL query error: {}", error))
+        match &error {
+            sqlx::Error::Io(_) => ApiError::DatabaseStorage(format!("SQL IO error: {}", error)),
+            sqlx::Error::PoolTimedOut => {
+                ApiError::DatabaseBusy(format!("SQL query error: {}", error))
+            }
+            sqlx::Error::Database(db_error) => {
+                // Extended result codes keep the primary result code in the lowest byte.
+                let primary_code = db_error
+                    .code()
+                    .and_then(|code| code.parse::<i64>().ok())
+                    .map(|code| code & 0xff);
+
+                match primary_code {
+                    Some(SQLITE_FULL | SQLITE_IOERR | SQLITE_CANTOPEN) => {
+                        ApiError::DatabaseStorage(format!("SQL storage error: {}", error))
+                    }
+                    Some(SQLITE_BUSY | SQLITE_LOCKED) => {
+                        ApiError::DatabaseBusy(format!("SQL query error: {}", error))
+                    }
+                    _ => ApiError::InternalError(format!("SQL query error: {}", error)),
+                }
+            }
+            _ => ApiError::InternalError(format!("SQL query error: {}", error)),
+        }
     }
 }
 
+/// Constraint violations are only expected by some queries, e.g. a concurrent insert of the same
+/// row, so they're handled where they happen instead of in `From<sqlx::Error>`.
+fn is_unique_violation(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(db_error) if db_error.is_unique_violation())
+}
+
+fn is_foreign_key_violation(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(db_error) if db_error.is_foreign_key_violation())
+}
+
 pub struct Subscription {
     pub id: String,
     pub channel_id: String,
@@ -23,6 +64,13 @@ pub struct Subscription {
     pub hmac_secret: String,
     pub expires: Option<i64>,
     pub post_shorts: bool,
+    pub min_duration_seconds: Option<i64>,
+    pub title_filters: Option<String>,
+    pub posting_cutoff: Option<i64>,
+    pub status: SubscriptionStatus,
+    pub crossposting: Option<String>,
+    pub min_post_interval_seconds: Option<i64>,
+    pub description_comment: Option<String>,
 }
 
 pub async fn get_subscription_details(
@@ -38,7 +86,14 @@ pub async fn get_subscription_details(
             s.channel_name,
             s.hmac_secret,
             s.expires,
-            s.post_shorts as "post_shorts: bool"
+            s.post_shorts as "post_shorts: bool",
+            s.min_duration_seconds,
+            s.title_filters,
+            s.posting_cutoff,
+            s.status as "status: SubscriptionStatus",
+            s.crossposting,
+            s.min_post_interval_seconds,
+            s.description_comment
         FROM
             subscriptions s
         WHERE
@@ -52,30 +107,34 @@ pub async fn get_subscription_details(
     Ok(subscription)
 }
 
+/// Returns the id the form data is stored under. When the idempotency key was already seen the
+/// existing row keeps its id and gets the new data, so repeated submissions don't add rows.
 pub async fn save_form_data(
     pool: &Pool<Sqlite>,
     key: &String,
     data: &String,
-) -> Result<(), ApiError> {
-    let save_form_data_result = query!(
+    idempotency_key: Option<&String>,
+) -> Result<String, ApiError> {
+    let created_at = Utc::now().timestamp();
+
+    let form_id = query_scalar!(
         r#"
-        INSERT INTO forms(id, form_data)
-        VALUES (?, ?);
+        INSERT INTO forms(id, form_data, idempotency_key, created_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(idempotency_key) DO UPDATE SET
+            form_data = excluded.form_data,
+            created_at = excluded.created_at
+        RETURNING id;
         "#,
         key,
-        data
+        data,
+        idempotency_key,
+        created_at
     )
-    .execute(&*pool)
+    .fetch_one(&*pool)
     .await?;
 
-    if save_form_data_result.rows_affected() != 1 {
-        return Err(ApiError::InternalError(format!(
-            "save_form_data rows_affected error: {:?}",
-            save_form_data_result
-        )));
-    }
-
-    Ok(())
+    Ok(form_id)
 }
 
 pub async fn fetch_form_data<T>(pool: &Pool<Sqlite>, key: &String) -> Result<T, ApiError>
@@ -105,6 +164,43 @@ where
     }
 }
 
+/// Removes the form data once it's consumed, so its state can't be used again.
+pub async fn delete_form_data(pool: &Pool<Sqlite>, key: &String) -> Result<(), ApiError> {
+    query!(
+        r#"
+        DELETE FROM
+            forms
+        WHERE
+            id = ?;
+        "#,
+        key
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes form data that was saved before the given timestamp and never consumed, returns how many rows were removed.
+pub async fn delete_expired_form_data(
+    pool: &Pool<Sqlite>,
+    older_than: &i64,
+) -> Result<u64, ApiError> {
+    let delete_result = query!(
+        r#"
+        DELETE FROM
+            forms
+        WHERE
+            created_at < ?;
+        "#,
+        older_than
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(delete_result.rows_affected())
+}
+
 pub async fn save_reddit_account(
     pool: &Pool<Sqlite>,
     username: &String,
@@ -139,6 +235,69 @@ pub async fn save_reddit_account(
     Ok(uuid_str)
 }
 
+/// The latest account with the username, usernames aren't unique since an account can be authorized again.
+pub async fn fetch_reddit_account_id_by_username(
+    pool: &Pool<Sqlite>,
+    username: &String,
+) -> Result<Option<String>, ApiError> {
+    let reddit_account_id = query_scalar!(
+        r#"
+        SELECT
+            ra.id
+        FROM
+            reddit_accounts ra
+        WHERE
+            ra.username = ?
+        ORDER BY
+            ra.id DESC
+        LIMIT 1;
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(reddit_account_id)
+}
+
+pub async fn update_reddit_account(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+    oauth_token: &RedditOAuthToken,
+    moderate_submissions: &bool,
+) -> Result<(), ApiError> {
+    let expires_at = Utc::now().timestamp() + oauth_token.expires_in;
+    let oauth_token_json_str = serde_json::to_string(oauth_token)?;
+
+    let update_reddit_account_result = query!(
+        r#"
+        UPDATE
+            reddit_accounts
+        SET
+            moderate_submissions = ?,
+            oauth_token = ?,
+            expires_at = ?
+        WHERE
+            id = ?;
+        "#,
+        moderate_submissions,
+        oauth_token_json_str,
+        expires_at,
+        reddit_account_id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_reddit_account_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_reddit_account error: {:?}",
+            update_reddit_account_result
+        )));
+    }
+
+    Ok(())
+}
+
 pub async fn handle_youtube_subscription(
     pool: &Pool<Sqlite>,
     uuid_str: &String,
@@ -150,10 +309,15 @@ pub async fn handle_youtube_subscription(
 ) -> Result<(), ApiError> {
     match verification.mode {
         VerificationMode::Subscribe => {
+            let title_filters = subscription_form.title_filters.to_column()?;
+            // Without an override only videos published after the subscription was created are submitted.
+            let posting_cutoff = subscription_form
+                .posting_cutoff
+                .unwrap_or_else(|| Utc::now().timestamp());
             let save_youtube_subscription_result = query!(
                 r#"
-                INSERT INTO subscriptions(id, channel_id, channel_name, hmac_secret, expires, post_shorts)
-                VALUES (?, ?, ?, ?, ?, ?);
+                INSERT INTO subscriptions(id, channel_id, channel_name, hmac_secret, expires, post_shorts, min_duration_seconds, title_filters, posting_cutoff)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);
                 "#,
                 uuid_str,
                 channel_id,
@@ -161,6 +325,9 @@ pub async fn handle_youtube_subscription(
                 subscription_form.hmac_secret,
                 expires_at,
                 subscription_form.post_shorts,
+                subscription_form.min_duration_seconds,
+                title_filters,
+                posting_cutoff,
             )
             .execute(&*pool)
             .await?;
@@ -172,6 +339,8 @@ pub async fn handle_youtube_subscription(
                 )));
             }
 
+            link_subscription_targets(pool, uuid_str, subscription_form).await?;
+
             Ok(())
         }
         VerificationMode::Unsubscribe => {
@@ -202,7 +371,10 @@ pub async fn update_youtube_subscription(
         UPDATE
             subscriptions
         SET
-            expires = ?
+            expires = ?,
+            status = 'active',
+            hmac_secret = COALESCE(pending_hmac_secret, hmac_secret),
+            pending_hmac_secret = NULL
         WHERE
             id = ?;
         "#,
@@ -222,164 +394,824 @@ pub async fn update_youtube_subscription(
     Ok(())
 }
 
-pub async fn fetch_reddit_accounts_for_subscription(
+/// Marks the subscription as waiting for the hub's verification, subscriptions that
+/// aren't verified for the first time yet have no row and are left alone.
+pub async fn mark_subscription_pending(
     pool: &Pool<Sqlite>,
     subscription_id: &String,
-) -> Result<Vec<RedditAccountDTO>, ApiError> {
-    let subscription_has_reddit_account = query_scalar!(
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            status = 'pending'
+        WHERE
+            id = ?;
+        "#,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the subscription as expired if its lease has run out, returns true if it was.
+pub async fn expire_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    now: &i64,
+) -> Result<bool, ApiError> {
+    let expire_subscription_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            status = 'expired'
+        WHERE
+            id = ?
+            AND expires IS NOT NULL
+            AND expires <= ?;
+        "#,
+        subscription_id,
+        now,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(expire_subscription_result.rows_affected() == 1)
+}
+
+pub async fn subscription_exists_for_channel(
+    pool: &Pool<Sqlite>,
+    channel_id: &String,
+) -> Result<bool, ApiError> {
+    let subscription_exists = query_scalar!(
         r#"
         SELECT EXISTS (
             SELECT
-                link.reddit_account_id
+                s.id
             FROM
-                subscription_links link
+                subscriptions s
             WHERE
-                link.subscription_id = ?
+                s.channel_id = ?
             LIMIT 1
-        ) AS "result: bool";        
+        ) AS "result: bool";
         "#,
-        subscription_id
+        channel_id,
     )
-    .fetch_one(&*pool)
+    .fetch_one(pool)
     .await?;
 
-    if !subscription_has_reddit_account {
-        return Ok(vec![]);
-    }
+    Ok(subscription_exists)
+}
 
-    let reddit_accounts = query_as!(
-        RedditAccountDTO,
+pub struct UpdatedSubscription {
+    pub id: String,
+    pub hmac_secret: String,
+}
+
+/// Updates the settings of the oldest subscription for the channel and returns its id and stored secret.
+/// A new secret is kept as pending, it only replaces the stored one once the hub verified the resubscribe with it.
+pub async fn update_subscription_for_channel(
+    pool: &Pool<Sqlite>,
+    subscription: &YouTubeSubscription,
+    new_hmac_secret: Option<&String>,
+) -> Result<UpdatedSubscription, ApiError> {
+    let title_filters = subscription.title_filters.to_column()?;
+    let updated_subscription = query_as!(
+        UpdatedSubscription,
         r#"
-        SELECT
-            ra.id,
-            ra.username,
-            ra.moderate_submissions as "moderate_submissions: bool",
-            ra.oauth_token,
-            ra.expires_at
-        FROM
-            reddit_accounts ra
-        INNER JOIN subscription_links link ON
-            link.reddit_account_id = ra.id
+        UPDATE
+            subscriptions
+        SET
+            pending_hmac_secret = ?,
+            post_shorts = ?,
+            min_duration_seconds = ?,
+            title_filters = ?,
+            posting_cutoff = COALESCE(?, posting_cutoff)
         WHERE
-            link.subscription_id = ?;
+            id = (
+                SELECT
+                    s.id
+                FROM
+                    subscriptions s
+                WHERE
+                    s.channel_id = ?
+                ORDER BY
+                    s.id
+                LIMIT 1
+            )
+        RETURNING id, hmac_secret;
+        "#,
+        new_hmac_secret,
+        subscription.post_shorts,
+        subscription.min_duration_seconds,
+        title_filters,
+        subscription.posting_cutoff,
+        subscription.channel_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(ApiError::NotFound(format!(
+        "No subscription found for the YouTube channel: {}",
+        subscription.channel_id
+    )))?;
+
+    Ok(updated_subscription)
+}
+
+/// The hub refused the resubscribe, so it keeps signing with the stored secret.
+pub async fn discard_pending_hmac_secret(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            pending_hmac_secret = NULL
+        WHERE
+            id = ?;
         "#,
         subscription_id
     )
-    .fetch_all(&*pool)
+    .execute(pool)
     .await?;
 
-    Ok(reddit_accounts)
+    Ok(())
 }
 
-pub async fn update_reddit_oauth_token(
+pub async fn update_subscription_settings(
     pool: &Pool<Sqlite>,
-    reddit_account_id: &String,
-    oauth_token: &RedditOAuthToken,
+    subscription_id: &String,
+    post_shorts: &bool,
+    min_duration_seconds: &Option<i64>,
+    title_filters: &Option<String>,
+    min_post_interval_seconds: &Option<i64>,
+    description_comment: &Option<String>,
 ) -> Result<(), ApiError> {
-    let expires_at = Utc::now().timestamp() + oauth_token.expires_in;
-    let oauth_token_json_str = serde_json::to_string(oauth_token)?;
-
-    let update_reddit_oauth_token_result = query!(
+    let update_settings_result = query!(
         r#"
         UPDATE
-            reddit_accounts
+            subscriptions
         SET
-            oauth_token = ?,
-            expires_at = ?
+            post_shorts = ?,
+            min_duration_seconds = ?,
+            title_filters = ?,
+            min_post_interval_seconds = ?,
+            description_comment = ?
         WHERE
             id = ?;
         "#,
-        oauth_token_json_str,
-        expires_at,
-        reddit_account_id,
+        post_shorts,
+        min_duration_seconds,
+        title_filters,
+        min_post_interval_seconds,
+        description_comment,
+        subscription_id,
     )
-    .execute(&*pool)
+    .execute(pool)
     .await?;
 
-    if update_reddit_oauth_token_result.rows_affected() != 1 {
+    if update_settings_result.rows_affected() != 1 {
         return Err(ApiError::InternalError(format!(
-            "update_reddit_oauth_token error: {:?}",
-            update_reddit_oauth_token_result
+            "update_subscription_settings error: {:?}",
+            update_settings_result
         )));
     }
 
     Ok(())
 }
 
-pub async fn fetch_subreddits_for_reddit_account(
+/// Replaces the Reddit account and subreddit pairs the subscription submits with,
+/// each with its optional moderation override.
+pub async fn replace_subscription_links(
     pool: &Pool<Sqlite>,
-    reddit_account_id: &String,
-) -> Result<Vec<Subreddit>, ApiError> {
-    let reddit_account_has_subreddit = query_scalar!(
+    subscription_id: &String,
+    links: &[(String, i64, Option<bool>)],
+) -> Result<(), ApiError> {
+    let mut tx = pool.begin().await?;
+
+    query!(
         r#"
-        SELECT EXISTS (
-            SELECT
-                link.subreddit_id
-            FROM
-                subscription_links link
-            WHERE
-                link.reddit_account_id = ?
-            LIMIT 1
-        ) AS "result: bool";        
+        DELETE FROM
+            subscription_links
+        WHERE
+            subscription_id = ?;
         "#,
-        reddit_account_id
+        subscription_id,
     )
-    .fetch_one(&*pool)
+    .execute(&mut *tx)
     .await?;
 
-    if !reddit_account_has_subreddit {
-        return Ok(vec![]);
+    for (reddit_account_id, subreddit_id, moderate_submissions) in links {
+        query!(
+            r#"
+            INSERT OR IGNORE INTO subscription_links(subscription_id, reddit_account_id, subreddit_id, moderate_submissions)
+            VALUES (?, ?, ?, ?);
+            "#,
+            subscription_id,
+            reddit_account_id,
+            subreddit_id,
+            moderate_submissions,
+        )
+        .execute(&mut *tx)
+        .await?;
     }
 
-    let subreddits = query_as!(
-        Subreddit,
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Claims the subscription's next post when its minimum interval has passed since the last one,
+/// returns how many seconds are left otherwise. The claim is a single update, so entries handled
+/// at the same time can't both claim it.
+pub async fn claim_subscription_post(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    now: &i64,
+    min_post_interval_seconds: &i64,
+) -> Result<Option<i64>, ApiError> {
+    let claim_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            last_posted_at = ?
+        WHERE
+            id = ?
+            AND (last_posted_at IS NULL OR last_posted_at + ? <= ?);
+        "#,
+        now,
+        subscription_id,
+        min_post_interval_seconds,
+        now,
+    )
+    .execute(pool)
+    .await?;
+
+    if claim_result.rows_affected() == 1 {
+        return Ok(None);
+    }
+
+    let last_posted_at = query_scalar!(
         r#"
         SELECT
-            s.id,
-            s.name,
-            s.title_prefix,
-            s.title_suffix,
-            s.flair_id
+            s.last_posted_at
         FROM
-            subreddits s
-        INNER JOIN subscription_links link ON
-            link.subreddit_id = s.id
+            subscriptions s
         WHERE
-            link.reddit_account_id = ?;
+            s.id = ?;
         "#,
-        reddit_account_id
+        subscription_id
     )
-    .fetch_all(&*pool)
+    .fetch_one(pool)
     .await?;
 
-    Ok(subreddits)
+    Ok(Some(
+        last_posted_at
+            .map(|last_posted_at| last_posted_at + min_post_interval_seconds - now)
+            .unwrap_or(0)
+            .max(1),
+    ))
+}
+
+pub async fn update_subscription_posting_cutoff(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    posting_cutoff: &Option<i64>,
+) -> Result<(), ApiError> {
+    let update_posting_cutoff_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            posting_cutoff = ?
+        WHERE
+            id = ?;
+        "#,
+        posting_cutoff,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_posting_cutoff_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_subscription_posting_cutoff error: {:?}",
+            update_posting_cutoff_result
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn update_subscription_crossposting(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    crossposting: &Option<String>,
+) -> Result<(), ApiError> {
+    let update_crossposting_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            crossposting = ?
+        WHERE
+            id = ?;
+        "#,
+        crossposting,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_crossposting_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_subscription_crossposting error: {:?}",
+            update_crossposting_result
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn update_subscription_channel_name(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    channel_name: &String,
+) -> Result<(), ApiError> {
+    let update_channel_name_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            channel_name = ?
+        WHERE
+            id = ?;
+        "#,
+        channel_name,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_channel_name_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_subscription_channel_name error: {:?}",
+            update_channel_name_result
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn update_subscription_channel(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    channel_id: &String,
+    channel_name: &String,
+) -> Result<(), ApiError> {
+    let update_subscription_channel_result = query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            channel_id = ?,
+            channel_name = ?,
+            last_seen_update = NULL
+        WHERE
+            id = ?;
+        "#,
+        channel_id,
+        channel_name,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_subscription_channel_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_subscription_channel error: {:?}",
+            update_subscription_channel_result
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn fetch_last_seen_update(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+) -> Result<Option<i64>, ApiError> {
+    let last_seen_update = query_scalar!(
+        r#"
+        SELECT
+            s.last_seen_update
+        FROM
+            subscriptions s
+        WHERE
+            s.id = ?;
+        "#,
+        subscription_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    Ok(last_seen_update)
+}
+
+/// Stores the `updated` timestamp of a processed feed entry, an older timestamp never
+/// replaces a newer one.
+pub async fn update_last_seen_update(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    updated: &i64,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE
+            subscriptions
+        SET
+            last_seen_update = MAX(COALESCE(last_seen_update, 0), ?)
+        WHERE
+            id = ?;
+        "#,
+        updated,
+        subscription_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_reddit_accounts_for_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+) -> Result<Vec<RedditAccountDTO>, ApiError> {
+    let subscription_has_reddit_account = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT
+                link.reddit_account_id
+            FROM
+                subscription_links link
+            WHERE
+                link.subscription_id = ?
+            LIMIT 1
+        ) AS "result: bool";        
+        "#,
+        subscription_id
+    )
+    .fetch_one(&*pool)
+    .await?;
+
+    if !subscription_has_reddit_account {
+        return Ok(vec![]);
+    }
+
+    let reddit_accounts = query_as!(
+        RedditAccountDTO,
+        r#"
+        SELECT
+            ra.id,
+            ra.username,
+            ra.moderate_submissions as "moderate_submissions: bool",
+            ra.oauth_token,
+            ra.expires_at
+        FROM
+            reddit_accounts ra
+        INNER JOIN subscription_links link ON
+            link.reddit_account_id = ra.id
+        WHERE
+            link.subscription_id = ?;
+        "#,
+        subscription_id
+    )
+    .fetch_all(&*pool)
+    .await?;
+
+    Ok(reddit_accounts)
+}
+
+pub async fn update_reddit_oauth_token(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+    oauth_token: &RedditOAuthToken,
+) -> Result<(), ApiError> {
+    let expires_at = Utc::now().timestamp() + oauth_token.expires_in;
+    let oauth_token_json_str = serde_json::to_string(oauth_token)?;
+
+    let update_reddit_oauth_token_result = query!(
+        r#"
+        UPDATE
+            reddit_accounts
+        SET
+            oauth_token = ?,
+            expires_at = ?
+        WHERE
+            id = ?;
+        "#,
+        oauth_token_json_str,
+        expires_at,
+        reddit_account_id,
+    )
+    .execute(&*pool)
+    .await?;
+
+    if update_reddit_oauth_token_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_reddit_oauth_token error: {:?}",
+            update_reddit_oauth_token_result
+        )));
+    }
+
+    Ok(())
+}
+
+/// Logs a token refresh attempt and removes the account's attempts older than the latest `keep`.
+pub async fn record_token_refresh(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+    refreshed_at: &i64,
+    error: Option<&String>,
+    keep: &i64,
+) -> Result<(), ApiError> {
+    let success = error.is_none();
+
+    query!(
+        r#"
+        INSERT INTO token_refreshes (reddit_account_id, refreshed_at, success, error)
+        VALUES (?, ?, ?, ?);
+        "#,
+        reddit_account_id,
+        refreshed_at,
+        success,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    query!(
+        r#"
+        DELETE FROM token_refreshes
+        WHERE
+            reddit_account_id = ?
+            AND id NOT IN (
+                SELECT
+                    tr.id
+                FROM
+                    token_refreshes tr
+                WHERE
+                    tr.reddit_account_id = ?
+                ORDER BY
+                    tr.id DESC
+                LIMIT ?
+            );
+        "#,
+        reddit_account_id,
+        reddit_account_id,
+        keep
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct TokenRefresh {
+    pub refreshed_at: i64,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// The account's latest token refresh attempts, the latest first.
+pub async fn fetch_token_refreshes(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+    limit: i64,
+) -> Result<Vec<TokenRefresh>, ApiError> {
+    let token_refreshes = query_as!(
+        TokenRefresh,
+        r#"
+        SELECT
+            tr.refreshed_at,
+            tr.success as "success: bool",
+            tr.error
+        FROM
+            token_refreshes tr
+        WHERE
+            tr.reddit_account_id = ?
+        ORDER BY
+            tr.id DESC
+        LIMIT ?;
+        "#,
+        reddit_account_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(token_refreshes)
+}
+
+/// The subreddits the Reddit account submits the subscription's videos to.
+pub async fn fetch_subreddits_for_reddit_account(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    reddit_account_id: &String,
+) -> Result<Vec<Subreddit>, ApiError> {
+    let reddit_account_has_subreddit = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT
+                link.subreddit_id
+            FROM
+                subscription_links link
+            WHERE
+                link.subscription_id = ?
+                AND link.reddit_account_id = ?
+            LIMIT 1
+        ) AS "result: bool";        
+        "#,
+        subscription_id,
+        reddit_account_id
+    )
+    .fetch_one(&*pool)
+    .await?;
+
+    if !reddit_account_has_subreddit {
+        return Ok(vec![]);
+    }
+
+    let subreddits = query_as!(
+        Subreddit,
+        r#"
+        SELECT
+            s.id,
+            s.name,
+            s.title_prefix,
+            s.title_suffix,
+            s.title_template,
+            s.flair_id,
+            s.flair_text,
+            s.nsfw as "nsfw: bool",
+            s.spoiler as "spoiler: bool",
+            s.min_account_age_days,
+            s.min_karma,
+            s.comment_template,
+            s.sticky_comment as "sticky_comment: bool",
+            s.thumbnail_post as "thumbnail_post: bool",
+            s.posting_disabled_reason
+        FROM
+            subreddits s
+        INNER JOIN subscription_links link ON
+            link.subreddit_id = s.id
+        WHERE
+            link.subscription_id = ?
+            AND link.reddit_account_id = ?;
+        "#,
+        subscription_id,
+        reddit_account_id
+    )
+    .fetch_all(&*pool)
+    .await?;
+
+    Ok(subreddits)
 }
 
 pub async fn video_already_submitted_to_subreddit(
     pool: &Pool<Sqlite>,
     subreddit_id: &i64,
     video_id: &String,
-) -> Result<bool, ApiError> {
-    let is_already_submitted = query_scalar!(
+) -> Result<bool, ApiError> {
+    let is_already_submitted = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT
+                s.id
+            FROM
+                submissions s
+            WHERE
+                s.subreddit_id = ?
+                AND s.video_id = ?
+            LIMIT 1
+        ) AS "result: bool";        
+        "#,
+        subreddit_id,
+        video_id
+    )
+    .fetch_one(&*pool)
+    .await?;
+
+    Ok(is_already_submitted)
+}
+
+/// Whether the Reddit submission is already saved, e.g. by an earlier import of the account's submissions.
+pub async fn reddit_submission_saved(
+    pool: &Pool<Sqlite>,
+    submission_id: &String,
+) -> Result<bool, ApiError> {
+    let is_saved = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT id FROM submissions WHERE id = ?
+        ) AS "result: bool";
+        "#,
+        submission_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(is_saved)
+}
+
+/// Claims the video's submission to the subreddit, returns false when another account or notification
+/// already holds the claim. A claim from before `stale_before` belongs to a submission that never
+/// finished, e.g. because the bot stopped, so it's taken over.
+pub async fn claim_video_submission(
+    pool: &Pool<Sqlite>,
+    video_id: &String,
+    subreddit_id: &i64,
+    now: &i64,
+    stale_before: &i64,
+) -> Result<bool, ApiError> {
+    let claim_result = query!(
+        r#"
+        INSERT INTO submission_claims(video_id, subreddit_id, claimed_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(video_id, subreddit_id) DO UPDATE SET
+            claimed_at = excluded.claimed_at
+        WHERE
+            submission_claims.claimed_at < ?;
+        "#,
+        video_id,
+        subreddit_id,
+        now,
+        stale_before
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(claim_result.rows_affected() == 1)
+}
+
+/// Releases the claim of a failed submission, so a retry can submit the video.
+pub async fn release_video_submission(
+    pool: &Pool<Sqlite>,
+    video_id: &String,
+    subreddit_id: &i64,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        DELETE FROM
+            submission_claims
+        WHERE
+            video_id = ?
+            AND subreddit_id = ?;
+        "#,
+        video_id,
+        subreddit_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The fullname of the video's submission to the subreddit, used as the source of crossposts.
+pub async fn fetch_submission_for_video(
+    pool: &Pool<Sqlite>,
+    subreddit_id: &i64,
+    video_id: &String,
+) -> Result<Option<String>, ApiError> {
+    let submission_id = query_scalar!(
         r#"
-        SELECT EXISTS (
-            SELECT
-                s.id
-            FROM
-                submissions s
-            WHERE
-                s.subreddit_id = ?
-                AND s.video_id = ?
-            LIMIT 1
-        ) AS "result: bool";        
+        SELECT
+            s.id
+        FROM
+            submissions s
+        WHERE
+            s.subreddit_id = ?
+            AND s.video_id = ?
+        ORDER BY
+            s.created_at ASC
+        LIMIT 1;
         "#,
         subreddit_id,
         video_id
     )
-    .fetch_one(&*pool)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(is_already_submitted)
+    Ok(submission_id)
 }
 
 pub async fn save_reddit_submission(
@@ -428,7 +1260,14 @@ pub async fn fetch_subscriptions(pool: &Pool<Sqlite>) -> Result<Vec<Subscription
             s.channel_name,
             s.hmac_secret,
             s.expires,
-            s.post_shorts as "post_shorts: bool"
+            s.post_shorts as "post_shorts: bool",
+            s.min_duration_seconds,
+            s.title_filters,
+            s.posting_cutoff,
+            s.status as "status: SubscriptionStatus",
+            s.crossposting,
+            s.min_post_interval_seconds,
+            s.description_comment
         FROM
             subscriptions s;
         "#,
@@ -459,6 +1298,164 @@ pub async fn fetch_reddit_accounts(pool: &Pool<Sqlite>) -> Result<Vec<RedditAcco
     Ok(subscription)
 }
 
+/// A page of the subscriptions, ordered by channel name. With `channel_id` only that channel's subscriptions.
+pub async fn fetch_subscriptions_page(
+    pool: &Pool<Sqlite>,
+    channel_id: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Subscription>, ApiError> {
+    let subscriptions = query_as!(
+        Subscription,
+        r#"
+        SELECT
+            s.id,
+            s.channel_id,
+            s.channel_name,
+            s.hmac_secret,
+            s.expires,
+            s.post_shorts as "post_shorts: bool",
+            s.min_duration_seconds,
+            s.title_filters,
+            s.posting_cutoff,
+            s.status as "status: SubscriptionStatus",
+            s.crossposting,
+            s.min_post_interval_seconds,
+            s.description_comment
+        FROM
+            subscriptions s
+        WHERE
+            ? IS NULL
+            OR s.channel_id = ?
+        ORDER BY
+            s.channel_name,
+            s.id
+        LIMIT ? OFFSET ?;
+        "#,
+        channel_id,
+        channel_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(subscriptions)
+}
+
+pub async fn count_subscriptions(
+    pool: &Pool<Sqlite>,
+    channel_id: Option<&str>,
+) -> Result<i64, ApiError> {
+    let total = query_scalar!(
+        r#"
+        SELECT
+            COUNT(*)
+        FROM
+            subscriptions s
+        WHERE
+            ? IS NULL
+            OR s.channel_id = ?;
+        "#,
+        channel_id,
+        channel_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
+}
+
+/// A page of the Reddit accounts for the landing page, ordered by username.
+pub async fn fetch_reddit_accounts_page(
+    pool: &Pool<Sqlite>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<RedditAccountDTO>, ApiError> {
+    let reddit_accounts = query_as!(
+        RedditAccountDTO,
+        r#"
+        SELECT
+            ra.id,
+            ra.username,
+            ra.moderate_submissions as "moderate_submissions: bool",
+            ra.oauth_token,
+            ra.expires_at
+        FROM
+            reddit_accounts ra
+        ORDER BY
+            ra.username,
+            ra.id
+        LIMIT ? OFFSET ?;
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(reddit_accounts)
+}
+
+pub async fn count_reddit_accounts(pool: &Pool<Sqlite>) -> Result<i64, ApiError> {
+    let total = query_scalar!(
+        r#"
+        SELECT
+            COUNT(*)
+        FROM
+            reddit_accounts;
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
+}
+
+/// Only the ids and channel names, for the subscription choices of the landing page's forms.
+pub async fn fetch_subscription_options(
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<SelectOption>, ApiError> {
+    let options = query_as!(
+        SelectOption,
+        r#"
+        SELECT
+            s.id,
+            s.channel_name as name
+        FROM
+            subscriptions s
+        ORDER BY
+            s.channel_name;
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(options)
+}
+
+/// Only the ids and usernames, for the Reddit account choices of the landing page's forms.
+pub async fn fetch_reddit_account_options(
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<SelectOption>, ApiError> {
+    let options = query_as!(
+        SelectOption,
+        r#"
+        SELECT
+            ra.id,
+            ra.username as name
+        FROM
+            reddit_accounts ra
+        ORDER BY
+            ra.username;
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(options)
+}
+
 #[derive(Debug)]
 pub struct RedditSubmission {
     pub id: String,
@@ -487,7 +1484,285 @@ pub async fn fetch_submissions_on_subreddit(
     .fetch_all(&*pool)
     .await?;
 
-    Ok(submissions)
+    Ok(submissions)
+}
+
+#[derive(Debug)]
+pub struct SubmissionRecord {
+    pub id: String,
+    pub video_id: String,
+    pub subreddit_id: i64,
+    pub subreddit_name: String,
+    pub reddit_username: String,
+    pub stickied: bool,
+    pub created_at: i64,
+}
+
+/// The submissions of a subscription, newest first.
+pub async fn fetch_submissions_for_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SubmissionRecord>, ApiError> {
+    let submissions = query_as!(
+        SubmissionRecord,
+        r#"
+        SELECT
+            s.id,
+            s.video_id,
+            s.subreddit_id,
+            sr.name as subreddit_name,
+            ra.username as reddit_username,
+            s.stickied as "stickied: bool",
+            s.created_at
+        FROM
+            submissions s
+            INNER JOIN subreddits sr ON sr.id = s.subreddit_id
+            INNER JOIN reddit_accounts ra ON ra.id = s.reddit_account_id
+        WHERE
+            s.subscription_id = ?
+        ORDER BY
+            s.created_at DESC,
+            s.id DESC
+        LIMIT ? OFFSET ?;
+        "#,
+        subscription_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(submissions)
+}
+
+pub async fn count_submissions_for_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+) -> Result<i64, ApiError> {
+    let total = query_scalar!(
+        r#"
+        SELECT
+            COUNT(*)
+        FROM
+            submissions s
+        WHERE
+            s.subscription_id = ?;
+        "#,
+        subscription_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
+}
+
+/// Stores the failed submission, or counts another attempt when the video already failed on the subreddit.
+/// Returns the id of the row and its number of attempts.
+pub async fn record_failed_submission(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    reddit_account_id: &String,
+    subreddit_id: &i64,
+    video_id: &String,
+    entry: &String,
+    error: &ApiError,
+) -> Result<(i64, i64), ApiError> {
+    let now = Utc::now().timestamp();
+    let last_error = error.to_string();
+    let error_code = error.reddit_error_code();
+    let failed_submission = query!(
+        r#"
+        INSERT INTO failed_submissions (subscription_id, reddit_account_id, subreddit_id, video_id, entry, attempts, last_error, error_code, created_at)
+        VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?)
+        ON CONFLICT (video_id, subreddit_id) DO UPDATE SET
+            reddit_account_id = excluded.reddit_account_id,
+            attempts = failed_submissions.attempts + 1,
+            last_error = excluded.last_error,
+            error_code = excluded.error_code
+        RETURNING
+            id as "id!",
+            attempts;
+        "#,
+        subscription_id,
+        reddit_account_id,
+        subreddit_id,
+        video_id,
+        entry,
+        last_error,
+        error_code,
+        now
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((failed_submission.id, failed_submission.attempts))
+}
+
+pub async fn update_failed_submission_status(
+    pool: &Pool<Sqlite>,
+    failed_submission_id: &i64,
+    status: FailedSubmissionStatus,
+    next_attempt_at: Option<i64>,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE failed_submissions
+        SET
+            status = ?,
+            next_attempt_at = ?
+        WHERE
+            id = ?;
+        "#,
+        status,
+        next_attempt_at,
+        failed_submission_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct FailedSubmission {
+    pub subscription_id: String,
+    pub reddit_account_id: String,
+    pub subreddit_id: i64,
+    pub entry: String,
+    pub attempts: i64,
+    pub status: FailedSubmissionStatus,
+}
+
+pub async fn fetch_failed_submission(
+    pool: &Pool<Sqlite>,
+    failed_submission_id: &i64,
+) -> Result<Option<FailedSubmission>, ApiError> {
+    let failed_submission = query_as!(
+        FailedSubmission,
+        r#"
+        SELECT
+            fs.subscription_id,
+            fs.reddit_account_id,
+            fs.subreddit_id,
+            fs.entry,
+            fs.attempts,
+            fs.status as "status: FailedSubmissionStatus"
+        FROM
+            failed_submissions fs
+        WHERE
+            fs.id = ?;
+        "#,
+        failed_submission_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(failed_submission)
+}
+
+/// Only removes the row if no other attempt was counted in the meantime, e.g. by the retry failing again.
+pub async fn delete_failed_submission(
+    pool: &Pool<Sqlite>,
+    failed_submission_id: &i64,
+    attempts: &i64,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        DELETE FROM failed_submissions
+        WHERE
+            id = ?
+            AND attempts = ?;
+        "#,
+        failed_submission_id,
+        attempts
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct FailedSubmissionRecord {
+    pub id: i64,
+    pub video_id: String,
+    pub entry: String,
+    pub subreddit_id: i64,
+    pub subreddit_name: String,
+    pub reddit_username: String,
+    pub attempts: i64,
+    pub last_error: String,
+    pub error_code: Option<String>,
+    pub status: FailedSubmissionStatus,
+    pub next_attempt_at: Option<i64>,
+    pub created_at: i64,
+}
+
+/// The failed submissions of a subscription, the latest first.
+pub async fn fetch_failed_submissions_for_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<FailedSubmissionRecord>, ApiError> {
+    let failed_submissions = query_as!(
+        FailedSubmissionRecord,
+        r#"
+        SELECT
+            fs.id,
+            fs.video_id,
+            fs.entry,
+            fs.subreddit_id,
+            sr.name as subreddit_name,
+            ra.username as reddit_username,
+            fs.attempts,
+            fs.last_error,
+            fs.error_code,
+            fs.status as "status: FailedSubmissionStatus",
+            fs.next_attempt_at,
+            fs.created_at
+        FROM
+            failed_submissions fs
+            INNER JOIN subreddits sr ON sr.id = fs.subreddit_id
+            INNER JOIN reddit_accounts ra ON ra.id = fs.reddit_account_id
+        WHERE
+            fs.subscription_id = ?
+        ORDER BY
+            fs.created_at DESC,
+            fs.id DESC
+        LIMIT ? OFFSET ?;
+        "#,
+        subscription_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(failed_submissions)
+}
+
+pub async fn count_failed_submissions_for_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+) -> Result<i64, ApiError> {
+    let total = query_scalar!(
+        r#"
+        SELECT
+            COUNT(*)
+        FROM
+            failed_submissions fs
+        WHERE
+            fs.subscription_id = ?;
+        "#,
+        subscription_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
 }
 
 pub async fn update_reddit_submission_sticky_state(
@@ -533,7 +1808,17 @@ pub async fn get_or_create_subreddit(
             s.name,
             s.title_prefix,
             s.title_suffix,
-            s.flair_id
+            s.title_template,
+            s.flair_id,
+            s.flair_text,
+            s.nsfw as "nsfw: bool",
+            s.spoiler as "spoiler: bool",
+            s.min_account_age_days,
+            s.min_karma,
+            s.comment_template,
+            s.sticky_comment as "sticky_comment: bool",
+            s.thumbnail_post as "thumbnail_post: bool",
+            s.posting_disabled_reason
         FROM
             subreddits s
         WHERE
@@ -576,7 +1861,17 @@ pub async fn get_or_create_subreddit(
             s.name,
             s.title_prefix,
             s.title_suffix,
-            s.flair_id
+            s.title_template,
+            s.flair_id,
+            s.flair_text,
+            s.nsfw as "nsfw: bool",
+            s.spoiler as "spoiler: bool",
+            s.min_account_age_days,
+            s.min_karma,
+            s.comment_template,
+            s.sticky_comment as "sticky_comment: bool",
+            s.thumbnail_post as "thumbnail_post: bool",
+            s.posting_disabled_reason
         FROM
             subreddits s
         WHERE
@@ -616,6 +1911,80 @@ pub async fn get_reddit_account_by_id(
     Ok(reddit_accounts)
 }
 
+pub async fn reddit_account_exists(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+) -> Result<bool, ApiError> {
+    let reddit_account_exists = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT
+                ra.id
+            FROM
+                reddit_accounts ra
+            WHERE
+                ra.id = ?
+            LIMIT 1
+        ) AS "result: bool";
+        "#,
+        reddit_account_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(reddit_account_exists)
+}
+
+/// Links the subscription to the Reddit account on every subreddit the account is already linked to,
+/// returns the number of new links.
+pub async fn link_reddit_account_to_subscription(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    reddit_account_id: &String,
+) -> Result<u64, ApiError> {
+    let link_result = query!(
+        r#"
+        INSERT OR IGNORE INTO subscription_links(subscription_id, reddit_account_id, subreddit_id)
+        SELECT DISTINCT
+            ?,
+            links.reddit_account_id,
+            links.subreddit_id
+        FROM
+            subscription_links links
+        WHERE
+            links.reddit_account_id = ?;
+        "#,
+        subscription_id,
+        reddit_account_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(link_result.rows_affected())
+}
+
+/// Links the subscription to every chosen Reddit account and subreddit pair,
+/// without chosen subreddits the accounts are linked on the subreddits they already submit to.
+pub async fn link_subscription_targets(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    subscription_form: &YouTubeSubscription,
+) -> Result<(), ApiError> {
+    for reddit_account_id in &subscription_form.reddit_account_ids {
+        if subscription_form.subreddit_ids.is_empty() {
+            link_reddit_account_to_subscription(pool, subscription_id, reddit_account_id).await?;
+            continue;
+        }
+
+        for subreddit_id in &subscription_form.subreddit_ids {
+            register_subscription_link(pool, subscription_id, reddit_account_id, subreddit_id)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn get_subscription_by_id(
     pool: &Pool<Sqlite>,
     subscription_account_id: &String,
@@ -629,7 +1998,14 @@ pub async fn get_subscription_by_id(
             s.channel_name,
             s.hmac_secret,
             s.expires,
-            s.post_shorts as "post_shorts: bool"
+            s.post_shorts as "post_shorts: bool",
+            s.min_duration_seconds,
+            s.title_filters,
+            s.posting_cutoff,
+            s.status as "status: SubscriptionStatus",
+            s.crossposting,
+            s.min_post_interval_seconds,
+            s.description_comment
         FROM
             subscriptions s
         WHERE
@@ -646,7 +2022,7 @@ pub async fn get_subscription_by_id(
 pub async fn get_subreddit_by_id(
     pool: &Pool<Sqlite>,
     subreddit_id: &i64,
-) -> Result<Subreddit, ApiError> {
+) -> Result<Option<Subreddit>, ApiError> {
     let subreddit = query_as!(
         Subreddit,
         r#"
@@ -655,7 +2031,17 @@ pub async fn get_subreddit_by_id(
             s.name,
             s.title_prefix,
             s.title_suffix,
-            s.flair_id
+            s.title_template,
+            s.flair_id,
+            s.flair_text,
+            s.nsfw as "nsfw: bool",
+            s.spoiler as "spoiler: bool",
+            s.min_account_age_days,
+            s.min_karma,
+            s.comment_template,
+            s.sticky_comment as "sticky_comment: bool",
+            s.thumbnail_post as "thumbnail_post: bool",
+            s.posting_disabled_reason
         FROM
             subreddits s
         WHERE
@@ -663,12 +2049,86 @@ pub async fn get_subreddit_by_id(
         "#,
         subreddit_id
     )
-    .fetch_one(&*pool)
+    .fetch_optional(pool)
     .await?;
 
     Ok(subreddit)
 }
 
+pub async fn update_subreddit(pool: &Pool<Sqlite>, subreddit: &Subreddit) -> Result<(), ApiError> {
+    let update_subreddit_result = query!(
+        r#"
+        UPDATE
+            subreddits
+        SET
+            title_prefix = ?,
+            title_suffix = ?,
+            title_template = ?,
+            flair_id = ?,
+            flair_text = ?,
+            nsfw = ?,
+            spoiler = ?,
+            min_account_age_days = ?,
+            min_karma = ?,
+            comment_template = ?,
+            sticky_comment = ?,
+            thumbnail_post = ?,
+            posting_disabled_reason = ?
+        WHERE
+            id = ?;
+        "#,
+        subreddit.title_prefix,
+        subreddit.title_suffix,
+        subreddit.title_template,
+        subreddit.flair_id,
+        subreddit.flair_text,
+        subreddit.nsfw,
+        subreddit.spoiler,
+        subreddit.min_account_age_days,
+        subreddit.min_karma,
+        subreddit.comment_template,
+        subreddit.sticky_comment,
+        subreddit.thumbnail_post,
+        subreddit.posting_disabled_reason,
+        subreddit.id,
+    )
+    .execute(pool)
+    .await?;
+
+    if update_subreddit_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "update_subreddit error: {:?}",
+            update_subreddit_result
+        )));
+    }
+
+    Ok(())
+}
+
+/// Stops the submissions to the subreddit until the reason is cleared with `update_subreddit`.
+pub async fn disable_subreddit_posting(
+    pool: &Pool<Sqlite>,
+    subreddit_id: &i64,
+    reason: &String,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE
+            subreddits
+        SET
+            posting_disabled_reason = ?
+        WHERE
+            id = ?;
+        "#,
+        reason,
+        subreddit_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn fetch_subreddits(pool: &Pool<Sqlite>) -> Result<Vec<Subreddit>, ApiError> {
     let subscription = query_as!(
         Subreddit,
@@ -678,7 +2138,17 @@ pub async fn fetch_subreddits(pool: &Pool<Sqlite>) -> Result<Vec<Subreddit>, Api
             s.name,
             s.title_prefix,
             s.title_suffix,
-            s.flair_id
+            s.title_template,
+            s.flair_id,
+            s.flair_text,
+            s.nsfw as "nsfw: bool",
+            s.spoiler as "spoiler: bool",
+            s.min_account_age_days,
+            s.min_karma,
+            s.comment_template,
+            s.sticky_comment as "sticky_comment: bool",
+            s.thumbnail_post as "thumbnail_post: bool",
+            s.posting_disabled_reason
         FROM
             subreddits s;
         "#,
@@ -691,6 +2161,8 @@ pub async fn fetch_subreddits(pool: &Pool<Sqlite>) -> Result<Vec<Subreddit>, Api
 
 pub async fn fetch_linked_subscriptions(
     pool: &Pool<Sqlite>,
+    limit: i64,
+    offset: i64,
 ) -> Result<Vec<LinkedSubscription>, ApiError> {
     let linked_subscriptions = query_as!(
         LinkedSubscription,
@@ -701,7 +2173,8 @@ pub async fn fetch_linked_subscriptions(
             ra.id as reddit_account_id,
             ra.username as reddit_username,
             subreddits.id as subreddit_id,
-            subreddits.name as subreddit_name
+            subreddits.name as subreddit_name,
+            link.moderate_submissions as "moderate_submissions: bool"
         FROM
             subscriptions
         INNER JOIN subscription_links link ON
@@ -710,7 +2183,14 @@ pub async fn fetch_linked_subscriptions(
             link.reddit_account_id = ra.id
         INNER JOIN subreddits ON
             link.subreddit_id = subreddits.id
+        ORDER BY
+            subscriptions.channel_name,
+            ra.username,
+            subreddits.name
+        LIMIT ? OFFSET ?;
         "#,
+        limit,
+        offset
     )
     .fetch_all(&*pool)
     .await?;
@@ -718,6 +2198,79 @@ pub async fn fetch_linked_subscriptions(
     Ok(linked_subscriptions)
 }
 
+pub async fn count_linked_subscriptions(pool: &Pool<Sqlite>) -> Result<i64, ApiError> {
+    let total = query_scalar!(
+        r#"
+        SELECT
+            COUNT(*)
+        FROM
+            subscription_links;
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
+}
+
+/// The moderation override of the link, `None` when the Reddit account's setting is used.
+pub async fn fetch_subscription_link_moderation(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    reddit_account_id: &String,
+    subreddit_id: &i64,
+) -> Result<Option<bool>, ApiError> {
+    let moderate_submissions = query_scalar!(
+        r#"
+        SELECT
+            link.moderate_submissions as "moderate_submissions: bool"
+        FROM
+            subscription_links link
+        WHERE
+            link.subscription_id = ?
+            AND link.reddit_account_id = ?
+            AND link.subreddit_id = ?;
+        "#,
+        subscription_id,
+        reddit_account_id,
+        subreddit_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    Ok(moderate_submissions)
+}
+
+pub async fn update_subscription_link_moderation(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    reddit_account_id: &String,
+    subreddit_id: &i64,
+    moderate_submissions: &Option<bool>,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        UPDATE
+            subscription_links
+        SET
+            moderate_submissions = ?
+        WHERE
+            subscription_id = ?
+            AND reddit_account_id = ?
+            AND subreddit_id = ?;
+        "#,
+        moderate_submissions,
+        subscription_id,
+        reddit_account_id,
+        subreddit_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn register_subscription_link(
     pool: &Pool<Sqlite>,
     subscription_id: &String,
@@ -757,7 +2310,17 @@ pub async fn register_subscription_link(
             subreddit_id,
         )
         .execute(&mut *tx)
-        .await?;
+        .await
+        .map_err(|e| {
+            if is_foreign_key_violation(&e) {
+                ApiError::BadRequest(format!(
+                    "The subscription {}, Reddit account {} or subreddit {} doesn't exist",
+                    subscription_id, reddit_account_id, subreddit_id
+                ))
+            } else {
+                e.into()
+            }
+        })?;
 
         if subscription_link_exist_result.rows_affected() != 1 {
             return Err(ApiError::InternalError(format!(
@@ -772,46 +2335,71 @@ pub async fn register_subscription_link(
     Ok(())
 }
 
-pub async fn register_subreddit_form(
+pub struct NewSubreddit {
+    pub name: String,
+    pub title_prefix: Option<String>,
+    pub title_suffix: Option<String>,
+    pub title_template: Option<String>,
+    pub flair_id: Option<String>,
+    pub nsfw: bool,
+    pub spoiler: bool,
+}
+
+async fn fetch_subreddit_id_by_name(
     pool: &Pool<Sqlite>,
     subreddit_name: &String,
-    submission_title_prefix: &Option<String>,
-    submission_title_suffix: &Option<String>,
-    submission_flair_id: &Option<String>,
-) -> Result<(), ApiError> {
-    let subreddit_exists = query_scalar!(
+) -> Result<Option<i64>, ApiError> {
+    let subreddit_id = query_scalar!(
         r#"
-        SELECT EXISTS (
-            SELECT
-                s.id
-            FROM
-                subreddits s
-            WHERE
-                s.name LIKE ?
-            LIMIT 1
-        ) AS "result: bool";
+        SELECT
+            s.id
+        FROM
+            subreddits s
+        WHERE
+            s.name LIKE ?
+        LIMIT 1;
         "#,
         subreddit_name,
     )
-    .fetch_one(&*pool)
+    .fetch_optional(pool)
     .await?;
 
-    if subreddit_exists {
-        return Ok(());
+    Ok(subreddit_id)
+}
+
+pub async fn register_subreddit_form(
+    pool: &Pool<Sqlite>,
+    subreddit: &NewSubreddit,
+) -> Result<i64, ApiError> {
+    if let Some(subreddit_id) = fetch_subreddit_id_by_name(pool, &subreddit.name).await? {
+        return Ok(subreddit_id);
     }
 
-    let register_subreddit_result = query!(
+    let register_subreddit_result = match query!(
         r#"
-        INSERT INTO subreddits(name, title_prefix, title_suffix, flair_id)
-        VALUES (?, ?, ?, ?);
+        INSERT INTO subreddits(name, title_prefix, title_suffix, title_template, flair_id, nsfw, spoiler)
+        VALUES (?, ?, ?, ?, ?, ?, ?);
         "#,
-        subreddit_name,
-        submission_title_prefix,
-        submission_title_suffix,
-        submission_flair_id,
+        subreddit.name,
+        subreddit.title_prefix,
+        subreddit.title_suffix,
+        subreddit.title_template,
+        subreddit.flair_id,
+        subreddit.nsfw,
+        subreddit.spoiler,
     )
-    .execute(&*pool)
-    .await?;
+    .execute(pool)
+    .await
+    {
+        Ok(register_subreddit_result) => register_subreddit_result,
+        // Registered by a concurrent request since the lookup.
+        Err(e) if is_unique_violation(&e) => {
+            return fetch_subreddit_id_by_name(pool, &subreddit.name)
+                .await?
+                .ok_or(ApiError::from(e));
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     if register_subreddit_result.rows_affected() != 1 {
         return Err(ApiError::InternalError(format!(
@@ -820,5 +2408,261 @@ pub async fn register_subreddit_form(
         )));
     }
 
+    Ok(register_subreddit_result.last_insert_rowid())
+}
+
+pub async fn save_region_check(
+    pool: &Pool<Sqlite>,
+    video_id: &String,
+    region: &String,
+    blocked: &bool,
+    skipped: &bool,
+    checked_at: &i64,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        INSERT OR REPLACE INTO region_checks(video_id, region, blocked, skipped, checked_at)
+        VALUES (?, ?, ?, ?, ?);
+        "#,
+        video_id,
+        region,
+        blocked,
+        skipped,
+        checked_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_video_duration(
+    pool: &Pool<Sqlite>,
+    video_id: &str,
+) -> Result<Option<i64>, ApiError> {
+    let duration_seconds = query_scalar!(
+        r#"
+        SELECT
+            vd.duration_seconds
+        FROM
+            video_durations vd
+        WHERE
+            vd.video_id = ?;
+        "#,
+        video_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(duration_seconds)
+}
+
+pub async fn save_video_duration(
+    pool: &Pool<Sqlite>,
+    video_id: &str,
+    duration_seconds: &i64,
+    fetched_at: &i64,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        INSERT OR REPLACE INTO video_durations(video_id, duration_seconds, fetched_at)
+        VALUES (?, ?, ?);
+        "#,
+        video_id,
+        duration_seconds,
+        fetched_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_reddit_account_stats(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+) -> Result<Option<RedditAccountStats>, ApiError> {
+    let account_stats = query_as!(
+        RedditAccountStats,
+        r#"
+        SELECT
+            ras.created_utc,
+            ras.total_karma,
+            ras.fetched_at
+        FROM
+            reddit_account_stats ras
+        WHERE
+            ras.reddit_account_id = ?;
+        "#,
+        reddit_account_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(account_stats)
+}
+
+pub async fn save_reddit_account_stats(
+    pool: &Pool<Sqlite>,
+    reddit_account_id: &String,
+    account_stats: &RedditAccountStats,
+) -> Result<(), ApiError> {
+    query!(
+        r#"
+        INSERT OR REPLACE INTO reddit_account_stats(reddit_account_id, created_utc, total_karma, fetched_at)
+        VALUES (?, ?, ?, ?);
+        "#,
+        reddit_account_id,
+        account_stats.created_utc,
+        account_stats.total_karma,
+        account_stats.fetched_at,
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
+
+pub struct FeedEvent {
+    pub id: i64,
+    pub subscription_id: String,
+    pub received_at: i64,
+    pub signature: Option<String>,
+    pub body: String,
+}
+
+pub async fn save_feed_event(
+    pool: &Pool<Sqlite>,
+    subscription_id: &String,
+    received_at: &i64,
+    signature: &Option<String>,
+    body: &String,
+) -> Result<i64, ApiError> {
+    let save_feed_event_result = query!(
+        r#"
+        INSERT INTO feed_events(subscription_id, received_at, signature, body)
+        VALUES (?, ?, ?, ?);
+        "#,
+        subscription_id,
+        received_at,
+        signature,
+        body,
+    )
+    .execute(pool)
+    .await?;
+
+    if save_feed_event_result.rows_affected() != 1 {
+        return Err(ApiError::InternalError(format!(
+            "save_feed_event rows_affected error: {:?}",
+            save_feed_event_result
+        )));
+    }
+
+    Ok(save_feed_event_result.last_insert_rowid())
+}
+
+pub async fn fetch_feed_event(
+    pool: &Pool<Sqlite>,
+    event_id: &i64,
+) -> Result<Option<FeedEvent>, ApiError> {
+    let feed_event = query_as!(
+        FeedEvent,
+        r#"
+        SELECT
+            fe.id,
+            fe.subscription_id,
+            fe.received_at,
+            fe.signature,
+            fe.body
+        FROM
+            feed_events fe
+        WHERE
+            fe.id = ?;
+        "#,
+        event_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(feed_event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        infrastructure::{AppState, Settings},
+        server::test_support::{CHANNEL_ID, verified_subscription},
+    };
+
+    fn new_subreddit(name: &str, title_prefix: Option<String>) -> NewSubreddit {
+        NewSubreddit {
+            name: name.to_string(),
+            title_prefix,
+            title_suffix: None,
+            title_template: None,
+            flair_id: None,
+            nsfw: false,
+            spoiler: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn full_disk_maps_to_database_storage() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        // The database can't grow past the pages it already has, like on a full disk.
+        sqlx::query("PRAGMA max_page_count = 1")
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let result = register_subreddit_form(
+            &state.db_pool,
+            &new_subreddit("videos", Some("x".repeat(1024 * 1024))),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::DatabaseStorage(_))));
+    }
+
+    #[tokio::test]
+    async fn subscription_without_a_lease_is_selected_with_no_expiry() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subscription_id = verified_subscription(&state, CHANNEL_ID).await;
+        sqlx::query("UPDATE subscriptions SET expires = NULL WHERE id = ?")
+            .bind(&subscription_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let subscription = get_subscription_details(&state.db_pool, &subscription_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscriptions = fetch_subscriptions_page(&state.db_pool, None, 10, 0)
+            .await
+            .unwrap();
+
+        assert_eq!(subscription.expires, None);
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].expires, None);
+    }
+
+    #[tokio::test]
+    async fn link_to_missing_subscription_is_a_bad_request() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let subreddit_id = register_subreddit_form(&state.db_pool, &new_subreddit("videos", None))
+            .await
+            .unwrap();
+
+        let result = register_subscription_link(
+            &state.db_pool,
+            &Uuid::now_v7().to_string(),
+            &Uuid::now_v7().to_string(),
+            &subreddit_id,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+}
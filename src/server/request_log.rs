@@ -0,0 +1,42 @@
+use std::time::Instant;
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use url::form_urlencoded;
+
+// The Reddit OAuth callback carries the authorization code and the form state in its query.
+const REDACTED_QUERY_PARAMETERS: [&str; 2] = ["code", "state"];
+
+/// Logs the method, path, status and latency of every request.
+pub async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().map(redact_query);
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    println!(
+        "{} {}{} -> {} in {} ms",
+        method,
+        path,
+        query.map(|query| format!("?{}", query)).unwrap_or_default(),
+        response.status().as_u16(),
+        started.elapsed().as_millis()
+    );
+
+    response
+}
+
+fn redact_query(query: &str) -> String {
+    let mut redacted = form_urlencoded::Serializer::new(String::new());
+
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        if REDACTED_QUERY_PARAMETERS.contains(&key.as_ref()) {
+            redacted.append_pair(&key, "REDACTED");
+        } else {
+            redacted.append_pair(&key, &value);
+        }
+    }
+
+    redacted.finish()
+}
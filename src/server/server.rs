@@ -1,14 +1,26 @@
-use axum::response::IntoResponse;
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    Json,
+    http::{HeaderValue, Method, header},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use sqlx::migrate::MigrateError;
 use thiserror::Error;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
-use utoipa::OpenApi;
+use utoipa::{OpenApi, ToSchema};
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_rapidoc::RapiDoc;
 
 use crate::{
-    infrastructure::{AppState, Settings, handle_scheduler},
-    server::{forms, frontend, google, reddit, shared},
+    infrastructure::{AppState, Settings, handle_scheduler, telemetry},
+    server::{
+        admin_auth, alerts, alerts::DatabaseStorageFailure, api, forms, frontend, google,
+        monitoring, reddit, request_log, shared,
+    },
 };
 
 impl From<MigrateError> for ApiError {
@@ -17,37 +29,101 @@ impl From<MigrateError> for ApiError {
     }
 }
 
+// The paths aren't listed here, they're collected from the `routes!` of every router merged in `api_router`.
 #[derive(OpenApi)]
 #[openapi(
-    paths(),
     components(schemas(
         shared::VerificationMode,
-        reddit::RedditCallbackErrors
+        reddit::RedditCallbackErrors,
+        ErrorResponse
     )),
     servers((url = "", description = "Reddit YouTube bot")),
 )]
 pub struct ApiDoc;
 
+fn api_router() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .merge(frontend::router())
+        .nest("/google", google::router())
+        .nest("/forms", forms::router())
+        .nest("/reddit", reddit::router())
+        .nest("/api", api::router())
+        .merge(monitoring::router())
+}
+
+/// The full OpenAPI spec with the paths of every router, built without starting the server.
+pub fn openapi_spec() -> utoipa::openapi::OpenApi {
+    api_router().split_for_parts().1
+}
+
 const APP_NAME: &str = env!("CARGO_PKG_NAME");
 pub async fn serve(port: u16, app_settings: Settings) -> Result<(), ApiError> {
-    let (state, receiver) = AppState::new(app_settings).await;
+    let catch_up_on_start = app_settings.catch_up_on_start;
+    let cors_layer = cors_layer(&app_settings.cors_allowed_origins);
+    let admin_credentials = app_settings.admin_credentials.clone();
+    let addr = SocketAddr::new(app_settings.bind_address, port);
+
+    shared::configure_http_client(
+        app_settings.http_timeouts,
+        app_settings.reddit_operator.as_deref(),
+    );
+    shared::configure_reddit_urls(app_settings.reddit_urls.clone());
+    shared::configure_hub_test_mode(app_settings.hub_test_mode);
+
+    if app_settings.hub_test_mode {
+        println!(
+            "HUB TEST MODE IS ACTIVE: subscriptions are verified locally and won't receive feeds from YouTube, POST them to the callback yourself."
+        );
+    }
+
+    if let Some(statsd_addr) = &app_settings.statsd_addr {
+        telemetry::install_statsd_exporter(statsd_addr)?;
+    }
+
+    let metrics_handle = if app_settings.prometheus_metrics {
+        Some(telemetry::install_prometheus_exporter()?)
+    } else {
+        None
+    };
+
+    let (state, receiver) = AppState::new(app_settings, metrics_handle).await;
 
     sqlx::migrate!().run(&state.db_pool).await?;
 
     handle_scheduler(&state, receiver).await?;
 
-    let (router, _api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
-        .merge(frontend::router())
-        .nest("/google", google::router())
-        .nest("/forms", forms::router())
-        .nest("/reddit", reddit::router())
-        .with_state(state)
-        .split_for_parts();
+    if catch_up_on_start {
+        let catch_up_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = google::catch_up_on_missed_videos(catch_up_state).await {
+                eprintln!("Catch-up on missed videos failed: {}", e);
+            }
+        });
+    }
 
-    let router =
-        router.merge(RapiDoc::with_openapi("/api-docs/openapi.json", _api).path("/rapidoc"));
+    let (router, _api) = api_router().with_state(state.clone()).split_for_parts();
+
+    let mut router = router
+        .merge(RapiDoc::with_openapi("/api-docs/openapi.json", _api).path("/rapidoc"))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            alerts::alert_on_storage_failure,
+        ));
+
+    if let Some(admin_credentials) = admin_credentials {
+        router = router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(admin_credentials),
+            admin_auth::require_admin_auth,
+        ));
+    }
+
+    // Added after the admin auth, so the CORS preflight requests are answered without credentials.
+    if let Some(cors_layer) = cors_layer {
+        router = router.layer(cors_layer);
+    }
+
+    let router = router.layer(axum::middleware::from_fn(request_log::log_request));
 
-    let addr = format!("0.0.0.0:{}", port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .map_err(ApiError::TcpListenerError)?;
@@ -59,6 +135,68 @@ pub async fn serve(port: u16, app_settings: Settings) -> Result<(), ApiError> {
     Ok(())
 }
 
+/// Without allowed origins no CORS headers are sent, so browsers keep to same-origin requests.
+/// The layer also answers the OPTIONS preflight requests, e.g. for the form POST endpoints.
+fn cors_layer(allowed_origins: &[String]) -> Option<CorsLayer> {
+    if allowed_origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
+    )
+}
+
+/// Re-runs the submission logic for a stored feed event without starting the server.
+pub async fn replay(event_id: i64, app_settings: Settings) -> Result<(), ApiError> {
+    shared::configure_http_client(
+        app_settings.http_timeouts,
+        app_settings.reddit_operator.as_deref(),
+    );
+    shared::configure_reddit_urls(app_settings.reddit_urls.clone());
+
+    // Rate limited retries aren't run as there is no scheduler, the replay can be run again instead.
+    let (state, _receiver) = AppState::new(app_settings, None).await;
+
+    sqlx::migrate!().run(&state.db_pool).await?;
+
+    google::replay_feed_event(&state, event_id).await
+}
+
+/// Submits a channel's videos published within the date range without starting the server.
+pub async fn backfill(
+    channel_id: String,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    app_settings: Settings,
+) -> Result<(), ApiError> {
+    shared::configure_http_client(
+        app_settings.http_timeouts,
+        app_settings.reddit_operator.as_deref(),
+    );
+    shared::configure_reddit_urls(app_settings.reddit_urls.clone());
+
+    // Like the replay, rate limited retries aren't run as there is no scheduler.
+    let (state, _receiver) = AppState::new(app_settings, None).await;
+
+    sqlx::migrate!().run(&state.db_pool).await?;
+
+    google::backfill_channel(&state, &channel_id, since, until).await
+}
+
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("Axum server start error: {0}")]
@@ -75,15 +213,62 @@ pub enum ApiError {
 
     #[error("Bad request error: {0}")]
     BadRequest(String),
+
+    #[error("Conflict error: {0}")]
+    Conflict(String),
+
+    #[error("Database busy error: {0}")]
+    DatabaseBusy(String),
+
+    #[error("Database storage error: {0}")]
+    DatabaseStorage(String),
+
+    #[error("Rate limited error: {0}, retry in {1} seconds")]
+    RateLimited(String, i64),
+
+    /// Reddit's error code, e.g. `SUBREDDIT_NOTALLOWED`, and its message.
+    #[error("Reddit rejected error {0}: {1}")]
+    RedditRejected(String, String),
+}
+
+impl ApiError {
+    /// The error code Reddit rejected the request with.
+    pub fn reddit_error_code(&self) -> Option<&String> {
+        match self {
+            ApiError::RedditRejected(code, _) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// The JSON body of every error response.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Machine-readable kind of the error.
+    #[schema(example = "bad_request")]
+    pub error: &'static str,
+    #[schema(example = "Invalid channel id, the input was: abc")]
+    pub message: String,
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let (status, message) = match &self {
+        // Tells well-behaved clients how long to back off, the seconds are also in the message.
+        let retry_after = match &self {
+            ApiError::RateLimited(_, wait_secs) => Some(wait_secs.max(&0).to_string()),
+            _ => None,
+        };
+        let storage_failure = match &self {
+            ApiError::DatabaseStorage(message) => Some(DatabaseStorageFailure(message.clone())),
+            _ => None,
+        };
+
+        let (status, error, message) = match &self {
             ApiError::AxumError(error) => {
                 println!("Axum error: {}", error);
                 (
                     axum::http::StatusCode::BAD_REQUEST,
+                    "server_error",
                     format!("Server error: {}", error),
                 )
             }
@@ -91,6 +276,7 @@ impl IntoResponse for ApiError {
                 println!("TCP listener error: {}", error);
                 (
                     axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "server_error",
                     format!("Server error: {}", error),
                 )
             }
@@ -98,24 +284,140 @@ impl IntoResponse for ApiError {
                 println!("Internal server error: {}", message);
                 (
                     axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Internal server error: {}", message),
+                    "internal_error",
+                    message.clone(),
                 )
             }
             ApiError::NotFound(message) => {
                 println!("Not found error: {}", message);
                 (
                     axum::http::StatusCode::NOT_FOUND,
-                    format!("Not found error: {}", message),
+                    "not_found",
+                    message.clone(),
                 )
             }
             ApiError::BadRequest(message) => {
                 println!("Bad request error: {}", message);
                 (
                     axum::http::StatusCode::BAD_REQUEST,
-                    format!("Bad request error: {}", message),
+                    "bad_request",
+                    message.clone(),
+                )
+            }
+            ApiError::Conflict(message) => {
+                println!("Conflict error: {}", message);
+                (
+                    axum::http::StatusCode::CONFLICT,
+                    "conflict",
+                    message.clone(),
+                )
+            }
+            ApiError::DatabaseBusy(message) => {
+                eprintln!("Database busy error: {}", message);
+                (
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                    "database_busy",
+                    "The database is busy, try again later".to_string(),
+                )
+            }
+            ApiError::DatabaseStorage(message) => {
+                // Disk full or IO failures won't fix themselves, so these are logged loudly.
+                eprintln!("CRITICAL database storage error: {}", message);
+                (
+                    axum::http::StatusCode::INSUFFICIENT_STORAGE,
+                    "database_storage",
+                    "The database storage failed".to_string(),
+                )
+            }
+            ApiError::RateLimited(message, wait_secs) => {
+                println!(
+                    "Rate limited error: {}, retry in {} seconds",
+                    message, wait_secs
+                );
+                (
+                    axum::http::StatusCode::TOO_MANY_REQUESTS,
+                    "rate_limited",
+                    format!("{}, retry in {} seconds", message, wait_secs),
+                )
+            }
+            ApiError::RedditRejected(code, message) => {
+                println!("Reddit rejected error {}: {}", code, message);
+                (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "reddit_rejected",
+                    format!("Reddit rejected the request with {}: {}", code, message),
                 )
             }
         };
-        (status, message).into_response()
+        let mut response = (status, Json(ErrorResponse { error, message })).into_response();
+
+        if let Some(retry_after) = retry_after.and_then(|secs| HeaderValue::from_str(&secs).ok()) {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after);
+        }
+
+        // The alert needs the webhook from the state, see `alert_on_storage_failure`.
+        if let Some(storage_failure) = storage_failure {
+            response.extensions_mut().insert(storage_failure);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, extract::Request, http::StatusCode, routing::put};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::infrastructure::Settings;
+
+    #[tokio::test]
+    async fn reddit_oauth_callback_route_responds() {
+        let (state, _receiver) = AppState::for_tests(Settings::for_tests()).await;
+        let (router, _api) = api_router().with_state(state).split_for_parts();
+
+        let response = router
+            .oneshot(
+                Request::get("/reddit/callback?code=&state=unknown&error=access_denied")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // The denied authorization is answered by the callback handler, not a missing route.
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_allows_put() {
+        let router = Router::new()
+            .route("/api/subscriptions/{id}", put(|| async {}))
+            .layer(cors_layer(&["https://dashboard.example.com".to_string()]).unwrap());
+
+        let response = router
+            .oneshot(
+                Request::options("/api/subscriptions/1")
+                    .header(header::ORIGIN, "https://dashboard.example.com")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let allowed_methods = response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_METHODS)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        assert!(
+            allowed_methods
+                .split(',')
+                .any(|method| method.trim() == "PUT")
+        );
     }
 }
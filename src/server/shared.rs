@@ -1,9 +1,17 @@
-use std::sync::LazyLock;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{LazyLock, OnceLock},
+    time::{Duration, Instant},
+};
 
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_textual::DisplaySerde;
+use tokio::sync::RwLock;
+use url::{Host, Url};
 use utoipa::ToSchema;
 
 use crate::server::ApiError;
@@ -15,6 +23,13 @@ pub struct RedditCredentials {
     pub client_secret: String,
 }
 
+/// HTTP Basic Auth credentials for the frontend, forms and API.
+#[derive(Debug, Clone)]
+pub struct AdminCredentials {
+    pub username: String,
+    pub password: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RedditAuthorization {
     pub r#type: FormType,
@@ -23,7 +38,7 @@ pub struct RedditAuthorization {
     pub scopes: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct RedditOAuthToken {
     pub access_token: String,
@@ -40,6 +55,142 @@ pub struct YouTubeSubscription {
     pub channel_id: String,
     pub hmac_secret: String,
     pub post_shorts: bool,
+    #[serde(default)]
+    pub min_duration_seconds: Option<i64>,
+    #[serde(default)]
+    pub reddit_account_ids: Vec<String>,
+    #[serde(default)]
+    pub subreddit_ids: Vec<i64>,
+    #[serde(default)]
+    pub title_filters: TitleFilters,
+    /// Overrides the default cutoff of the subscription's creation time, e.g. to backfill older videos.
+    #[serde(default)]
+    pub posting_cutoff: Option<i64>,
+    /// Fetched when the subscribe form is sent, forms stored before that don't have it.
+    #[serde(default)]
+    pub channel_name: Option<String>,
+}
+
+/// Regexes matched against the video titles, stored as JSON on the subscription.
+#[derive(Serialize, Deserialize, ToSchema, Default, Clone, Debug)]
+pub struct TitleFilters {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl TitleFilters {
+    /// Compiles every pattern so invalid ones are rejected before they're stored.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        for pattern in self.include.iter().chain(&self.exclude) {
+            Regex::new(pattern).map_err(|e| {
+                ApiError::BadRequest(format!("Invalid title filter pattern '{}': {}", pattern, e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// The JSON stored in the `title_filters` column, `None` when there are no patterns.
+    pub fn to_column(&self) -> Result<Option<String>, ApiError> {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::to_string(self)?))
+    }
+
+    /// Returns why the title is filtered out, `None` when the video should be submitted.
+    pub fn skip_reason(&self, title: &str) -> Option<String> {
+        if let Some(pattern) = self
+            .exclude
+            .iter()
+            .find(|pattern| pattern_matches(pattern, title))
+        {
+            return Some(format!("it matches the exclude pattern '{}'", pattern));
+        }
+
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|pattern| pattern_matches(pattern, title))
+        {
+            return Some(format!(
+                "it matches none of the include patterns '{}'",
+                self.include.join("', '")
+            ));
+        }
+
+        None
+    }
+}
+
+// Patterns are validated when saved, an invalid one is logged and treated as not matching.
+fn pattern_matches(pattern: &str, title: &str) -> bool {
+    match Regex::new(pattern) {
+        Ok(regex) => regex.is_match(title),
+        Err(e) => {
+            eprintln!("Invalid title filter pattern '{}': {}", pattern, e);
+            false
+        }
+    }
+}
+
+/// Comments the video's YouTube description under each submission, stored as JSON on the subscription.
+#[derive(Serialize, Deserialize, ToSchema, Default, Clone, Debug)]
+pub struct DescriptionComment {
+    /// Only the first lines of the description are commented, the whole description when `null`.
+    #[schema(example = 5)]
+    pub max_lines: Option<u32>,
+}
+
+/// Submits the video once to the primary subreddit and crossposts that submission to the other
+/// subreddits, stored as JSON on the subscription.
+#[derive(Serialize, Deserialize, ToSchema, Default, Clone, Debug)]
+pub struct CrosspostSettings {
+    #[schema(example = 1)]
+    pub primary_subreddit_id: Option<i64>,
+    #[serde(default)]
+    pub crosspost_subreddit_ids: Vec<i64>,
+}
+
+impl CrosspostSettings {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        match self.primary_subreddit_id {
+            None if !self.crosspost_subreddit_ids.is_empty() => Err(ApiError::BadRequest(
+                "Crosspost subreddits need a primary subreddit to crosspost from".into(),
+            )),
+            Some(primary_subreddit_id)
+                if self.crosspost_subreddit_ids.contains(&primary_subreddit_id) =>
+            {
+                Err(ApiError::BadRequest(format!(
+                    "The primary subreddit {} can't also be a crosspost subreddit",
+                    primary_subreddit_id
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The JSON stored in the `crossposting` column, `None` when nothing is crossposted.
+    pub fn to_column(&self) -> Result<Option<String>, ApiError> {
+        if self.primary_subreddit_id.is_none() || self.crosspost_subreddit_ids.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::to_string(self)?))
+    }
+
+    /// The primary subreddit to crosspost from, `None` when the subreddit gets a link post.
+    pub fn crosspost_source(&self, subreddit_id: i64) -> Option<i64> {
+        if self.crosspost_subreddit_ids.contains(&subreddit_id) {
+            return self.primary_subreddit_id;
+        }
+
+        None
+    }
 }
 
 #[derive(Deserialize, ToSchema, Debug)]
@@ -60,7 +211,9 @@ pub struct Feed {
     pub links: Vec<Link>,
     pub title: String,
     pub updated: DateTime<Utc>,
-    pub entry: Entry,
+    /// The hub can batch several entries in one notification.
+    #[serde(rename = "entry", default)]
+    pub entries: Vec<Entry>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
@@ -88,7 +241,50 @@ pub struct Entry {
     pub updated: DateTime<Utc>,
 }
 
+/// Sent instead of an entry when a video is deleted or made private.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DeletedFeed {
+    #[serde(rename = "deleted-entry")]
+    pub deleted_entry: DeletedEntry,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DeletedEntry {
+    /// In the form of "yt:video:{video id}".
+    #[serde(rename = "@ref")]
+    pub r#ref: String,
+    #[serde(rename = "@when")]
+    pub when: DateTime<Utc>,
+    pub by: Option<Author>,
+}
+
+impl DeletedEntry {
+    pub fn yt_video_id(&self) -> &str {
+        self.r#ref.strip_prefix("yt:video:").unwrap_or(&self.r#ref)
+    }
+}
+
+pub enum FeedNotification {
+    NewEntry(Feed),
+    DeletedEntry(DeletedFeed),
+}
+
+impl FeedNotification {
+    /// The deleted-entry form is only tried when the body isn't a regular feed,
+    /// the regular feed's error is returned if neither matches.
+    pub fn parse(body: &str) -> Result<Self, quick_xml::DeError> {
+        match quick_xml::de::from_str::<Feed>(body) {
+            Ok(feed) => Ok(FeedNotification::NewEntry(feed)),
+            Err(error) => match quick_xml::de::from_str::<DeletedFeed>(body) {
+                Ok(deleted_feed) => Ok(FeedNotification::DeletedEntry(deleted_feed)),
+                Err(_) => Err(error),
+            },
+        }
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SimpleEntry {
     pub id: String,
     pub yt_video_id: String,
@@ -98,6 +294,9 @@ pub struct SimpleEntry {
     pub author: Author,
     pub published: DateTime<Utc>,
     pub updated: DateTime<Utc>,
+    /// The largest thumbnail from the YouTube Data API, only set when a YouTube API key is configured.
+    #[serde(default)]
+    pub thumbnail_url: Option<String>,
 }
 
 impl Into<Option<SimpleEntry>> for &Entry {
@@ -118,6 +317,7 @@ impl Into<Option<SimpleEntry>> for &Entry {
                 author: self.author.clone(),
                 published: self.published,
                 updated: self.updated,
+                thumbnail_url: None,
             }),
             None => None,
         }
@@ -144,13 +344,34 @@ pub struct RedditAccount {
     pub moderate_submissions: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct Subreddit {
     pub id: i64,
     pub name: String,
     pub title_prefix: Option<String>,
     pub title_suffix: Option<String>,
+    pub title_template: Option<String>,
     pub flair_id: Option<String>,
+    /// Sent with the flair id, or on its own for subreddits whose flair has no templates.
+    pub flair_text: Option<String>,
+    pub nsfw: bool,
+    pub spoiler: bool,
+    pub min_account_age_days: Option<i64>,
+    pub min_karma: Option<i64>,
+    pub comment_template: Option<String>,
+    pub sticky_comment: bool,
+    /// Submits the video's thumbnail with the video link as the first comment.
+    pub thumbnail_post: bool,
+    /// Set when Reddit permanently refused a submission, e.g. because the account is banned.
+    /// No videos are submitted to the subreddit until it's cleared.
+    pub posting_disabled_reason: Option<String>,
+}
+
+/// Cached `/api/v1/me` values of a Reddit account.
+pub struct RedditAccountStats {
+    pub created_utc: i64,
+    pub total_karma: i64,
+    pub fetched_at: i64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -182,11 +403,106 @@ pub enum FormType {
     Youtube,
 }
 
+#[derive(Serialize, ToSchema, sqlx::Type, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum SubscriptionStatus {
+    /// A subscribe request was sent and the hub hasn't verified it yet.
+    Pending,
+    Active,
+    /// The lease ran out without being renewed.
+    Expired,
+}
+
+/// Google's PubSubHubbub hub, subscribe and unsubscribe requests are both sent here.
+pub const PUBSUBHUBBUB_HUB_URL: &str = "https://pubsubhubbub.appspot.com/subscribe";
+
+#[cfg(test)]
+thread_local! {
+    /// The mock hub of the running test, a `#[tokio::test]` runs on its own thread.
+    pub static MOCK_HUB_URL: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+    /// The mock youtube.com of the running test, the channel feeds are fetched from it.
+    pub static MOCK_YOUTUBE_URL: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+    /// The mock Reddit API of the running test, used for both the www and OAuth base URLs.
+    pub static MOCK_REDDIT_URL: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+/// The hub the subscribe and unsubscribe requests are sent to.
+fn hub_url() -> String {
+    #[cfg(test)]
+    if let Some(mock_hub_url) = MOCK_HUB_URL.with_borrow(|url| url.clone()) {
+        return mock_hub_url;
+    }
+
+    PUBSUBHUBBUB_HUB_URL.to_string()
+}
+
+#[derive(Serialize, ToSchema, sqlx::Type, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum FailedSubmissionStatus {
+    /// The scheduler retries the submission at `next_attempt_at`.
+    Pending,
+    /// The retries ran out.
+    Failed,
+}
+
+/// How long before the lease expires a subscription is renewed, 1 hour.
+pub const RESUBSCRIBE_EARLY_SECS: i64 = 3600;
+
 pub enum SubCommand {
     Schedule {
         subscription_id: String,
         wait_secs: i64,
     },
+    RetrySubmission {
+        submission: Box<PendingSubmission>,
+        wait_secs: i64,
+    },
+    RefreshToken {
+        reddit_account_id: String,
+        wait_secs: i64,
+    },
+    CheckVerification {
+        verification: Box<PendingVerification>,
+        wait_secs: i64,
+    },
+    DeferEntry {
+        subscription_id: String,
+        entry: Box<SimpleEntry>,
+        wait_secs: i64,
+    },
+    CleanupFormData {
+        wait_secs: i64,
+    },
+    RetryFailedSubmission {
+        failed_submission_id: i64,
+        wait_secs: i64,
+    },
+}
+
+/// A subscribe request the hub hasn't verified yet, the scheduler checks it once the timeout is over.
+pub struct PendingVerification {
+    pub subscription_id: String,
+    pub channel_id: String,
+    pub hmac_secret: String,
+    pub attempt: u32,
+}
+
+/// A submission Reddit rate limited, the scheduler submits it again once the wait is over.
+pub struct PendingSubmission {
+    pub subscription_id: String,
+    pub reddit_account_id: String,
+    pub subreddit_id: i64,
+    pub entry: SimpleEntry,
+    pub attempt: u32,
+}
+
+/// An id and name pair for the choices of a form's select.
+#[derive(Serialize)]
+pub struct SelectOption {
+    pub id: String,
+    pub name: String,
 }
 
 #[derive(Serialize)]
@@ -197,21 +513,155 @@ pub struct LinkedSubscription {
     pub reddit_username: String,
     pub subreddit_id: i64,
     pub subreddit_name: String,
+    /// Overrides the Reddit account's `moderate_submissions` on this subreddit.
+    pub moderate_submissions: Option<bool>,
 }
 
 // Static vars
+#[derive(Debug, Clone, Copy)]
+pub struct HttpTimeouts {
+    pub connect_secs: u64,
+    pub request_secs: u64,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            connect_secs: 10,
+            request_secs: 30,
+        }
+    }
+}
+
+static HTTP_TIMEOUTS: OnceLock<HttpTimeouts> = OnceLock::new();
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+
+/// The Reddit base URLs, overridable to point the bot at a mock server or a proxy.
+#[derive(Debug, Clone)]
+pub struct RedditUrls {
+    /// Used for the OAuth authorization and token requests and the public listings.
+    pub www: String,
+    /// Used for the requests authenticated with an account's access token.
+    pub oauth: String,
+}
+
+impl Default for RedditUrls {
+    fn default() -> Self {
+        Self {
+            www: "https://www.reddit.com".to_string(),
+            oauth: "https://oauth.reddit.com".to_string(),
+        }
+    }
+}
+
+static REDDIT_URLS: OnceLock<RedditUrls> = OnceLock::new();
+static HUB_TEST_MODE: OnceLock<bool> = OnceLock::new();
+
+/// Sets whether subscribe and unsubscribe requests skip the real hub, for local development.
+pub fn configure_hub_test_mode(enabled: bool) {
+    let _ = HUB_TEST_MODE.set(enabled);
+}
+
+pub fn hub_test_mode() -> bool {
+    HUB_TEST_MODE.get().copied().unwrap_or(false)
+}
+
+/// Sets the Reddit base URLs, has to be called before the first Reddit request.
+pub fn configure_reddit_urls(urls: RedditUrls) {
+    if let Err(urls) = REDDIT_URLS.set(urls) {
+        eprintln!(
+            "The Reddit base URLs were already configured, ignoring: {:?}",
+            urls
+        );
+    }
+}
+
+fn reddit_urls() -> RedditUrls {
+    #[cfg(test)]
+    if let Some(mock_reddit_url) = MOCK_REDDIT_URL.with_borrow(|url| url.clone()) {
+        return RedditUrls {
+            www: mock_reddit_url.clone(),
+            oauth: mock_reddit_url,
+        };
+    }
+
+    REDDIT_URLS.get_or_init(RedditUrls::default).clone()
+}
+
+/// The `path` on the www Reddit base URL, e.g. `/api/v1/access_token`.
+pub fn reddit_url(path: &str) -> String {
+    format!("{}{}", reddit_urls().www, path)
+}
+
+/// The `path` on the OAuth Reddit base URL, e.g. `/api/submit`.
+pub fn reddit_oauth_url(path: &str) -> String {
+    format!("{}{}", reddit_urls().oauth, path)
+}
+
+/// Sets the timeouts and user agent of `HTTP_CLIENT`, has to be called before the client is first used.
+pub fn configure_http_client(timeouts: HttpTimeouts, operator: Option<&str>) {
+    if HTTP_TIMEOUTS.set(timeouts).is_err() {
+        eprintln!(
+            "The HTTP client timeouts were already configured, ignoring: {:?}",
+            timeouts
+        );
+    }
+
+    if USER_AGENT.set(user_agent(operator)).is_err() {
+        eprintln!("The HTTP client user agent was already configured, ignoring it.");
+    }
+}
+
+/// Reddit throttles generic or shared user agents, so it names the Reddit user running the bot,
+/// e.g. `reddit_youtube_bot/0.2.0 (by /u/operator)`.
+fn user_agent(operator: Option<&str>) -> String {
+    let app = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    match operator.map(|operator| operator.trim_start_matches('/').trim_start_matches("u/")) {
+        Some(operator) => format!("{} (by /u/{})", app, operator),
+        None => format!(
+            "{} (by Tomas R J. Source code: {})",
+            app,
+            env!("CARGO_PKG_REPOSITORY")
+        ),
+    }
+}
+
 pub static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    let timeouts = HTTP_TIMEOUTS.get().copied().unwrap_or_default();
+    let user_agent = USER_AGENT
+        .get()
+        .cloned()
+        .unwrap_or_else(|| user_agent(None));
+
+    build_http_client(
+        Duration::from_secs(timeouts.connect_secs),
+        Duration::from_secs(timeouts.request_secs),
+        user_agent,
+    )
+});
+
+fn build_http_client(
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    user_agent: String,
+) -> Client {
     Client::builder()
-        .user_agent("reddit_youtube_bot v0.1.0 by Tomas R J. Source code: https://github.com/TomasRJ/reddit_youtube_bot")
+        .user_agent(user_agent)
+        .connect_timeout(connect_timeout)
+        .timeout(request_timeout)
         .build()
         .expect("Failed to create HTTP client")
-});
+}
 
+/// A malformed channel id is rejected, a subscription for it would never get any feeds.
 pub fn extract_channel_id_from_topic_url(topic_url: &String) -> Result<&str, ApiError> {
     if let Some(("https://www.youtube.com/xml/feeds/videos.xml?channel_id", channel_id)) =
         topic_url.split_once('=')
     {
-        Ok(channel_id.trim())
+        let channel_id = channel_id.trim();
+        validate_channel_id(channel_id)?;
+        Ok(channel_id)
     } else {
         Err(ApiError::BadRequest(format!(
             "The topic URL has to contain 'https://www.youtube.com/xml/feeds/videos.xml?channel_id=', the input was: {:}",
@@ -220,20 +670,318 @@ pub fn extract_channel_id_from_topic_url(topic_url: &String) -> Result<&str, Api
     }
 }
 
+/// How long a fetched channel is reused before the channel's feed is fetched again, 1 hour.
+const CHANNEL_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// The hub topic of a YouTube channel, the URL its videos are subscribed to with.
+pub fn youtube_topic_url(channel_id: &str) -> String {
+    format!(
+        "https://www.youtube.com/xml/feeds/videos.xml?channel_id={}",
+        channel_id
+    )
+}
+
+/// A channel resolved from YouTube, with the canonical topic URL of its id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedChannel {
+    pub channel_name: String,
+    pub topic_url: String,
+}
+
+impl CachedChannel {
+    pub fn new(channel_id: &str, channel_name: &str) -> Self {
+        Self {
+            channel_name: channel_name.to_string(),
+            topic_url: youtube_topic_url(channel_id),
+        }
+    }
+}
+
+/// Channels by channel id, so e.g. a burst of subscriptions to the same channel fetches its feed once.
+#[derive(Default)]
+pub struct ChannelCache(RwLock<HashMap<String, (CachedChannel, Instant)>>);
+
+impl ChannelCache {
+    pub async fn get(&self, channel_id: &str) -> Option<CachedChannel> {
+        self.0
+            .read()
+            .await
+            .get(channel_id)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < CHANNEL_CACHE_TTL)
+            .map(|(channel, _)| channel.clone())
+    }
+
+    pub async fn insert(&self, channel_id: &str, channel: CachedChannel) {
+        let mut channels = self.0.write().await;
+        // Expired channels are dropped here, so channels that aren't looked up again don't pile up.
+        channels.retain(|_, (_, fetched_at)| fetched_at.elapsed() < CHANNEL_CACHE_TTL);
+        channels.insert(channel_id.to_string(), (channel, Instant::now()));
+    }
+}
+
+/// The largest thumbnail YouTube generates, not every video has one.
+pub fn youtube_thumbnail_url(video_id: &str) -> String {
+    format!("https://i.ytimg.com/vi/{}/maxresdefault.jpg", video_id)
+}
+
+/// The short link of a submission, the id is the fullname Reddit returned, e.g. `t3_1abcde`.
+pub fn reddit_submission_permalink(submission_id: &str) -> String {
+    format!(
+        "https://redd.it/{}",
+        submission_id.strip_prefix("t3_").unwrap_or(submission_id)
+    )
+}
+
+/// The variables a subreddit title or comment template can reference, e.g. `"[{channel_name}] {title} ({date})"`.
+/// `date` is an alias of `published`.
+pub const TITLE_TEMPLATE_VARIABLES: [&str; 6] = [
+    "title",
+    "channel_name",
+    "channel_url",
+    "video_id",
+    "published",
+    "date",
+];
+
+enum TitleTemplatePart<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// Splits a title template into text and `{variable}` parts, `{{` and `}}` are literal braces.
+fn parse_title_template(template: &str) -> Result<Vec<TitleTemplatePart<'_>>, ApiError> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        if let Some(escaped) = rest.strip_prefix("{{") {
+            parts.push(TitleTemplatePart::Text("{"));
+            rest = escaped;
+        } else if let Some(escaped) = rest.strip_prefix("}}") {
+            parts.push(TitleTemplatePart::Text("}"));
+            rest = escaped;
+        } else if let Some(placeholder) = rest.strip_prefix('{') {
+            let (variable, remaining) =
+                placeholder
+                    .split_once('}')
+                    .ok_or(ApiError::BadRequest(format!(
+                        "Unclosed '{{' in the title template: {}",
+                        template
+                    )))?;
+            let variable = variable.trim();
+
+            if !TITLE_TEMPLATE_VARIABLES.contains(&variable) {
+                return Err(ApiError::BadRequest(format!(
+                    "Unknown title template variable '{{{}}}', the available variables are: {}",
+                    variable,
+                    TITLE_TEMPLATE_VARIABLES.join(", ")
+                )));
+            }
+
+            parts.push(TitleTemplatePart::Variable(variable));
+            rest = remaining;
+        } else if rest.starts_with('}') {
+            return Err(ApiError::BadRequest(format!(
+                "Unmatched '}}' in the title template, use '}}}}' for a literal brace: {}",
+                template
+            )));
+        } else {
+            let end = rest.find(['{', '}']).unwrap_or(rest.len());
+            parts.push(TitleTemplatePart::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    Ok(parts)
+}
+
+pub fn validate_title_template(template: &str) -> Result<(), ApiError> {
+    parse_title_template(template)?;
+    Ok(())
+}
+
+/// Renders the template for the entry, `video_title` is used for the `{title}` variable
+/// so a shortened video title can be rendered.
+pub fn render_title_template(
+    template: &str,
+    video_title: &str,
+    entry: &SimpleEntry,
+) -> Result<String, ApiError> {
+    let mut title = String::new();
+
+    for part in parse_title_template(template)? {
+        match part {
+            TitleTemplatePart::Text(text) => title.push_str(text),
+            TitleTemplatePart::Variable("title") => title.push_str(video_title),
+            TitleTemplatePart::Variable("channel_name") => title.push_str(&entry.author.name),
+            TitleTemplatePart::Variable("channel_url") => title.push_str(&entry.author.uri),
+            TitleTemplatePart::Variable("video_id") => title.push_str(&entry.yt_video_id),
+            TitleTemplatePart::Variable("published" | "date") => {
+                title.push_str(&entry.published.format("%Y-%m-%d").to_string())
+            }
+            TitleTemplatePart::Variable(_) => unreachable!("validated in parse_title_template"),
+        }
+    }
+
+    Ok(title)
+}
+
+/// YouTube channel ids are "UC" followed by 22 base64url characters.
+pub fn validate_channel_id(channel_id: &str) -> Result<(), ApiError> {
+    let valid = channel_id.len() == 24
+        && channel_id.starts_with("UC")
+        && channel_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if !valid {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid YouTube channel id, expected 'UC' followed by 22 characters, the input was: {}",
+            channel_id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Google's hub only delivers to publicly reachable HTTPS endpoints,
+/// so loopback and private addresses would never get verified.
+pub fn validate_callback_url(callback_url: &str) -> Result<(), ApiError> {
+    let url = Url::parse(callback_url).map_err(|e| {
+        ApiError::BadRequest(format!(
+            "Invalid callback URL: {}, error: {}",
+            callback_url, e
+        ))
+    })?;
+
+    if url.scheme() != "https" {
+        return Err(ApiError::BadRequest(format!(
+            "The callback URL has to use HTTPS for Google PubSubHubbub, the URL was: {}",
+            callback_url
+        )));
+    }
+
+    let is_public = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.to_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(ip)) => is_public_ip(&IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public_ip(&IpAddr::V6(ip)),
+        None => false,
+    };
+
+    if !is_public {
+        return Err(ApiError::BadRequest(format!(
+            "The callback URL has to be publicly reachable for Google PubSubHubbub, the URL was: {}",
+            callback_url
+        )));
+    }
+
+    Ok(())
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => {
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
+
+/// Sends a HEAD request to the callback URL, any HTTP response counts as reachable.
+pub async fn check_callback_reachable(callback_url: &str) -> Result<(), ApiError> {
+    HTTP_CLIENT.head(callback_url).send().await.map_err(|e| {
+        ApiError::BadRequest(format!(
+            "The callback URL isn't reachable: {}, error: {}",
+            callback_url, e
+        ))
+    })?;
+
+    Ok(())
+}
+
+/// Reddit subreddit names are 3 to 21 letters, digits or underscores and can't start with an underscore.
+pub fn validate_subreddit_name(name: &str) -> Result<(), ApiError> {
+    let valid = (3..=21).contains(&name.len())
+        && !name.starts_with('_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !valid {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid subreddit name, expected 3 to 21 letters, digits or underscores, the input was: {}",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn unsubscribe_from_channel(
+    callback_url: &String,
+    channel_id: &String,
+) -> Result<(), ApiError> {
+    if hub_test_mode() {
+        println!(
+            "Hub test mode: not sending the unsubscribe request for the YouTube channel {} to the hub.",
+            channel_id
+        );
+        return Ok(());
+    }
+
+    let subscription_client = &HTTP_CLIENT;
+
+    let topic_url = youtube_topic_url(channel_id);
+
+    let unsubscribe_res = subscription_client
+        .post(hub_url())
+        .form(&[
+            ("hub.callback", callback_url),
+            ("hub.mode", &"unsubscribe".to_string()),
+            ("hub.topic", &topic_url),
+        ])
+        .send()
+        .await?;
+
+    check_hub_response("unsubscribe", unsubscribe_res).await?;
+
+    println!(
+        "Successfully sent Google PubSubHubbub unsubscribe request, now waiting for verification"
+    );
+
+    Ok(())
+}
+
 pub async fn subscribe_to_channel(
     callback_url: &String,
     channel_id: &String,
     hmac_secret: &String,
 ) -> Result<(), ApiError> {
+    // The subscription is verified locally instead, see `schedule_verification_check`.
+    if hub_test_mode() {
+        println!(
+            "Hub test mode: not sending the subscribe request for the YouTube channel {} to the hub.",
+            channel_id
+        );
+        return Ok(());
+    }
+
     let subscription_client = &HTTP_CLIENT;
 
-    let topic_url = format!(
-        "https://www.youtube.com/xml/feeds/videos.xml?channel_id={}",
-        &channel_id
-    );
+    let topic_url = youtube_topic_url(channel_id);
 
     let subscription_res = subscription_client
-        .post("https://pubsubhubbub.appspot.com/subscribe")
+        .post(hub_url())
         .form(&[
             ("hub.callback", callback_url),
             ("hub.mode", &"subscribe".to_string()),
@@ -243,10 +991,252 @@ pub async fn subscribe_to_channel(
         .send()
         .await?;
 
-    Ok(match subscription_res.error_for_status() {
-        Ok(_) => println!(
-            "Successfully sent Google PubSubHubbub subscription request, now waiting for verification"
-        ),
-        Err(err) => return Err(err.into()),
-    })
+    check_hub_response("subscribe", subscription_res).await?;
+
+    println!(
+        "Successfully sent Google PubSubHubbub subscription request, now waiting for verification"
+    );
+
+    Ok(())
+}
+
+/// The hub explains why it refused a request in the response body, e.g. an unreachable callback,
+/// so the body is kept in the error instead of only the status.
+async fn check_hub_response(mode: &str, hub_response: reqwest::Response) -> Result<(), ApiError> {
+    let status = hub_response.status();
+
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let body = hub_response.text().await.unwrap_or_default();
+
+    Err(ApiError::InternalError(format!(
+        "Google PubSubHubbub refused the {} request with {}: {}",
+        mode,
+        status,
+        body.trim()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+    use super::*;
+    use crate::server::test_support::{CHANNEL_ID, feed_entry, feed_xml, simple_entry, topic_url};
+
+    #[tokio::test]
+    async fn slow_server_times_out_as_an_internal_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+        let client = build_http_client(
+            Duration::from_secs(1),
+            Duration::from_millis(200),
+            user_agent(None),
+        );
+
+        let error = client.get(server.uri()).send().await.unwrap_err();
+
+        let ApiError::InternalError(message) = ApiError::from(error) else {
+            panic!("the timeout wasn't mapped to an internal error");
+        };
+        assert!(message.contains("timed out"), "{}", message);
+    }
+
+    #[test]
+    fn feed_notification_parses_every_entry_of_a_batch() {
+        let published = "2026-01-02T03:04:05Z".parse().unwrap();
+        let body = feed_xml(&[
+            feed_entry("video1", "First", published),
+            feed_entry("video2", "Second", published),
+        ]);
+
+        let Ok(FeedNotification::NewEntry(feed)) = FeedNotification::parse(&body) else {
+            panic!("the feed wasn't parsed as new entries");
+        };
+
+        let video_ids: Vec<&str> = feed
+            .entries
+            .iter()
+            .map(|entry| entry.yt_video_id.as_str())
+            .collect();
+        assert_eq!(video_ids, ["video1", "video2"]);
+        assert_eq!(feed.entries[1].title, "Second");
+        assert_eq!(feed.entries[0].yt_channel_id, CHANNEL_ID);
+    }
+
+    #[test]
+    fn feed_notification_parses_a_deleted_entry() {
+        let body = format!(
+            r#"<?xml version='1.0' encoding='UTF-8'?>
+<feed xmlns:at="http://purl.org/atompub/tombstones/1.0" xmlns="http://www.w3.org/2005/Atom"><at:deleted-entry ref="yt:video:video1" when="2026-01-03T10:00:00.552394234+00:00"><link href="https://www.youtube.com/watch?v=video1"/><at:by><name>Channel</name><uri>https://www.youtube.com/channel/{CHANNEL_ID}</uri></at:by></at:deleted-entry></feed>"#
+        );
+
+        let Ok(FeedNotification::DeletedEntry(deleted_feed)) = FeedNotification::parse(&body)
+        else {
+            panic!("the feed wasn't parsed as a deleted entry");
+        };
+
+        assert_eq!(deleted_feed.deleted_entry.yt_video_id(), "video1");
+        assert_eq!(deleted_feed.deleted_entry.by.unwrap().name, "Channel");
+    }
+
+    #[test]
+    fn feed_notification_refuses_other_xml() {
+        assert!(FeedNotification::parse("<feed><title>Not a video</title></feed>").is_err());
+    }
+
+    #[test]
+    fn title_template_renders_every_variable() {
+        let entry = simple_entry("video1", "A video");
+
+        let title = render_title_template(
+            "[{channel_name}] {title} ({ published }) {video_id} {channel_url} {date} {{tag}}",
+            "A shorter video",
+            &entry,
+        )
+        .unwrap();
+
+        assert_eq!(
+            title,
+            format!(
+                "[Channel] A shorter video (2026-01-02) video1 https://www.youtube.com/channel/{} 2026-01-02 {{tag}}",
+                CHANNEL_ID
+            )
+        );
+    }
+
+    #[test]
+    fn title_template_refuses_unknown_variables_and_unmatched_braces() {
+        for template in ["{description}", "{title", "title}", "{}"] {
+            assert!(
+                matches!(
+                    validate_title_template(template),
+                    Err(ApiError::BadRequest(_))
+                ),
+                "{}",
+                template
+            );
+        }
+
+        assert!(validate_title_template("{{title}} {title}").is_ok());
+    }
+
+    #[test]
+    fn channel_ids_are_uc_and_22_characters() {
+        assert!(validate_channel_id(CHANNEL_ID).is_ok());
+        assert!(validate_channel_id("UC_x5XG1OV2P6uZZ5FSM9Ttw").is_ok());
+
+        for channel_id in [
+            "",
+            "UCBR8-60-B28hp2BmDPdntc",
+            "UCBR8-60-B28hp2BmDPdntcQQ",
+            "UUBR8-60-B28hp2BmDPdntcQ",
+            "UCBR8-60-B28hp2BmDPdnt Q",
+        ] {
+            assert!(validate_channel_id(channel_id).is_err(), "{}", channel_id);
+        }
+    }
+
+    #[test]
+    fn topic_urls_give_their_trimmed_channel_id() {
+        assert_eq!(
+            extract_channel_id_from_topic_url(&topic_url(CHANNEL_ID)).unwrap(),
+            CHANNEL_ID
+        );
+        assert_eq!(
+            extract_channel_id_from_topic_url(&format!("{} \n", topic_url(CHANNEL_ID))).unwrap(),
+            CHANNEL_ID
+        );
+    }
+
+    #[test]
+    fn topic_urls_with_a_malformed_channel_id_are_refused() {
+        for url in [
+            topic_url("UCBR8-60-B28hp2BmDPdntc"),
+            topic_url("UUBR8-60-B28hp2BmDPdntcQ"),
+            topic_url(""),
+            format!(
+                "https://www.youtube.com/feeds/videos.xml?channel_id={}",
+                CHANNEL_ID
+            ),
+        ] {
+            assert!(
+                matches!(
+                    extract_channel_id_from_topic_url(&url),
+                    Err(ApiError::BadRequest(_))
+                ),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn thumbnail_url_is_the_videos_maxres_image() {
+        assert_eq!(
+            youtube_thumbnail_url("dQw4w9WgXcQ"),
+            "https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg"
+        );
+    }
+
+    #[test]
+    fn subreddit_names_are_3_to_21_word_characters() {
+        for name in ["abc", "videos", "Music_Videos", "a23456789012345678901"] {
+            assert!(validate_subreddit_name(name).is_ok(), "{}", name);
+        }
+
+        for name in [
+            "ab",
+            "a234567890123456789012",
+            "_videos",
+            "r/videos",
+            "my-videos",
+        ] {
+            assert!(validate_subreddit_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn callback_urls_have_to_be_public_https() {
+        assert!(validate_callback_url("https://bot.example.com/google/subscription/1").is_ok());
+        assert!(validate_callback_url("https://203.0.113.10/callback").is_ok());
+
+        for callback_url in [
+            "not a url",
+            "http://bot.example.com/callback",
+            "https://localhost/callback",
+            "https://bot.localhost/callback",
+            "https://127.0.0.1/callback",
+            "https://192.168.1.10/callback",
+            "https://10.0.0.1/callback",
+            "https://169.254.1.1/callback",
+            "https://[::1]/callback",
+            "https://[fd00::1]/callback",
+        ] {
+            assert!(
+                validate_callback_url(callback_url).is_err(),
+                "{}",
+                callback_url
+            );
+        }
+    }
+
+    #[test]
+    fn crossposting_needs_a_primary_subreddit_outside_the_crossposts() {
+        let settings = |primary_subreddit_id, crosspost_subreddit_ids| CrosspostSettings {
+            primary_subreddit_id,
+            crosspost_subreddit_ids,
+        };
+
+        assert!(settings(None, vec![]).validate().is_ok());
+        assert!(settings(Some(1), vec![]).validate().is_ok());
+        assert!(settings(Some(1), vec![2, 3]).validate().is_ok());
+        assert!(settings(None, vec![2]).validate().is_err());
+        assert!(settings(Some(1), vec![1, 2]).validate().is_err());
+    }
 }
@@ -0,0 +1,164 @@
+//! Fixtures shared by the tests, created through the same repository functions and handlers the bot uses.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    infrastructure::AppState,
+    server::{
+        google::verify_subscription,
+        repository::{get_or_create_subreddit, save_form_data, save_reddit_account},
+        shared::{
+            Author, FormType, Link, RedditOAuthToken, SimpleEntry, Subreddit, TitleFilters,
+            Verification, VerificationMode, YouTubeSubscription,
+        },
+    },
+};
+
+pub const CHANNEL_ID: &str = "UCBR8-60-B28hp2BmDPdntcQ";
+pub const OLD_CHANNEL_ID: &str = "UC_x5XG1OV2P6uZZ5FSM9Ttw";
+
+pub fn topic_url(channel_id: &str) -> String {
+    format!(
+        "https://www.youtube.com/xml/feeds/videos.xml?channel_id={}",
+        channel_id
+    )
+}
+
+/// The entry the feed of `CHANNEL_ID` has for the video, published 2026-01-02.
+pub fn simple_entry(video_id: &str, title: &str) -> SimpleEntry {
+    let published: DateTime<Utc> = "2026-01-02T03:04:05Z".parse().unwrap();
+
+    SimpleEntry {
+        id: format!("yt:video:{}", video_id),
+        yt_video_id: video_id.to_string(),
+        yt_channel_id: CHANNEL_ID.to_string(),
+        title: title.to_string(),
+        link: Link {
+            rel: "alternate".to_string(),
+            href: format!("https://www.youtube.com/watch?v={}", video_id),
+            hreflang: None,
+        },
+        author: Author {
+            name: "Channel".to_string(),
+            uri: format!("https://www.youtube.com/channel/{}", CHANNEL_ID),
+        },
+        published,
+        updated: published,
+        thumbnail_url: None,
+    }
+}
+
+/// An entry of the feed of `CHANNEL_ID`.
+pub fn feed_entry(video_id: &str, title: &str, published: DateTime<Utc>) -> String {
+    format!(
+        r#"<entry>
+  <id>yt:video:{video_id}</id>
+  <yt:videoId>{video_id}</yt:videoId>
+  <yt:channelId>{CHANNEL_ID}</yt:channelId>
+  <title>{title}</title>
+  <link rel="alternate" href="https://www.youtube.com/watch?v={video_id}"/>
+  <author>
+   <name>Channel</name>
+   <uri>https://www.youtube.com/channel/{CHANNEL_ID}</uri>
+  </author>
+  <published>{published}</published>
+  <updated>{published}</updated>
+ </entry>"#,
+        published = published.to_rfc3339()
+    )
+}
+
+/// The feed of `CHANNEL_ID` with the entries, the form of both the hub's notifications and the channel's feed.
+pub fn feed_xml(entries: &[String]) -> String {
+    format!(
+        r#"<?xml version='1.0' encoding='UTF-8'?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom"><link rel="hub" href="https://pubsubhubbub.appspot.com"/><link rel="self" href="https://www.youtube.com/xml/feeds/videos.xml?channel_id={CHANNEL_ID}"/><title>YouTube video feed</title><author><name>Channel</name><uri>https://www.youtube.com/channel/{CHANNEL_ID}</uri></author><updated>2026-01-02T03:04:06.916571846+00:00</updated>{}</feed>"#,
+        entries.join("")
+    )
+}
+
+pub fn subscription_form(channel_id: &str) -> YouTubeSubscription {
+    YouTubeSubscription {
+        r#type: FormType::Youtube,
+        topic_url: topic_url(channel_id),
+        channel_id: channel_id.to_string(),
+        hmac_secret: "secret".to_string(),
+        post_shorts: false,
+        min_duration_seconds: None,
+        reddit_account_ids: Vec::new(),
+        subreddit_ids: Vec::new(),
+        title_filters: TitleFilters::default(),
+        posting_cutoff: None,
+        channel_name: Some("Channel".to_string()),
+    }
+}
+
+/// Stores the subscribe form and verifies it like the hub does, returns the subscription id.
+pub async fn verified_subscription(state: &Arc<AppState>, channel_id: &str) -> String {
+    verified_subscription_from_form(state, &subscription_form(channel_id)).await
+}
+
+pub async fn verified_subscription_from_form(
+    state: &Arc<AppState>,
+    subscription_form: &YouTubeSubscription,
+) -> String {
+    let subscription_id = Uuid::now_v7().to_string();
+
+    save_form_data(
+        &state.db_pool,
+        &subscription_id,
+        &serde_json::to_string(subscription_form).unwrap(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let verification = Verification {
+        mode: VerificationMode::Subscribe,
+        topic: subscription_form.topic_url.clone(),
+        challenge: "subscribe-challenge".to_string(),
+        lease_seconds: Some(432000),
+    };
+    verify_subscription(state, &subscription_id, verification)
+        .await
+        .unwrap();
+
+    subscription_id
+}
+
+pub async fn reddit_account(state: &Arc<AppState>, username: &str) -> String {
+    let oauth_token = RedditOAuthToken {
+        access_token: "access_token".to_string(),
+        token_type: "bearer".to_string(),
+        expires_in: 86400,
+        scope: "submit".to_string(),
+        refresh_token: Some("refresh_token".to_string()),
+    };
+
+    save_reddit_account(&state.db_pool, &username.to_string(), &oauth_token, &false)
+        .await
+        .unwrap()
+}
+
+pub async fn subreddit(state: &Arc<AppState>, name: &str) -> Subreddit {
+    get_or_create_subreddit(&state.db_pool, &name.to_string(), &None)
+        .await
+        .unwrap()
+}
+
+/// A subscription submitting with the Reddit account to the subreddit, returns the subscription id.
+pub async fn linked_subscription(
+    state: &Arc<AppState>,
+    channel_id: &str,
+    reddit_account_id: &str,
+    subreddit: &Subreddit,
+) -> String {
+    let mut subscription_form = subscription_form(channel_id);
+    subscription_form.reddit_account_ids = vec![reddit_account_id.to_string()];
+    subscription_form.subreddit_ids = vec![subreddit.id];
+
+    verified_subscription_from_form(state, &subscription_form).await
+}
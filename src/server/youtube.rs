@@ -0,0 +1,737 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    infrastructure::{AppState, RegionBlockAction},
+    server::{
+        ApiError,
+        repository::{Subscription, fetch_video_duration, save_region_check, save_video_duration},
+        shared::{Author, HTTP_CLIENT, Link, SimpleEntry},
+    },
+};
+
+#[derive(Deserialize, Debug)]
+struct VideoListResponse {
+    items: Vec<VideoDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoDetails {
+    pub content_details: ContentDetails,
+    pub snippet: Option<VideoMetadata>,
+}
+
+/// The canonical video metadata, the feed only has what the hub pushed.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+    pub title: String,
+    pub channel_title: String,
+    #[serde(default)]
+    pub thumbnails: Thumbnails,
+}
+
+/// Source: https://developers.google.com/youtube/v3/docs/thumbnails, only the sizes
+/// the video has are included.
+#[derive(Deserialize, Debug, Default)]
+pub struct Thumbnails {
+    maxres: Option<Thumbnail>,
+    standard: Option<Thumbnail>,
+    high: Option<Thumbnail>,
+    medium: Option<Thumbnail>,
+    default: Option<Thumbnail>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Thumbnail {
+    pub url: String,
+}
+
+impl Thumbnails {
+    /// The URL of the largest thumbnail the video has.
+    pub fn largest_url(&self) -> Option<&String> {
+        [
+            &self.maxres,
+            &self.standard,
+            &self.high,
+            &self.medium,
+            &self.default,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|thumbnail| &thumbnail.url)
+        .next()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentDetails {
+    /// ISO 8601 duration, e.g. `PT4M13S`.
+    pub duration: Option<String>,
+    pub region_restriction: Option<RegionRestriction>,
+}
+
+/// Parses the ISO 8601 durations the YouTube API returns (`P#DT#H#M#S`) into seconds.
+pub fn parse_duration_seconds(duration: &str) -> Option<i64> {
+    let rest = duration.strip_prefix('P')?;
+    let (days, time) = match rest.split_once('T') {
+        Some((days, time)) => (days, time),
+        None => (rest, ""),
+    };
+
+    let mut seconds = 0;
+
+    if !days.is_empty() {
+        seconds += days.strip_suffix('D')?.parse::<i64>().ok()? * 24 * 60 * 60;
+    }
+
+    let mut number = String::new();
+    for c in time.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value = number.parse::<i64>().ok()?;
+        number.clear();
+
+        seconds += match c {
+            'H' => value * 60 * 60,
+            'M' => value * 60,
+            'S' => value,
+            _ => return None,
+        };
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    Some(seconds)
+}
+
+/// Source: https://developers.google.com/youtube/v3/docs/videos#contentDetails.regionRestriction
+#[derive(Deserialize, Debug)]
+pub struct RegionRestriction {
+    pub allowed: Option<Vec<String>>,
+    pub blocked: Option<Vec<String>>,
+}
+
+impl RegionRestriction {
+    /// A video is blocked in a region if an allow list exists without the region,
+    /// or a block list exists with the region.
+    pub fn is_blocked_in(&self, region: &str) -> bool {
+        if let Some(allowed) = &self.allowed {
+            return !allowed.iter().any(|r| r.eq_ignore_ascii_case(region));
+        }
+
+        if let Some(blocked) = &self.blocked {
+            return blocked.iter().any(|r| r.eq_ignore_ascii_case(region));
+        }
+
+        false
+    }
+}
+
+/// Fetches the YouTube Data API video resource, `None` if the video doesn't exist (or is private).
+pub async fn fetch_video_details(
+    api_key: &str,
+    video_id: &str,
+) -> Result<Option<VideoDetails>, ApiError> {
+    let client = &HTTP_CLIENT;
+
+    let url = Url::parse_with_params(
+        "https://www.googleapis.com/youtube/v3/videos",
+        &[
+            ("part", "snippet,contentDetails"),
+            ("id", video_id),
+            ("key", api_key),
+        ],
+    )
+    .map_err(|e| ApiError::InternalError(format!("Invalid YouTube API URL: {}", e)))?;
+
+    let video_list = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<VideoListResponse>()
+        .await?;
+
+    Ok(video_list.items.into_iter().next())
+}
+
+#[derive(Deserialize, Debug)]
+struct VideoSnippetListResponse {
+    items: Vec<VideoSnippet>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VideoSnippet {
+    snippet: Snippet,
+}
+
+#[derive(Deserialize, Debug)]
+struct Snippet {
+    description: String,
+}
+
+/// The video's description, the push notifications from the hub don't include it.
+/// `None` if the video doesn't exist (or is private).
+pub async fn fetch_video_description(
+    api_key: &str,
+    video_id: &str,
+) -> Result<Option<String>, ApiError> {
+    let url = Url::parse_with_params(
+        "https://www.googleapis.com/youtube/v3/videos",
+        &[("part", "snippet"), ("id", video_id), ("key", api_key)],
+    )
+    .map_err(|e| ApiError::InternalError(format!("Invalid YouTube API URL: {}", e)))?;
+
+    let video_list = HTTP_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<VideoSnippetListResponse>()
+        .await?;
+
+    Ok(video_list
+        .items
+        .into_iter()
+        .next()
+        .map(|video| video.snippet.description))
+}
+
+/// Fetches the video details once for the enrichment and the checks below, `None` when no
+/// YouTube API key is configured.
+pub async fn fetch_video_details_for_entry(
+    state: &Arc<AppState>,
+    video_id: &str,
+) -> Result<Option<VideoDetails>, ApiError> {
+    let Some(api_key) = &state.youtube_api_key else {
+        return Ok(None);
+    };
+
+    fetch_video_details(api_key, video_id).await
+}
+
+/// Replaces the feed's title and channel name with the canonical ones and adds the largest thumbnail,
+/// which the title templates and thumbnail posts then use. The entry is unchanged without details.
+pub fn enrich_entry(simple_entry: &SimpleEntry, details: Option<&VideoDetails>) -> SimpleEntry {
+    let mut enriched_entry = simple_entry.clone();
+
+    let Some(metadata) = details.and_then(|details| details.snippet.as_ref()) else {
+        return enriched_entry;
+    };
+
+    if !metadata.title.trim().is_empty() {
+        enriched_entry.title = metadata.title.clone();
+    }
+
+    if !metadata.channel_title.trim().is_empty() {
+        enriched_entry.author.name = metadata.channel_title.clone();
+    }
+
+    enriched_entry.thumbnail_url = metadata.thumbnails.largest_url().cloned();
+
+    enriched_entry
+}
+
+#[derive(Deserialize, Debug)]
+struct ChannelListResponse {
+    #[serde(default)]
+    items: Vec<ChannelResource>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChannelResource {
+    snippet: ChannelSnippet,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChannelSnippet {
+    title: String,
+}
+
+/// Fetches the channel's title from the YouTube Data API, `None` if the channel doesn't exist.
+pub async fn fetch_channel_title(
+    api_key: &str,
+    channel_id: &str,
+) -> Result<Option<String>, ApiError> {
+    let url = Url::parse_with_params(
+        "https://www.googleapis.com/youtube/v3/channels",
+        &[("part", "snippet"), ("id", channel_id), ("key", api_key)],
+    )
+    .map_err(|e| ApiError::InternalError(format!("Invalid YouTube API URL: {}", e)))?;
+
+    let channel_list = HTTP_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ChannelListResponse>()
+        .await?;
+
+    Ok(channel_list
+        .items
+        .into_iter()
+        .next()
+        .map(|channel| channel.snippet.title))
+}
+
+#[derive(Deserialize, Debug)]
+struct ChannelUploadsListResponse {
+    #[serde(default)]
+    items: Vec<ChannelUploadsResource>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChannelUploadsResource {
+    content_details: ChannelContentDetails,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChannelContentDetails {
+    related_playlists: RelatedPlaylists,
+}
+
+#[derive(Deserialize, Debug)]
+struct RelatedPlaylists {
+    uploads: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemListResponse {
+    #[serde(default)]
+    items: Vec<PlaylistItem>,
+    next_page_token: Option<String>,
+}
+
+/// Source: https://developers.google.com/youtube/v3/docs/playlistItems
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItem {
+    snippet: PlaylistItemSnippet,
+    content_details: PlaylistItemContentDetails,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemSnippet {
+    title: String,
+    channel_title: String,
+    #[serde(default)]
+    thumbnails: Thumbnails,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemContentDetails {
+    video_id: String,
+    /// Missing for private and deleted videos.
+    video_published_at: Option<DateTime<Utc>>,
+}
+
+impl PlaylistItem {
+    /// The entry the channel's feed would have for the video, `None` for private and deleted videos.
+    fn to_simple_entry(&self, channel_id: &str) -> Option<SimpleEntry> {
+        let published = self.content_details.video_published_at?;
+        let video_id = &self.content_details.video_id;
+
+        Some(SimpleEntry {
+            id: format!("yt:video:{}", video_id),
+            yt_video_id: video_id.clone(),
+            yt_channel_id: channel_id.to_string(),
+            title: self.snippet.title.clone(),
+            link: Link {
+                rel: "alternate".to_string(),
+                href: format!("https://www.youtube.com/watch?v={}", video_id),
+                hreflang: None,
+            },
+            author: Author {
+                name: self.snippet.channel_title.clone(),
+                uri: format!("https://www.youtube.com/channel/{}", channel_id),
+            },
+            published,
+            updated: published,
+            thumbnail_url: self.snippet.thumbnails.largest_url().cloned(),
+        })
+    }
+}
+
+/// Fetches the channel's uploads published within the range from its uploads playlist, which unlike
+/// the feed goes back to the channel's first video. The playlist is newest first, so paging stops
+/// at the first video published before the range.
+pub async fn fetch_channel_uploads_in_range(
+    api_key: &str,
+    channel_id: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Vec<SimpleEntry>, ApiError> {
+    let url = Url::parse_with_params(
+        "https://www.googleapis.com/youtube/v3/channels",
+        &[
+            ("part", "contentDetails"),
+            ("id", channel_id),
+            ("key", api_key),
+        ],
+    )
+    .map_err(|e| ApiError::InternalError(format!("Invalid YouTube API URL: {}", e)))?;
+
+    let channel_list = HTTP_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ChannelUploadsListResponse>()
+        .await?;
+
+    let Some(channel) = channel_list.items.into_iter().next() else {
+        return Err(ApiError::NotFound(format!(
+            "No YouTube channel found for the id: {}",
+            channel_id
+        )));
+    };
+
+    let uploads_playlist_id = channel.content_details.related_playlists.uploads;
+    let mut entries = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let mut params = vec![
+            ("part", "snippet,contentDetails"),
+            ("playlistId", uploads_playlist_id.as_str()),
+            ("maxResults", "50"),
+            ("key", api_key),
+        ];
+        if let Some(page_token) = &page_token {
+            params.push(("pageToken", page_token.as_str()));
+        }
+
+        let url = Url::parse_with_params(
+            "https://www.googleapis.com/youtube/v3/playlistItems",
+            &params,
+        )
+        .map_err(|e| ApiError::InternalError(format!("Invalid YouTube API URL: {}", e)))?;
+
+        let playlist_items = HTTP_CLIENT
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PlaylistItemListResponse>()
+            .await?;
+
+        let mut reached_start = false;
+        for entry in playlist_items
+            .items
+            .iter()
+            .filter_map(|item| item.to_simple_entry(channel_id))
+        {
+            if since.is_some_and(|since| entry.published < since) {
+                reached_start = true;
+                continue;
+            }
+
+            if until.is_none_or(|until| entry.published <= until) {
+                entries.push(entry);
+            }
+        }
+
+        page_token = playlist_items.next_page_token;
+        if reached_start || page_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The video's duration in seconds, from the cache or else from the fetched details, which is then cached.
+pub async fn video_duration_seconds(
+    state: &Arc<AppState>,
+    video_id: &str,
+    details: Option<&VideoDetails>,
+) -> Result<Option<i64>, ApiError> {
+    if let Some(duration_seconds) = fetch_video_duration(&state.db_pool, video_id).await? {
+        return Ok(Some(duration_seconds));
+    }
+
+    let Some(duration_seconds) = details
+        .and_then(|details| details.content_details.duration.as_deref())
+        .and_then(parse_duration_seconds)
+    else {
+        return Ok(None);
+    };
+
+    save_video_duration(
+        &state.db_pool,
+        video_id,
+        &duration_seconds,
+        &Utc::now().timestamp(),
+    )
+    .await?;
+
+    Ok(Some(duration_seconds))
+}
+
+/// Checks the video against the configured target region, returns true if the video should be skipped.
+/// Does nothing when the target region isn't configured.
+pub async fn skip_region_blocked_video(
+    state: &Arc<AppState>,
+    video_id: &String,
+    details: &VideoDetails,
+) -> Result<bool, ApiError> {
+    let Some(region) = &state.target_region else {
+        return Ok(false);
+    };
+
+    let blocked = details
+        .content_details
+        .region_restriction
+        .as_ref()
+        .is_some_and(|restriction| restriction.is_blocked_in(region));
+
+    let skipped = blocked && matches!(state.region_block_action, RegionBlockAction::Skip);
+
+    save_region_check(
+        &state.db_pool,
+        video_id,
+        region,
+        &blocked,
+        &skipped,
+        &Utc::now().timestamp(),
+    )
+    .await?;
+
+    if blocked {
+        println!(
+            "The video https://www.youtube.com/watch?v={} is region-blocked in {}, {}.",
+            video_id,
+            region,
+            if skipped {
+                "skipping the submission"
+            } else {
+                "submitting it anyway"
+            }
+        );
+    }
+
+    Ok(skipped)
+}
+
+/// Videos shorter than this are treated as Shorts, even when the feed links to the `/watch` page.
+const SHORTS_MAX_DURATION_SECS: i64 = 60;
+
+/// Returns true if the video is a Short the subscription doesn't post, or shorter than the subscription's minimum duration.
+pub fn skip_short_video(
+    subscription: &Subscription,
+    video_id: &str,
+    duration_seconds: Option<i64>,
+) -> bool {
+    let Some(duration_seconds) = duration_seconds else {
+        return false;
+    };
+
+    if !subscription.post_shorts && duration_seconds < SHORTS_MAX_DURATION_SECS {
+        println!(
+            "The video https://www.youtube.com/watch?v={} is {} seconds long, a Short, and the subscription doesn't post Shorts, skipping the submission.",
+            video_id, duration_seconds
+        );
+        return true;
+    }
+
+    let Some(min_duration_seconds) = subscription.min_duration_seconds else {
+        return false;
+    };
+
+    if duration_seconds < min_duration_seconds {
+        println!(
+            "The video https://www.youtube.com/watch?v={} is {} seconds long, shorter than the subscription's minimum of {} seconds, skipping the submission.",
+            video_id, duration_seconds, min_duration_seconds
+        );
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{infrastructure::Settings, server::shared::SubscriptionStatus};
+
+    fn subscription(post_shorts: bool, min_duration_seconds: Option<i64>) -> Subscription {
+        Subscription {
+            id: "subscription".to_string(),
+            channel_id: "UCchannel".to_string(),
+            channel_name: "Channel".to_string(),
+            hmac_secret: "secret".to_string(),
+            expires: None,
+            post_shorts,
+            min_duration_seconds,
+            title_filters: None,
+            posting_cutoff: None,
+            status: SubscriptionStatus::Active,
+            crossposting: None,
+            min_post_interval_seconds: None,
+            description_comment: None,
+        }
+    }
+
+    #[test]
+    fn video_shorter_than_the_minimum_duration_is_skipped() {
+        let subscription = subscription(true, Some(60));
+
+        assert!(skip_short_video(&subscription, "video1", Some(45)));
+        assert!(!skip_short_video(&subscription, "video1", Some(60)));
+        assert!(!skip_short_video(&subscription, "video1", Some(253)));
+        assert!(!skip_short_video(&subscription, "video1", None));
+    }
+
+    #[test]
+    fn shorts_are_skipped_unless_the_subscription_posts_them() {
+        assert!(skip_short_video(
+            &subscription(false, None),
+            "video1",
+            Some(45)
+        ));
+        assert!(!skip_short_video(
+            &subscription(true, None),
+            "video1",
+            Some(45)
+        ));
+        assert!(!skip_short_video(
+            &subscription(false, None),
+            "video1",
+            Some(253)
+        ));
+    }
+
+    #[test]
+    fn iso_8601_durations_are_parsed_to_seconds() {
+        assert_eq!(parse_duration_seconds("PT45S"), Some(45));
+        assert_eq!(parse_duration_seconds("PT4M13S"), Some(253));
+        assert_eq!(parse_duration_seconds("PT1H2M"), Some(3720));
+        assert_eq!(parse_duration_seconds("P1DT1S"), Some(86401));
+        assert_eq!(parse_duration_seconds("P0D"), Some(0));
+        assert_eq!(parse_duration_seconds("PT4X"), None);
+        assert_eq!(parse_duration_seconds("PT45"), None);
+        assert_eq!(parse_duration_seconds("4M13S"), None);
+    }
+
+    fn region_blocked_details() -> VideoDetails {
+        serde_json::from_value(json!({
+            "contentDetails": {
+                "duration": "PT4M13S",
+                "regionRestriction": { "blocked": ["DE", "FR"] }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn region_blocked_video_is_skipped_or_submitted_per_the_action() {
+        for (region, action, blocked, skipped) in [
+            ("DE", RegionBlockAction::Skip, true, true),
+            ("DE", RegionBlockAction::Warn, true, false),
+            ("US", RegionBlockAction::Skip, false, false),
+        ] {
+            let mut settings = Settings::for_tests();
+            settings.target_region = Some(region.to_string());
+            settings.region_block_action = action;
+            let (state, _receiver) = AppState::for_tests(settings).await;
+
+            let result =
+                skip_region_blocked_video(&state, &"video1".to_string(), &region_blocked_details())
+                    .await
+                    .unwrap();
+
+            let stored: (bool, bool) = sqlx::query_as(
+                "SELECT blocked, skipped FROM region_checks WHERE video_id = 'video1' AND region = ?",
+            )
+            .bind(region)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+            assert_eq!(result, skipped, "{}", region);
+            assert_eq!(stored, (blocked, skipped), "{}", region);
+        }
+    }
+
+    #[test]
+    fn allow_list_blocks_every_other_region() {
+        let restriction = RegionRestriction {
+            allowed: Some(vec!["US".to_string(), "CA".to_string()]),
+            blocked: None,
+        };
+
+        assert!(!restriction.is_blocked_in("us"));
+        assert!(restriction.is_blocked_in("DE"));
+    }
+
+    #[test]
+    fn playlist_items_become_feed_entries_without_private_videos() {
+        let playlist_items: PlaylistItemListResponse = serde_json::from_str(
+            r#"{
+                "nextPageToken": "page2",
+                "items": [
+                    {
+                        "snippet": {
+                            "title": "A video",
+                            "channelTitle": "Channel",
+                            "thumbnails": {
+                                "high": { "url": "https://i.ytimg.com/vi/video1/hqdefault.jpg" },
+                                "default": { "url": "https://i.ytimg.com/vi/video1/default.jpg" }
+                            }
+                        },
+                        "contentDetails": {
+                            "videoId": "video1",
+                            "videoPublishedAt": "2026-01-02T03:04:05Z"
+                        }
+                    },
+                    {
+                        "snippet": { "title": "Private video", "channelTitle": "Channel" },
+                        "contentDetails": { "videoId": "video2" }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let entries: Vec<SimpleEntry> = playlist_items
+            .items
+            .iter()
+            .filter_map(|item| item.to_simple_entry("UCchannel"))
+            .collect();
+
+        assert_eq!(playlist_items.next_page_token.as_deref(), Some("page2"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, "yt:video:video1");
+        assert_eq!(entries[0].yt_channel_id, "UCchannel");
+        assert_eq!(
+            entries[0].link.href,
+            "https://www.youtube.com/watch?v=video1"
+        );
+        assert_eq!(entries[0].author.name, "Channel");
+        assert_eq!(
+            entries[0].thumbnail_url.as_deref(),
+            Some("https://i.ytimg.com/vi/video1/hqdefault.jpg")
+        );
+        assert_eq!(
+            entries[0].published.to_rfc3339(),
+            "2026-01-02T03:04:05+00:00"
+        );
+    }
+}